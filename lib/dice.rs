@@ -37,82 +37,339 @@ use bevy_egui::egui::*;
 use num_complex::Complex64 as c64;
 use num_traits::identities::{One, Zero};
 use rand::Rng;
+use tracing::error;
 
-type Weights = [c64; 6];
+pub type Weights = Vec<c64>;
+
+/// Number of faces on a standard die
+pub const DEFAULT_FACES: usize = 6;
+/// Fraction of the remaining probability that moves onto the rolled face
+/// when a roll collapses the die
+pub const COLLAPSE_STRENGTH: f64 = 0.5;
+
+/// Fewest faces a die can be configured to have
+pub const MIN_FACES: usize = 4;
+/// Most faces a die can be configured to have
+pub const MAX_FACES: usize = 20;
+
+pub fn format_amplitude(amplitude: c64) -> String {
+    let sign = if amplitude.im.is_sign_negative() {
+        '-'
+    } else {
+        '+'
+    };
+    format!("{:.4} {} {:.4}i", amplitude.re, sign, amplitude.im.abs())
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct WeightedDie {
+    #[cfg_attr(feature = "serde", serde(with = "amplitudes"))]
     weights: Weights,
+    probability_floor: f64,
 }
 
-type Matrix = [[c64; 6]; 6];
+type Matrix = Vec<Vec<c64>>;
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct WeightTransform {
+    #[cfg_attr(feature = "serde", serde(with = "matrix"))]
     matrix: Matrix,
 }
 
+// Complex numbers don't implement the serde traits, so amplitudes are stored
+// as (re, im) pairs
+#[cfg(feature = "serde")]
+mod amplitudes {
+    use num_complex::Complex64 as c64;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(amplitudes: &[c64], serializer: S) -> Result<S::Ok, S::Error> {
+        let pairs: Vec<(f64, f64)> = amplitudes.iter().map(|a| (a.re, a.im)).collect();
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<c64>, D::Error> {
+        let pairs = Vec::<(f64, f64)>::deserialize(deserializer)?;
+        Ok(pairs.into_iter().map(|(re, im)| c64::new(re, im)).collect())
+    }
+}
+
+#[cfg(feature = "serde")]
+mod matrix {
+    use num_complex::Complex64 as c64;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(matrix: &[Vec<c64>], serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<Vec<(f64, f64)>> = matrix
+            .iter()
+            .map(|row| row.iter().map(|a| (a.re, a.im)).collect())
+            .collect();
+        rows.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<c64>>, D::Error> {
+        let rows = Vec::<Vec<(f64, f64)>>::deserialize(deserializer)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|(re, im)| c64::new(re, im)).collect())
+            .collect())
+    }
+}
+
 impl Clone for WeightedDie {
     fn clone(&self) -> Self {
         WeightedDie {
-            weights: self.weights,
+            weights: self.weights.clone(),
+            probability_floor: self.probability_floor,
         }
     }
 }
 
 impl WeightedDie {
     pub fn fair_die() -> Self {
+        WeightedDie::fair_die_with_faces(DEFAULT_FACES)
+    }
+
+    pub fn fair_die_with_faces(faces: usize) -> Self {
+        debug_assert!(faces > 0);
         WeightedDie {
-            weights: [c64::from((1f64 / 6.).sqrt()); 6],
+            weights: vec![c64::from((1. / faces as f64).sqrt()); faces],
+            probability_floor: 0.,
         }
     }
 
+    pub fn faces(&self) -> usize {
+        self.weights.len()
+    }
+
     pub fn with_weights(weights: Weights) -> Self {
-        if cfg!(debug_assertions) {
-            let mut total = 0.;
-            for w in weights {
-                total += w.norm_sqr();
-            }
-            debug_assert!((total - 1.).abs() < 1e-12);
+        debug_assert!(WeightedDie::is_normalized(&weights));
+        WeightedDie {
+            weights,
+            probability_floor: 0.,
+        }
+    }
+
+    fn is_normalized(weights: &Weights) -> bool {
+        let total: f64 = weights.iter().map(|w| w.norm_sqr()).sum();
+        (total - 1.).abs() < 1e-12
+    }
+
+    /// Encodes the die's weights as a string that can be shared with other
+    /// players
+    pub fn export_string(&self) -> String {
+        let mut bytes = Vec::with_capacity(16 * self.faces());
+        for weight in &self.weights {
+            bytes.extend(weight.re.to_le_bytes());
+            bytes.extend(weight.im.to_le_bytes());
         }
-        WeightedDie { weights }
+        base64::encode(bytes)
+    }
+
+    pub fn import_string(encoded: &str) -> Result<Self, String> {
+        let bytes = base64::decode(encoded.trim()).map_err(|_| "Not a valid die string")?;
+        let faces = bytes.len() / 16;
+        if bytes.len() % 16 != 0 || !(MIN_FACES..=MAX_FACES).contains(&faces) {
+            return Err("Not a valid die string".to_string());
+        }
+        let weights = bytes
+            .chunks_exact(16)
+            .map(|chunk| {
+                let re = f64::from_le_bytes(chunk[..8].try_into().unwrap());
+                let im = f64::from_le_bytes(chunk[8..].try_into().unwrap());
+                c64::new(re, im)
+            })
+            .collect::<Weights>();
+        if !WeightedDie::is_normalized(&weights) {
+            return Err("The die's probabilities don't add up to 1".to_string());
+        }
+        Ok(WeightedDie::with_weights(weights))
     }
 
     pub fn weights(&self) -> Weights {
-        self.weights
+        self.weights.clone()
+    }
+
+    /// Replaces the die's weights, normalizing them first. Weights whose
+    /// total probability is zero or not finite are rejected and the die is
+    /// left unchanged.
+    pub fn set_weights(&mut self, weights: Weights) -> Result<(), String> {
+        let total: f64 = weights.iter().map(|w| w.norm_sqr()).sum();
+        if !total.is_finite() || total <= 0. {
+            return Err("The die's probabilities can't be normalized".to_string());
+        }
+        let norm = total.sqrt();
+        self.weights = weights.into_iter().map(|w| w / norm).collect();
+        self.apply_probability_floor();
+        Ok(())
+    }
+
+    /// Sets the minimum probability of rolling each face. A floor of zero
+    /// disables the rule.
+    pub fn set_probability_floor(&mut self, floor: f64) {
+        self.probability_floor = floor.clamp(0., 1. / self.faces() as f64);
+        self.apply_probability_floor();
+    }
+
+    // Raises any face below the floor back up to it, taking the difference
+    // proportionally from the other faces. Faces are fixed at the floor one
+    // round at a time since scaling down the rest can push more below it.
+    fn apply_probability_floor(&mut self) {
+        let floor = self.probability_floor;
+        if floor <= 0. {
+            return;
+        }
+        let mut fixed = vec![false; self.faces()];
+        loop {
+            let mut raised = false;
+            for (weight, fixed) in self.weights.iter_mut().zip(fixed.iter_mut()) {
+                if !*fixed && weight.norm_sqr() < floor {
+                    *weight = c64::from_polar(floor.sqrt(), weight.arg());
+                    *fixed = true;
+                    raised = true;
+                }
+            }
+            if !raised {
+                break;
+            }
+            let fixed_total = fixed.iter().filter(|f| **f).count() as f64 * floor;
+            let free_total: f64 = self
+                .weights
+                .iter()
+                .zip(fixed.iter())
+                .filter(|(_, fixed)| !**fixed)
+                .map(|(w, _)| w.norm_sqr())
+                .sum();
+            if free_total <= 0. {
+                break;
+            }
+            let scale = ((1. - fixed_total) / free_total).sqrt();
+            for (weight, fixed) in self.weights.iter_mut().zip(fixed.iter()) {
+                if !*fixed {
+                    *weight *= scale;
+                }
+            }
+        }
+    }
+
+    /// The chance of rolling each face, starting with 1
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.weights.iter().map(|w| w.norm_sqr()).collect()
     }
 
     pub fn expected_value(&self) -> f64 {
-        self.weights.iter().enumerate().fold(0., |mut acc, (i, x)| {
-            acc += i as f64 * x.norm_sqr();
-            acc
-        }) / 6.
+        self.weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (i + 1) as f64 * w.norm_sqr())
+            .sum()
+    }
+
+    pub fn variance(&self) -> f64 {
+        let mean = self.expected_value();
+        self.weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| ((i + 1) as f64 - mean).powi(2) * w.norm_sqr())
+            .sum()
+    }
+
+    /// The lowest face with a non-negligible chance of being rolled
+    pub fn lowest_possible_face(&self) -> u32 {
+        self.weights
+            .iter()
+            .position(|w| w.norm_sqr() > 1e-9)
+            .map_or(self.faces() as u32, |face| face as u32 + 1)
+    }
+
+    /// Estimates how many turns it takes to travel the given distance based
+    /// on the expected roll, ignoring items and junctions
+    pub fn estimated_turns(&self, distance: usize) -> u32 {
+        (distance as f64 / self.expected_value()).ceil() as u32
     }
 
     pub fn roll(&self) -> u32 {
-        let mut roll: f64 = rand::thread_rng().gen_range(0.0..1.0);
+        self.roll_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls the die using the given random number generator. A seeded
+    /// generator gives the same sequence of rolls every time.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
+        let mut roll: f64 = rng.gen_range(0.0..1.0);
         for (value, weight) in self.weights.iter().enumerate() {
             if roll < weight.norm_sqr() {
                 return value as u32 + 1;
             }
             roll -= weight.norm_sqr();
         }
-        panic!("Failed to roll a number");
+        // Rounding errors can leave a tiny remainder after the last face
+        error!(roll, "Failed to roll a number");
+        self.weights
+            .iter()
+            .rposition(|w| w.norm_sqr() > 0.)
+            .map_or(self.faces() as u32, |face| face as u32 + 1)
+    }
+
+    /// Rolls the die like a measurement: the rolled face's probability moves
+    /// toward 1 by `COLLAPSE_STRENGTH` and the other faces are scaled down to
+    /// keep the die normalized. Phases are left unchanged.
+    pub fn roll_and_collapse(&mut self) -> u32 {
+        self.roll_and_collapse_with(&mut rand::thread_rng())
+    }
+
+    pub fn roll_and_collapse_with<R: Rng + ?Sized>(&mut self, rng: &mut R) -> u32 {
+        let rolled = self.roll_with(rng);
+        let k = rolled as usize - 1;
+        let before = self.weights[k].norm_sqr();
+        let after = before + (1. - before) * COLLAPSE_STRENGTH;
+        let rest = 1. - before;
+        if rest > 0. {
+            let scale = ((1. - after) / rest).sqrt();
+            for (i, weight) in self.weights.iter_mut().enumerate() {
+                if i == k {
+                    *weight = c64::from_polar(after.sqrt(), weight.arg());
+                } else {
+                    *weight *= scale;
+                }
+            }
+        }
+        self.apply_probability_floor();
+        rolled
     }
 
     pub fn apply_transformation(&mut self, transform: &WeightTransform) {
-        self.weights = transform.apply(self.weights);
+        self.weights = transform.apply(&self.weights);
+        self.apply_probability_floor();
+    }
+
+    /// Determines which face's bar, as drawn by `visualize_weights`, lies at
+    /// the given horizontal position in the unit square
+    pub fn face_at(x: f32, faces: usize) -> Option<usize> {
+        let spacing = (faces + 1) as f32;
+        let face = (x * spacing).round();
+        if (1. ..=faces as f32).contains(&face) && (x - face / spacing).abs() <= 0.25 / spacing {
+            Some(face as usize)
+        } else {
+            None
+        }
     }
 
     pub fn visualize_weights(&self, painter: &Painter, to_screen: RectTransform, color: Color32) {
+        let spacing = (self.faces() + 1) as f32;
         for (i, weight) in self.weights.iter().enumerate() {
             let face = i + 1;
             painter.rect_filled(
                 Rect::from([
                     to_screen
                         * Pos2 {
-                            x: face as f32 / 7. - 1. / 28.,
+                            x: (face as f32 - 0.25) / spacing,
                             y: 0.9 - weight.norm_sqr() as f32,
                         },
                     to_screen
                         * Pos2 {
-                            x: face as f32 / 7. + 1. / 28.,
+                            x: (face as f32 + 0.25) / spacing,
                             y: 0.9,
                         },
                 ]),
@@ -124,21 +381,28 @@ impl WeightedDie {
 }
 
 impl WeightTransform {
-    pub fn identity() -> Self {
-        let mut matrix = [[c64::zero(); 6]; 6];
+    pub fn identity(faces: usize) -> Self {
+        let mut matrix = vec![vec![c64::zero(); faces]; faces];
         #[allow(clippy::needless_range_loop)]
-        for i in 0..6 {
+        for i in 0..faces {
             matrix[i][i] = c64::one();
         }
         WeightTransform { matrix }
     }
 
+    /// The number of faces on the dice this transform applies to
+    pub fn faces(&self) -> usize {
+        self.matrix.len()
+    }
+
     #[allow(clippy::needless_range_loop)]
     pub fn matrix_product(a: &Matrix, b: &Matrix) -> Matrix {
-        let mut combined = [[c64::zero(); 6]; 6];
-        for i in 0..6 {
-            for j in 0..6 {
-                for k in 0..6 {
+        let n = a.len();
+        debug_assert_eq!(n, b.len());
+        let mut combined = vec![vec![c64::zero(); n]; n];
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..n {
                     combined[i][j] += a[i][k] * b[k][j];
                 }
             }
@@ -153,18 +417,34 @@ impl WeightTransform {
         WeightTransform { matrix }
     }
 
-    #[cfg(debug_assertions)]
     #[allow(clippy::needless_range_loop)]
-    fn is_unitary(matrix: &Matrix) -> bool {
-        let mut cc = [[c64::zero(); 6]; 6];
-        for i in 0..6 {
-            for j in 0..6 {
+    fn conjugate_transpose(matrix: &Matrix) -> Matrix {
+        let n = matrix.len();
+        let mut cc = vec![vec![c64::zero(); n]; n];
+        for i in 0..n {
+            for j in 0..n {
                 cc[i][j] = matrix[j][i].conj();
             }
         }
+        cc
+    }
+
+    /// The transform that undoes this one. Since transforms are unitary, this
+    /// is the conjugate transpose.
+    pub fn inverse(&self) -> Self {
+        WeightTransform {
+            matrix: WeightTransform::conjugate_transpose(&self.matrix),
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[allow(clippy::needless_range_loop)]
+    fn is_unitary(matrix: &Matrix) -> bool {
+        let n = matrix.len();
+        let cc = WeightTransform::conjugate_transpose(matrix);
         let product = WeightTransform::matrix_product(matrix, &cc);
-        for i in 0..6 {
-            for j in 0..6 {
+        for i in 0..n {
+            for j in 0..n {
                 let term = product[i][j];
                 if i == j {
                     if (term - c64::one()).norm() > 1e-12 {
@@ -184,12 +464,19 @@ impl WeightTransform {
         WeightTransform { matrix }
     }
 
-    pub fn superimpose_pair(v1: u32, v2: u32, transfer: f64) -> Self {
+    pub fn superimpose_pair(faces: usize, v1: u32, v2: u32, transfer: f64) -> Self {
         debug_assert!(transfer <= 1.);
         debug_assert!(transfer >= 0.);
+        assert!(
+            (1..=faces as u32).contains(&v1) && (1..=faces as u32).contains(&v2),
+            "Faces {} and {} are not on a die with {} faces",
+            v1,
+            v2,
+            faces
+        );
 
         let (v1, v2) = (v1 as usize - 1, v2 as usize - 1);
-        let mut transform = WeightTransform::identity();
+        let mut transform = WeightTransform::identity(faces);
         let a = c64::from((transfer / 2.).sqrt());
         let b = c64::from(((2. - transfer) / 2.).sqrt());
 
@@ -204,11 +491,51 @@ impl WeightTransform {
         transform
     }
 
+    /// Creates a transform that exchanges the amplitudes on two faces. Swapping
+    /// the same faces again undoes it.
+    pub fn swap_faces(faces: usize, a: u32, b: u32) -> Self {
+        assert!(
+            (1..=faces as u32).contains(&a) && (1..=faces as u32).contains(&b),
+            "Faces {} and {} are not on a die with {} faces",
+            a,
+            b,
+            faces
+        );
+        let (a, b) = (a as usize - 1, b as usize - 1);
+        let mut transform = WeightTransform::identity(faces);
+        transform.matrix.swap(a, b);
+        transform
+    }
+
+    /// Creates a transform that moves all of the weight on one face of the
+    /// given die onto another face, leaving the other faces unchanged
+    pub fn drain(die: &WeightedDie, from: u32, to: u32) -> Self {
+        let (from, to) = (from as usize - 1, to as usize - 1);
+        let mut transform = WeightTransform::identity(die.faces());
+        let (w_from, w_to) = (die.weights[from], die.weights[to]);
+        let norm = (w_from.norm_sqr() + w_to.norm_sqr()).sqrt();
+        if norm == 0. {
+            return transform;
+        }
+
+        transform.matrix[to][to] = w_to.conj() / norm;
+        transform.matrix[to][from] = w_from.conj() / norm;
+        transform.matrix[from][to] = -w_from / norm;
+        transform.matrix[from][from] = w_to / norm;
+
+        #[cfg(debug_assertions)]
+        debug_assert!(WeightTransform::is_unitary(&transform.matrix));
+
+        transform
+    }
+
     #[allow(clippy::needless_range_loop)]
-    pub fn apply(&self, rhs: Weights) -> Weights {
-        let mut res = [c64::zero(); 6];
-        for i in 0..6 {
-            for j in 0..6 {
+    pub fn apply(&self, rhs: &[c64]) -> Weights {
+        let n = self.faces();
+        debug_assert_eq!(n, rhs.len());
+        let mut res = vec![c64::zero(); n];
+        for i in 0..n {
+            for j in 0..n {
                 res[i] += self.matrix[i][j] * rhs[j];
             }
         }
@@ -230,13 +557,141 @@ impl WeightTransform {
     }
 }
 
+/// How much more likely the second die of an entangled pair is to match the
+/// first die's roll than it would be on its own
+pub const ENTANGLEMENT_BIAS: f64 = 3.;
+
+/// Two players' dice sharing a joint state. Rolling the first die conditions
+/// the distribution of the second.
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct EntangledPair {
+    first: usize,
+    second: usize,
+    faces: usize,
+    // Row i holds the amplitudes of the second die when the first rolls i + 1
+    #[cfg_attr(feature = "serde", serde(with = "amplitudes"))]
+    amplitudes: Vec<c64>,
+    measured: Option<(u32, u32)>,
+}
+
+// Picks an index with probability given by the squared amplitudes, which need
+// not be normalized
+fn sample<R: Rng + ?Sized>(amplitudes: &[c64], rng: &mut R) -> usize {
+    let total: f64 = amplitudes.iter().map(|w| w.norm_sqr()).sum();
+    let mut roll: f64 = rng.gen_range(0.0..total);
+    for (i, weight) in amplitudes.iter().enumerate() {
+        if roll < weight.norm_sqr() {
+            return i;
+        }
+        roll -= weight.norm_sqr();
+    }
+    amplitudes
+        .iter()
+        .rposition(|w| w.norm_sqr() > 0.)
+        .unwrap_or(amplitudes.len() - 1)
+}
+
+impl EntangledPair {
+    /// Entangles the dice of the given players. The first die keeps its
+    /// distribution; each row of the second die's amplitudes favors the face
+    /// matching the first die's roll by `ENTANGLEMENT_BIAS`.
+    pub fn new(first: usize, second: usize, die1: &WeightedDie, die2: &WeightedDie) -> Self {
+        debug_assert_eq!(die1.faces(), die2.faces());
+        let faces = die1.faces();
+        let mut amplitudes = Vec::with_capacity(faces * faces);
+        for (i, w1) in die1.weights.iter().enumerate() {
+            let row: Weights = die2
+                .weights
+                .iter()
+                .enumerate()
+                .map(|(j, w2)| {
+                    if i == j {
+                        *w2 * ENTANGLEMENT_BIAS.sqrt()
+                    } else {
+                        *w2
+                    }
+                })
+                .collect();
+            let norm = row.iter().map(|w| w.norm_sqr()).sum::<f64>().sqrt();
+            amplitudes.extend(row.into_iter().map(|w| *w1 * w / norm));
+        }
+        EntangledPair {
+            first,
+            second,
+            faces,
+            amplitudes,
+            measured: None,
+        }
+    }
+
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    pub fn second(&self) -> usize {
+        self.second
+    }
+
+    /// The rolls from the measurement, if the pair has been measured
+    pub fn measured(&self) -> Option<(u32, u32)> {
+        self.measured
+    }
+
+    fn row(&self, face: u32) -> &[c64] {
+        let start = (face as usize - 1) * self.faces;
+        &self.amplitudes[start..start + self.faces]
+    }
+
+    /// Rolls the first die and collapses the joint state onto that result.
+    /// Returns the first die's roll and a roll of the second die drawn from
+    /// the conditioned distribution.
+    pub fn measure_first(&mut self) -> (u32, u32) {
+        self.measure_first_with(&mut rand::thread_rng())
+    }
+
+    pub fn measure_first_with<R: Rng + ?Sized>(&mut self, rng: &mut R) -> (u32, u32) {
+        if let Some(rolls) = self.measured {
+            return rolls;
+        }
+        let rows: Weights = self
+            .amplitudes
+            .chunks_exact(self.faces)
+            .map(|row| c64::from(row.iter().map(|w| w.norm_sqr()).sum::<f64>().sqrt()))
+            .collect();
+        let first = sample(&rows, rng) as u32 + 1;
+        let second = sample(self.row(first), rng) as u32 + 1;
+        let norm = rows[first as usize - 1].re;
+        for (i, amplitude) in self.amplitudes.iter_mut().enumerate() {
+            if i / self.faces == first as usize - 1 {
+                *amplitude /= norm;
+            } else {
+                *amplitude = c64::zero();
+            }
+        }
+        self.measured = Some((first, second));
+        (first, second)
+    }
+
+    /// The second die's weights after measurement, conditioned on the first
+    /// die's roll
+    pub fn second_weights(&self) -> Option<Weights> {
+        self.measured.map(|(first, _)| self.row(first).to_vec())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::dice::{WeightTransform, WeightedDie};
+    use crate::dice::{
+        format_amplitude, EntangledPair, WeightTransform, WeightedDie, COLLAPSE_STRENGTH,
+        ENTANGLEMENT_BIAS, MAX_FACES, MIN_FACES,
+    };
     use num_complex::Complex64 as c64;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
-    fn generate_rolls(die: &WeightedDie, count: u32) -> [i32; 6] {
-        let mut results = [0; 6];
+    fn generate_rolls(die: &WeightedDie, count: u32) -> Vec<i32> {
+        let mut results = vec![0; die.faces()];
         for _ in 0..count {
             let roll = die.roll();
             results[roll as usize - 1] += 1;
@@ -253,7 +708,7 @@ mod tests {
 
     #[test]
     fn unfair_rolls() {
-        let die = WeightedDie::with_weights([
+        let die = WeightedDie::with_weights(vec![
             c64::from((1f64 / 21.).sqrt()),
             c64::from((2f64 / 21.).sqrt()),
             c64::from((3f64 / 21.).sqrt()),
@@ -262,25 +717,282 @@ mod tests {
             c64::from((6f64 / 21.).sqrt()),
         ]);
         let results = generate_rolls(&die, 1000);
-        dbg!(results.map(|x| x as f64 / results[0] as f64));
+        dbg!(results
+            .iter()
+            .map(|x| *x as f64 / results[0] as f64)
+            .collect::<Vec<_>>());
     }
 
     #[test]
     fn superposition() {
         let mut die = WeightedDie::fair_die();
         // Transfer all weight from 2 to 1
-        let transform = WeightTransform::superimpose_pair(1, 2, 1.);
+        let transform = WeightTransform::superimpose_pair(6, 1, 2, 1.);
         die.apply_transformation(&transform);
         dbg!(die.weights);
     }
 
     #[test]
     fn multiple_transformations() {
-        let m1 = WeightTransform::superimpose_pair(1, 3, 1.);
+        let m1 = WeightTransform::superimpose_pair(6, 1, 3, 1.);
         assert!(WeightTransform::is_unitary(&m1.matrix));
-        let m2 = WeightTransform::superimpose_pair(2, 4, 1.);
+        let m2 = WeightTransform::superimpose_pair(6, 2, 4, 1.);
         assert!(WeightTransform::is_unitary(&m2.matrix));
         let m3 = m1.combined_with(&m2);
         assert!(WeightTransform::is_unitary(&m3.matrix));
     }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        let fair = WeightedDie::fair_die();
+        for p in fair.probabilities() {
+            assert!((p - 1. / 6.).abs() < 1e-12);
+        }
+
+        let mut die = WeightedDie::fair_die_with_faces(8);
+        die.apply_transformation(&WeightTransform::superimpose_pair(8, 3, 7, 0.7));
+        let probabilities = die.probabilities();
+        assert_eq!(probabilities.len(), 8);
+        assert!((probabilities.iter().sum::<f64>() - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn swapping_faces_twice_changes_nothing() {
+        let swap = WeightTransform::swap_faces(6, 2, 5);
+        assert!(WeightTransform::is_unitary(&swap.matrix));
+
+        let mut die = WeightedDie::fair_die();
+        die.apply_transformation(&WeightTransform::superimpose_pair(6, 2, 3, 0.4));
+        let before = die.weights();
+        let swapped = swap.apply(&before);
+        assert_eq!(swapped[1], before[4]);
+        assert_eq!(swapped[4], before[1]);
+        assert_eq!(swapped[2], before[2]);
+        assert_eq!(swap.apply(&swapped), before);
+    }
+
+    #[test]
+    fn loaded_die_finishes_sooner() {
+        let fair = WeightedDie::fair_die();
+        assert!((fair.expected_value() - 3.5).abs() < 1e-12);
+        assert!((fair.variance() - 35. / 12.).abs() < 1e-12);
+        assert_eq!(fair.estimated_turns(35), 10);
+
+        let mut loaded = fair.clone();
+        loaded.apply_transformation(&WeightTransform::superimpose_pair(6, 6, 1, 1.));
+        loaded.apply_transformation(&WeightTransform::superimpose_pair(6, 5, 2, 1.));
+        assert!(loaded.estimated_turns(35) < fair.estimated_turns(35));
+        assert_eq!(loaded.estimated_turns(0), 0);
+    }
+
+    #[test]
+    fn amplitude_formatting() {
+        assert_eq!(format_amplitude(c64::new(0.5, 0.25)), "0.5000 + 0.2500i");
+        assert_eq!(format_amplitude(c64::new(-0.125, -1.)), "-0.1250 - 1.0000i");
+        assert_eq!(format_amplitude(c64::from(1.)), "1.0000 + 0.0000i");
+    }
+
+    #[test]
+    fn face_positions() {
+        assert_eq!(WeightedDie::face_at(1. / 7., 6), Some(1));
+        assert_eq!(WeightedDie::face_at(6. / 7. + 1. / 30., 6), Some(6));
+        assert_eq!(WeightedDie::face_at(1.5 / 7., 6), None);
+        assert_eq!(WeightedDie::face_at(0., 6), None);
+        assert_eq!(WeightedDie::face_at(20. / 21., 20), Some(20));
+    }
+
+    #[test]
+    fn probability_floor_keeps_faces_possible() {
+        let transfer = WeightTransform::superimpose_pair(6, 6, 1, 1.);
+        let mut drained = WeightedDie::fair_die();
+        drained.apply_transformation(&transfer);
+        assert!(drained.weights()[0].norm_sqr() < 1e-12);
+
+        let mut floored = WeightedDie::fair_die();
+        floored.set_probability_floor(0.02);
+        floored.apply_transformation(&transfer);
+        let weights = floored.weights();
+        assert!(weights[0].norm_sqr() >= 0.02 - 1e-12);
+        assert!(WeightedDie::is_normalized(&weights));
+        assert!(weights[5].norm_sqr() > 1. / 6.);
+    }
+
+    #[test]
+    fn die_string_round_trip() {
+        let mut die = WeightedDie::fair_die();
+        die.apply_transformation(&WeightTransform::superimpose_pair(6, 3, 5, 0.7));
+        let imported = WeightedDie::import_string(&die.export_string()).unwrap();
+        assert_eq!(imported.weights(), die.weights());
+
+        assert!(WeightedDie::import_string("not a die").is_err());
+        assert!(WeightedDie::import_string(&base64::encode([0u8; 48])).is_err());
+        let unnormalized = WeightedDie {
+            weights: vec![c64::from(1.); 6],
+            probability_floor: 0.,
+        };
+        assert!(WeightedDie::import_string(&unnormalized.export_string()).is_err());
+    }
+
+    #[test]
+    fn set_weights_normalizes_or_rejects() {
+        let mut die = WeightedDie::fair_die();
+        die.set_weights(vec![
+            c64::from(2.),
+            c64::new(0., 0.),
+            c64::new(0., 0.),
+            c64::new(0., 0.),
+        ])
+        .unwrap();
+        assert!((die.weights()[0].norm_sqr() - 1.).abs() < 1e-12);
+        assert_eq!(die.faces(), 4);
+
+        let before = die.weights();
+        assert!(die.set_weights(vec![c64::new(0., 0.); 6]).is_err());
+        assert!(die.set_weights(vec![c64::new(f64::NAN, 0.); 6]).is_err());
+        assert!(die
+            .set_weights(vec![c64::new(f64::INFINITY, 0.); 6])
+            .is_err());
+        assert_eq!(die.weights(), before);
+    }
+
+    #[test]
+    fn dice_with_other_face_counts() {
+        for faces in [MIN_FACES, 8, MAX_FACES] {
+            let mut die = WeightedDie::fair_die_with_faces(faces);
+            assert!(WeightedDie::is_normalized(&die.weights()));
+            assert!((die.expected_value() - (faces + 1) as f64 / 2.).abs() < 1e-9);
+
+            let transform = WeightTransform::superimpose_pair(faces, faces as u32, 1, 1.);
+            assert!(WeightTransform::is_unitary(&transform.matrix));
+            die.apply_transformation(&transform);
+            assert!(WeightedDie::is_normalized(&die.weights()));
+            assert!(generate_rolls(&die, 100).len() == faces);
+
+            let imported = WeightedDie::import_string(&die.export_string()).unwrap();
+            assert_eq!(imported.faces(), faces);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn faces_must_be_on_the_die() {
+        WeightTransform::superimpose_pair(4, 1, 6, 0.5);
+    }
+
+    #[test]
+    fn collapse_favors_rolled_face() {
+        let mut die = WeightedDie::fair_die();
+        die.apply_transformation(&WeightTransform::superimpose_pair(6, 2, 4, 0.3));
+        for _ in 0..50 {
+            let before = die.weights();
+            let rolled = die.roll_and_collapse() as usize;
+            let weights = die.weights();
+            assert!(WeightedDie::is_normalized(&weights));
+            let p = before[rolled - 1].norm_sqr();
+            let expected = p + (1. - p) * COLLAPSE_STRENGTH;
+            assert!((weights[rolled - 1].norm_sqr() - expected).abs() < 1e-12);
+        }
+
+        // Plain rolls leave the die untouched
+        let fair = WeightedDie::fair_die();
+        fair.roll();
+        assert_eq!(fair.weights(), WeightedDie::fair_die().weights());
+    }
+
+    #[test]
+    fn seeded_rolls_repeat() {
+        let mut die = WeightedDie::fair_die();
+        die.apply_transformation(&WeightTransform::superimpose_pair(6, 2, 4, 0.3));
+        let rolls = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut die = die.clone();
+            (0..20)
+                .map(|i| {
+                    if i % 2 == 0 {
+                        die.roll_with(&mut rng)
+                    } else {
+                        die.roll_and_collapse_with(&mut rng)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(3), rolls(3));
+
+        // Each value from this generator lands in the middle of the next face
+        let mut rng = StepRng::new(u64::MAX / 12, u64::MAX / 6);
+        let fair = WeightedDie::fair_die();
+        let rolls: Vec<u32> = (0..6).map(|_| fair.roll_with(&mut rng)).collect();
+        assert_eq!(rolls, vec![1, 2, 3, 4, 5, 6]);
+
+        let fair = WeightedDie::fair_die();
+        let measure = |seed| {
+            EntangledPair::new(0, 1, &fair, &fair)
+                .measure_first_with(&mut StdRng::seed_from_u64(seed))
+        };
+        assert_eq!(measure(8), measure(8));
+    }
+
+    #[test]
+    fn entangled_roll_conditions_second_die() {
+        let fair = WeightedDie::fair_die();
+        let mut pair = EntangledPair::new(0, 1, &fair, &fair);
+        let total: f64 = pair.amplitudes.iter().map(|w| w.norm_sqr()).sum();
+        assert!((total - 1.).abs() < 1e-12);
+        assert!(pair.second_weights().is_none());
+
+        let (first, second) = pair.measure_first();
+        assert!((1..=6).contains(&second));
+        assert_eq!(pair.measure_first(), (first, second));
+        let weights = pair.second_weights().unwrap();
+        assert!(WeightedDie::is_normalized(&weights));
+        // The matching face is more likely than each of the others
+        let matching = weights[first as usize - 1].norm_sqr();
+        let other = weights[first as usize % 6].norm_sqr();
+        assert!((matching / other - ENTANGLEMENT_BIAS).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let t = WeightTransform::superimpose_pair(6, 1, 3, 0.6)
+            .combined_with(&WeightTransform::superimpose_pair(6, 5, 2, 0.9));
+        let product = t.combined_with(&t.inverse());
+        let identity = WeightTransform::identity(6);
+        for (row, expected) in product.matrix.iter().zip(identity.matrix.iter()) {
+            for (term, expected) in row.iter().zip(expected.iter()) {
+                assert!((term - expected).norm() < 1e-12);
+            }
+        }
+
+        let mut die = WeightedDie::fair_die();
+        die.apply_transformation(&t);
+        die.apply_transformation(&t.inverse());
+        for (w, fair) in die.weights().iter().zip(WeightedDie::fair_die().weights()) {
+            assert!((w - fair).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn die_survives_ron() {
+        let die = WeightedDie::fair_die();
+        let text = ron::to_string(&die).unwrap();
+        let loaded: WeightedDie = ron::from_str(&text).unwrap();
+        assert_eq!(loaded.faces(), die.faces());
+        for (a, b) in loaded.weights().iter().zip(die.weights()) {
+            assert!((a - b).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn transform_survives_ron() {
+        let transform = WeightTransform::superimpose_pair(6, 6, 1, 0.7);
+        let text = ron::to_string(&transform).unwrap();
+        let loaded: WeightTransform = ron::from_str(&text).unwrap();
+        for (row, loaded_row) in transform.matrix.iter().zip(&loaded.matrix) {
+            for (a, b) in row.iter().zip(loaded_row) {
+                assert!((a - b).norm() < 1e-12);
+            }
+        }
+    }
 }
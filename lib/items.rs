@@ -32,10 +32,13 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::dice::{WeightTransform, WeightedDie};
+use crate::dice::{EntangledPair, WeightTransform, WeightedDie};
 use crate::player::Player;
+use num_complex::Complex64 as c64;
+use rand::seq::SliceRandom;
 use rand::Rng;
 use std::fmt::{Display, Formatter};
+use tracing::error;
 
 pub type HeldItem = Box<dyn Item>;
 pub type PossibleItem = Option<HeldItem>;
@@ -47,14 +50,134 @@ pub trait Item: Send + Sync {
     fn use_item_on_die(&self, die: &mut WeightedDie);
     fn item_type(&self) -> ItemType;
     fn item_benefit(&self, target: &Player) -> f64;
+
+    /// Uses the item on behalf of the source player. Most items only affect
+    /// the target.
+    fn use_item_from(&self, _source: usize, target: usize, players: &mut [Player]) {
+        self.use_item(&mut players[target]);
+    }
+
+    /// Copies the item into a form that can be saved
+    fn save(&self) -> SavedItem;
+
+    /// The number of times the item can still be used
+    fn charges(&self) -> u32 {
+        1
+    }
+
+    /// Uses up one charge of the item. Returns whether the item can still be
+    /// used afterwards.
+    fn consume_charge(&mut self) -> bool {
+        false
+    }
+}
+
+/// A copy of a held item that, unlike the boxed item itself, can be
+/// serialized
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum SavedItem {
+    WeightTransfer(WeightTransfer),
+    Gamble(Gamble),
+    Floor(Floor),
+    Reflect,
+    Phase,
+    Boost,
+    Entangle,
+    SwapPositions,
+    Rebalance,
+    Scout,
 }
 
-const ITEM_TYPES: u32 = 3;
+impl SavedItem {
+    pub fn restore(self) -> HeldItem {
+        match self {
+            SavedItem::WeightTransfer(item) => Box::new(item),
+            SavedItem::Gamble(item) => Box::new(item),
+            SavedItem::Floor(item) => Box::new(item),
+            SavedItem::Reflect => Box::new(Reflect),
+            SavedItem::Phase => Box::new(Phase),
+            SavedItem::Boost => Box::new(Boost),
+            SavedItem::Entangle => Box::new(Entangle),
+            SavedItem::SwapPositions => Box::new(SwapPositions),
+            SavedItem::Rebalance => Box::new(Rebalance),
+            SavedItem::Scout => Box::new(Scout),
+        }
+    }
+}
+
+// Held items are saved through their SavedItem copies
+#[cfg(feature = "serde")]
+pub(crate) mod saved_items {
+    use crate::items::{HeldItem, SavedItem};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(items: &[HeldItem], serializer: S) -> Result<S::Ok, S::Error> {
+        let saved: Vec<SavedItem> = items.iter().map(|item| item.save()).collect();
+        saved.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<HeldItem>, D::Error> {
+        let saved = Vec::<SavedItem>::deserialize(deserializer)?;
+        Ok(saved.into_iter().map(SavedItem::restore).collect())
+    }
+}
+
+#[cfg(feature = "serde")]
+pub(crate) mod saved_item {
+    use crate::items::{PossibleItem, SavedItem};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(item: &PossibleItem, serializer: S) -> Result<S::Ok, S::Error> {
+        item.as_ref().map(|item| item.save()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PossibleItem, D::Error> {
+        let saved = Option::<SavedItem>::deserialize(deserializer)?;
+        Ok(saved.map(SavedItem::restore))
+    }
+}
+
+const ITEM_TYPES: [ItemType; 14] = [
+    ItemType::WeightTransfer,
+    ItemType::DoubleWeightTransfer,
+    ItemType::WeightTransferPair,
+    ItemType::Gamble,
+    ItemType::Reflect,
+    ItemType::Phase,
+    ItemType::Boost,
+    ItemType::Floor,
+    ItemType::Entangle,
+    ItemType::SwapPositions,
+    ItemType::Rebalance,
+    ItemType::Scout,
+    ItemType::SwapFaces,
+    ItemType::CustomTransfer,
+];
+// Maps with this many cells generate every item type equally often, whatever
+// the bias toward movement items
+const REFERENCE_MAP_CELLS: usize = 40 * 40;
+
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum ItemType {
     WeightTransfer,
     DoubleWeightTransfer,
     WeightTransferPair,
+    Gamble,
+    Reflect,
+    Phase,
+    Boost,
+    Floor,
+    Entangle,
+    SwapPositions,
+    Rebalance,
+    Scout,
+    SwapFaces,
+    CustomTransfer,
 }
 
 impl Display for ItemType {
@@ -63,42 +186,179 @@ impl Display for ItemType {
             ItemType::WeightTransfer => write!(f, "Weight Transfer"),
             ItemType::DoubleWeightTransfer => write!(f, "2x Weight Transfer"),
             ItemType::WeightTransferPair => write!(f, "Pair of Weight Transfers"),
+            ItemType::Gamble => write!(f, "Gamble"),
+            ItemType::Reflect => write!(f, "Reflect"),
+            ItemType::Phase => write!(f, "Phase"),
+            ItemType::Boost => write!(f, "Boost"),
+            ItemType::Floor => write!(f, "Floor"),
+            ItemType::Entangle => write!(f, "Entangle"),
+            ItemType::SwapPositions => write!(f, "Swap Positions"),
+            ItemType::Rebalance => write!(f, "Rebalance"),
+            ItemType::Scout => write!(f, "Scout"),
+            ItemType::SwapFaces => write!(f, "Swap Faces"),
+            ItemType::CustomTransfer => write!(f, "Custom Weight Transfer"),
         }
     }
 }
 
+impl ItemType {
+    /// Whether the item moves players around the map rather than changing
+    /// their dice
+    pub fn is_movement(&self) -> bool {
+        matches!(
+            self,
+            ItemType::Boost | ItemType::Phase | ItemType::SwapPositions
+        )
+    }
+
+    /// Whether a reflecting target sends this item back to its user. Only
+    /// items that change the target's die are reflected, so helpful items and
+    /// items that affect both players still land.
+    pub fn is_reflectable(&self) -> bool {
+        matches!(
+            self,
+            ItemType::WeightTransfer
+                | ItemType::DoubleWeightTransfer
+                | ItemType::WeightTransferPair
+                | ItemType::Gamble
+                | ItemType::SwapFaces
+                | ItemType::CustomTransfer
+        )
+    }
+}
+
 impl Default for ItemType {
     fn default() -> Self {
         ItemType::WeightTransfer
     }
 }
 
-pub fn random_item() -> HeldItem {
-    let mut rng = rand::thread_rng();
-    Box::new(match rng.gen_range(0..ITEM_TYPES) {
-        0 => WeightTransfer::random_single(),
-        1 => WeightTransfer::random_double(),
-        2 => WeightTransfer::random_pair(),
-        _ => panic!("Unknown item type"),
-    })
+/// How much more often movement items are generated than other items on a map
+/// with the given number of cells. Movement items become more common on large
+/// maps, where distance matters, and rarer on small ones. A bias of zero keeps
+/// every item type equally likely.
+pub fn movement_item_weight(cells: usize, bias: f64) -> f64 {
+    (cells as f64 / REFERENCE_MAP_CELLS as f64).powf(bias)
+}
+
+/// Generates a random item for dice with the given number of faces
+pub fn random_item<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> HeldItem {
+    random_item_weighted(faces, 1., rng)
+}
+
+/// Generates a random item, with movement items weighted relative to the
+/// other item types as given by `movement_item_weight`
+pub fn random_item_weighted<R: Rng + ?Sized>(
+    faces: usize,
+    movement_weight: f64,
+    rng: &mut R,
+) -> HeldItem {
+    let uniform = (movement_weight - 1.).abs() < f64::EPSILON
+        || !movement_weight.is_finite()
+        || movement_weight < 0.;
+    let item_type = if uniform {
+        ITEM_TYPES[rng.gen_range(0..ITEM_TYPES.len())]
+    } else {
+        ITEM_TYPES
+            .choose_weighted(rng, |item_type| {
+                if item_type.is_movement() {
+                    movement_weight
+                } else {
+                    1.
+                }
+            })
+            .map_or_else(|_| ItemType::default(), |item_type| *item_type)
+    };
+    match item_type {
+        ItemType::WeightTransfer => Box::new(WeightTransfer::random_single(faces, rng)),
+        ItemType::DoubleWeightTransfer => Box::new(WeightTransfer::random_double(faces, rng)),
+        ItemType::WeightTransferPair => Box::new(WeightTransfer::random_pair(faces, rng)),
+        ItemType::Gamble => Box::new(Gamble::new(faces)),
+        ItemType::Reflect => Box::new(Reflect),
+        ItemType::Phase => Box::new(Phase),
+        ItemType::Boost => Box::new(Boost),
+        ItemType::Floor => Box::new(Floor::random(faces, rng)),
+        ItemType::Entangle => Box::new(Entangle),
+        ItemType::SwapPositions => Box::new(SwapPositions),
+        ItemType::Rebalance => Box::new(Rebalance),
+        ItemType::Scout => Box::new(Scout),
+        ItemType::SwapFaces => Box::new(WeightTransfer::random_swap(faces, rng)),
+        ItemType::CustomTransfer => Box::new(WeightTransfer::random_custom(faces, rng)),
+    }
+}
+
+/// Uses an item on the target player, redirecting it to the user if the
+/// target is reflecting. Returns the player the item was actually applied to.
+pub fn use_item_on(item: &dyn Item, source: usize, target: usize, players: &mut [Player]) -> usize {
+    let mut target = target;
+    if item.item_type().is_reflectable() && source != target && players[target].is_reflecting() {
+        players[target].set_reflecting(false);
+        target = source;
+    }
+    item.use_item_from(source, target, players);
+    target
 }
 
+/// The faces and strength picked for a custom weight transfer. Weight moves
+/// from `from` to `to`, favoring `to` at the given strength.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct TransferParameters {
+    pub from: u32,
+    pub to: u32,
+    pub strength: f64,
+}
+
+impl TransferParameters {
+    /// Starts out moving weight from the lowest face to the highest
+    pub fn new(faces: usize) -> Self {
+        TransferParameters {
+            from: 1,
+            to: faces as u32,
+            strength: 0.5,
+        }
+    }
+
+    /// Whether the parameters describe a transfer between two faces of a die
+    /// with the given number of faces
+    pub fn is_valid(&self, faces: usize) -> bool {
+        let on_die = |face: u32| (1..=faces as u32).contains(&face);
+        self.from != self.to
+            && on_die(self.from)
+            && on_die(self.to)
+            && (0.0..=1.0).contains(&self.strength)
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct WeightTransfer {
     item_type: ItemType,
     transform: WeightTransform,
     short: String,
     full: String,
+    #[cfg_attr(feature = "serde", serde(default = "single_charge"))]
+    charges: u32,
 }
 
-fn random_transfer_parameters(count: u32) -> (u32, Vec<u32>, Vec<f64>) {
-    let mut rng = rand::thread_rng();
+#[cfg(feature = "serde")]
+fn single_charge() -> u32 {
+    1
+}
+
+fn random_transfer_parameters<R: Rng + ?Sized>(
+    count: u32,
+    die_faces: usize,
+    rng: &mut R,
+) -> (u32, Vec<u32>, Vec<f64>) {
+    let max = die_faces as u32;
     let mut faces = Vec::with_capacity(count as usize);
     let mut strengths = Vec::with_capacity(count as usize);
-    let dest = rng.gen_range(1..=6);
+    let dest = rng.gen_range(1..=max);
     for _ in 0..count {
-        let mut next = rng.gen_range(1..=6);
+        let mut next = rng.gen_range(1..=max);
         while faces.contains(&next) || dest == next {
-            next = rng.gen_range(1..=6);
+            next = rng.gen_range(1..=max);
         }
         faces.push(next);
         strengths.push(rng.gen_range(0.5..=1.0));
@@ -107,10 +367,11 @@ fn random_transfer_parameters(count: u32) -> (u32, Vec<u32>, Vec<f64>) {
 }
 
 impl WeightTransfer {
-    fn new_single(from: u32, to: u32, strength: f64) -> Self {
+    pub fn new_single(faces: usize, from: u32, to: u32, strength: f64) -> Self {
         WeightTransfer {
             item_type: ItemType::WeightTransfer,
-            transform: WeightTransform::superimpose_pair(to, from, strength),
+            charges: 1,
+            transform: WeightTransform::superimpose_pair(faces, to, from, strength),
             short: format!("Weight transfer {} > {}", from, to),
             full: format!(
                 "Changes the weights on {1} and {2} to a weighted average favoring {2} at {0:.0}%",
@@ -121,18 +382,40 @@ impl WeightTransfer {
         }
     }
 
-    fn random_single() -> Self {
-        let (to, mut from, mut strength) = random_transfer_parameters(1);
+    fn random_single<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> Self {
+        let (to, mut from, mut strength) = random_transfer_parameters(1, faces, rng);
         let from = from.pop().unwrap();
         let strength = strength.pop().unwrap();
-        WeightTransfer::new_single(from, to, strength)
+        let transfer = WeightTransfer::new_single(faces, from, to, strength);
+        // Some of the weaker transfers can be used twice
+        if rng.gen_bool(0.25) {
+            transfer.with_charges(2)
+        } else {
+            transfer
+        }
     }
 
-    fn new_double(from1: u32, strength1: f64, from2: u32, strength2: f64, to: u32) -> Self {
+    pub fn with_charges(mut self, charges: u32) -> Self {
+        debug_assert!(charges > 0);
+        self.charges = charges;
+        self
+    }
+
+    fn new_double(
+        faces: usize,
+        from1: u32,
+        strength1: f64,
+        from2: u32,
+        strength2: f64,
+        to: u32,
+    ) -> Self {
         WeightTransfer {
             item_type: ItemType::DoubleWeightTransfer,
-            transform: WeightTransform::superimpose_pair(to, from1, strength1)
-                .combined_with(&WeightTransform::superimpose_pair(to, from2, strength2)),
+            charges: 1,
+            transform: WeightTransform::superimpose_pair(faces, to, from1, strength1)
+                .combined_with(&WeightTransform::superimpose_pair(
+                    faces, to, from2, strength2,
+                )),
             short: format!("Weight transfer {}, {} > {}", from1, from2, to),
             full: format!(
                 "Sets the weight on {0} to a weighted average with the weight \
@@ -146,16 +429,18 @@ impl WeightTransfer {
         }
     }
 
-    fn random_double() -> Self {
-        let (to, mut froms, mut strengths) = random_transfer_parameters(2);
+    fn random_double<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> Self {
+        let (to, mut froms, mut strengths) = random_transfer_parameters(2, faces, rng);
         let from1 = froms.pop().unwrap();
         let from2 = froms.pop().unwrap();
         let strength1 = strengths.pop().unwrap();
         let strength2 = strengths.pop().unwrap();
-        WeightTransfer::new_double(from1, strength1, from2, strength2, to)
+        WeightTransfer::new_double(faces, from1, strength1, from2, strength2, to)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new_pair(
+        faces: usize,
         from1: u32,
         strength1: f64,
         to1: u32,
@@ -165,8 +450,11 @@ impl WeightTransfer {
     ) -> Self {
         WeightTransfer {
             item_type: ItemType::WeightTransferPair,
-            transform: WeightTransform::superimpose_pair(to1, from1, strength1)
-                .combined_with(&WeightTransform::superimpose_pair(to2, from2, strength2)),
+            charges: 1,
+            transform: WeightTransform::superimpose_pair(faces, to1, from1, strength1)
+                .combined_with(&WeightTransform::superimpose_pair(
+                    faces, to2, from2, strength2,
+                )),
             short: format!(
                 "Weight transfers {} > {} and then {} > {}",
                 from2, to2, from1, to1
@@ -184,14 +472,54 @@ impl WeightTransfer {
         }
     }
 
-    fn random_pair() -> Self {
-        let (to1, mut from1, mut strength1) = random_transfer_parameters(1);
+    fn random_pair<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> Self {
+        let (to1, mut from1, mut strength1) = random_transfer_parameters(1, faces, rng);
         let from1 = from1.pop().unwrap();
         let strength1 = strength1.pop().unwrap();
-        let (to2, mut from2, mut strength2) = random_transfer_parameters(1);
+        let (to2, mut from2, mut strength2) = random_transfer_parameters(1, faces, rng);
         let from2 = from2.pop().unwrap();
         let strength2 = strength2.pop().unwrap();
-        WeightTransfer::new_pair(from1, strength1, to1, from2, strength2, to2)
+        WeightTransfer::new_pair(faces, from1, strength1, to1, from2, strength2, to2)
+    }
+
+    pub fn new_swap(faces: usize, a: u32, b: u32) -> Self {
+        WeightTransfer {
+            item_type: ItemType::SwapFaces,
+            charges: 1,
+            transform: WeightTransform::swap_faces(faces, a, b),
+            short: format!("Swap faces {} <> {}", a, b),
+            full: format!("Exchanges the weights on {} and {}", a, b),
+        }
+    }
+
+    fn random_swap<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> Self {
+        let (a, mut b, _) = random_transfer_parameters(1, faces, rng);
+        WeightTransfer::new_swap(faces, a, b.pop().unwrap())
+    }
+
+    /// A transfer whose faces and strength the user picks when using it. The
+    /// given parameters are used by players who don't pick their own.
+    pub fn new_custom(faces: usize, parameters: TransferParameters) -> Self {
+        let TransferParameters { from, to, strength } = parameters;
+        WeightTransfer {
+            item_type: ItemType::CustomTransfer,
+            charges: 1,
+            transform: WeightTransform::superimpose_pair(faces, to, from, strength),
+            short: "Custom weight transfer".to_string(),
+            full: "Changes the weights on two faces of your choice to a weighted average \
+                favoring one of them, at a strength of your choice"
+                .to_string(),
+        }
+    }
+
+    fn random_custom<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> Self {
+        let (to, mut from, mut strength) = random_transfer_parameters(1, faces, rng);
+        let parameters = TransferParameters {
+            from: from.pop().unwrap(),
+            to,
+            strength: strength.pop().unwrap(),
+        };
+        WeightTransfer::new_custom(faces, parameters)
     }
 }
 
@@ -219,4 +547,647 @@ impl Item for WeightTransfer {
     fn item_benefit(&self, target: &Player) -> f64 {
         self.transform.rel_benefit(target.die())
     }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::WeightTransfer(self.clone())
+    }
+
+    fn charges(&self) -> u32 {
+        self.charges
+    }
+
+    fn consume_charge(&mut self) -> bool {
+        self.charges = self.charges.saturating_sub(1);
+        self.charges > 0
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Gamble {
+    die: WeightedDie,
+    full: String,
+}
+
+impl Gamble {
+    fn new(faces: usize) -> Self {
+        let low = c64::from(0.4f64.sqrt());
+        let middle = c64::from((0.15 / (faces - 2) as f64).sqrt());
+        let high = c64::from(0.45f64.sqrt());
+        let mut weights = vec![middle; faces];
+        weights[0] = low;
+        weights[faces - 1] = high;
+        Gamble {
+            die: WeightedDie::with_weights(weights),
+            full: format!(
+                "Replaces the die with one that almost always rolls either 1 or {}",
+                faces
+            ),
+        }
+    }
+}
+
+impl Item for Gamble {
+    fn short_description(&self) -> &str {
+        "Gamble"
+    }
+
+    fn full_description(&self) -> &str {
+        &self.full
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        player.set_die_weights(self.die.weights());
+    }
+
+    fn use_item_on_die(&self, die: &mut WeightedDie) {
+        if let Err(err) = die.set_weights(self.die.weights()) {
+            error!(%err, "Gamble die has invalid weights");
+        }
+    }
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Gamble
+    }
+
+    fn item_benefit(&self, target: &Player) -> f64 {
+        let before = target.die().expected_value();
+        (self.die.expected_value() - before) / before
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Gamble(self.clone())
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Floor {
+    minimum: u32,
+    short: String,
+    full: String,
+}
+
+impl Floor {
+    pub fn new(minimum: u32) -> Self {
+        debug_assert!(minimum >= 2);
+        Floor {
+            minimum,
+            short: format!("Floor {}", minimum),
+            full: format!(
+                "Moves all weight off the faces below {0} onto the higher faces, \
+                so that you roll at least {0}",
+                minimum
+            ),
+        }
+    }
+
+    fn random<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> Self {
+        Floor::new(rng.gen_range(2..=faces as u32 / 2 + 1))
+    }
+}
+
+impl Item for Floor {
+    fn short_description(&self) -> &str {
+        &self.short
+    }
+
+    fn full_description(&self) -> &str {
+        &self.full
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        let mut die = player.die().clone();
+        self.use_item_on_die(&mut die);
+        player.set_die_weights(die.weights());
+    }
+
+    // The weight on each low face is spread over the faces at or above the
+    // minimum. The transforms depend on the die's current weights, so they
+    // are computed one after the other.
+    fn use_item_on_die(&self, die: &mut WeightedDie) {
+        let minimum = self.minimum.min(die.faces() as u32);
+        let targets = die.faces() as u32 + 1 - minimum;
+        for face in 1..minimum {
+            let to = minimum + (face - 1) % targets;
+            let transform = WeightTransform::drain(die, face, to);
+            die.apply_transformation(&transform);
+        }
+    }
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Floor
+    }
+
+    fn item_benefit(&self, target: &Player) -> f64 {
+        let before = target.die().expected_value();
+        let mut after = target.die().clone();
+        self.use_item_on_die(&mut after);
+        (after.expected_value() - before) / before
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Floor(self.clone())
+    }
+}
+
+pub struct Reflect;
+
+impl Item for Reflect {
+    fn short_description(&self) -> &str {
+        "Reflect"
+    }
+
+    fn full_description(&self) -> &str {
+        "The next item another player uses on you is applied to them instead"
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        player.set_reflecting(true);
+    }
+
+    fn use_item_on_die(&self, _die: &mut WeightedDie) {}
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Reflect
+    }
+
+    fn item_benefit(&self, _target: &Player) -> f64 {
+        0.
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Reflect
+    }
+}
+
+pub struct Phase;
+
+impl Item for Phase {
+    fn short_description(&self) -> &str {
+        "Phase"
+    }
+
+    fn full_description(&self) -> &str {
+        "Lets you walk through walls during your next move"
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        player.set_phasing(true);
+    }
+
+    fn use_item_on_die(&self, _die: &mut WeightedDie) {}
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Phase
+    }
+
+    fn item_benefit(&self, _target: &Player) -> f64 {
+        0.
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Phase
+    }
+}
+
+pub struct Boost;
+
+impl Item for Boost {
+    fn short_description(&self) -> &str {
+        "Boost"
+    }
+
+    fn full_description(&self) -> &str {
+        "Doubles the number of steps you can take after your next roll"
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        player.set_boosted(true);
+    }
+
+    fn use_item_on_die(&self, _die: &mut WeightedDie) {}
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Boost
+    }
+
+    // Doubling the steps has the same effect as doubling the expected roll,
+    // so the benefit is comparable to the relative benefit of other items
+    fn item_benefit(&self, target: &Player) -> f64 {
+        if target.is_boosted() {
+            0.
+        } else {
+            1.
+        }
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Boost
+    }
+}
+
+pub struct Rebalance;
+
+impl Item for Rebalance {
+    fn short_description(&self) -> &str {
+        "Rebalance"
+    }
+
+    fn full_description(&self) -> &str {
+        "Makes the die fair again, undoing any weight that was moved around on it"
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        let mut die = player.die().clone();
+        self.use_item_on_die(&mut die);
+        player.set_die_weights(die.weights());
+    }
+
+    fn use_item_on_die(&self, die: &mut WeightedDie) {
+        let fair = WeightedDie::fair_die_with_faces(die.faces());
+        if let Err(err) = die.set_weights(fair.weights()) {
+            error!(%err, "Failed to rebalance die");
+        }
+    }
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Rebalance
+    }
+
+    // Only helps players whose die is worse than a fair one
+    fn item_benefit(&self, target: &Player) -> f64 {
+        let before = target.die().expected_value();
+        let mut after = target.die().clone();
+        self.use_item_on_die(&mut after);
+        (after.expected_value() - before) / before
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Rebalance
+    }
+}
+
+pub struct Scout;
+
+impl Item for Scout {
+    fn short_description(&self) -> &str {
+        "Scout"
+    }
+
+    fn full_description(&self) -> &str {
+        "Lets you look at the whole map whenever it's your turn"
+    }
+
+    fn use_item(&self, player: &mut Player) {
+        player.set_scouted(true);
+    }
+
+    fn use_item_on_die(&self, _die: &mut WeightedDie) {}
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Scout
+    }
+
+    fn item_benefit(&self, _target: &Player) -> f64 {
+        0.
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Scout
+    }
+}
+
+pub struct Entangle;
+
+impl Item for Entangle {
+    fn short_description(&self) -> &str {
+        "Entangle"
+    }
+
+    fn full_description(&self) -> &str {
+        "Entangles your die with the target's, so that your next roll makes \
+        theirs more likely to land on the same face"
+    }
+
+    // Entanglement needs both players' dice, see use_item_from
+    fn use_item(&self, _player: &mut Player) {}
+
+    fn use_item_on_die(&self, _die: &mut WeightedDie) {}
+
+    fn item_type(&self) -> ItemType {
+        ItemType::Entangle
+    }
+
+    fn item_benefit(&self, _target: &Player) -> f64 {
+        0.
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::Entangle
+    }
+
+    fn use_item_from(&self, source: usize, target: usize, players: &mut [Player]) {
+        if source == target {
+            return;
+        }
+        let pair = EntangledPair::new(source, target, players[source].die(), players[target].die());
+        players[source].set_entanglement(pair);
+    }
+}
+
+pub struct SwapPositions;
+
+impl Item for SwapPositions {
+    fn short_description(&self) -> &str {
+        "Swap positions"
+    }
+
+    fn full_description(&self) -> &str {
+        "Swaps your position on the map with the target's"
+    }
+
+    // Swapping needs both players, see use_item_from
+    fn use_item(&self, _player: &mut Player) {}
+
+    fn use_item_on_die(&self, _die: &mut WeightedDie) {}
+
+    fn item_type(&self) -> ItemType {
+        ItemType::SwapPositions
+    }
+
+    fn item_benefit(&self, _target: &Player) -> f64 {
+        0.
+    }
+
+    fn save(&self) -> SavedItem {
+        SavedItem::SwapPositions
+    }
+
+    fn use_item_from(&self, source: usize, target: usize, players: &mut [Player]) {
+        let source_position = players[source].position();
+        let target_position = players[target].position();
+        players[source].place_at(target_position);
+        players[target].place_at(source_position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice::WeightedDie;
+    use crate::items::*;
+    use crate::map::Coordinates;
+    use crate::player::PlayerType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn gamble_increases_variance() {
+        let fair = WeightedDie::fair_die();
+        let mut die = fair.clone();
+        Gamble::new(6).use_item_on_die(&mut die);
+        assert!(die.variance() > fair.variance());
+
+        let fair = WeightedDie::fair_die_with_faces(12);
+        let mut die = fair.clone();
+        Gamble::new(12).use_item_on_die(&mut die);
+        assert!(die.variance() > fair.variance());
+    }
+
+    #[test]
+    fn reflected_transfer_hits_attacker() {
+        let mut players: Vec<Player> = (0..2)
+            .map(|i| {
+                Player::spawn_at(
+                    Coordinates(0, 0),
+                    format!("Player {}", i),
+                    i,
+                    PlayerType::LocalHuman,
+                )
+            })
+            .collect();
+        Reflect.use_item(&mut players[1]);
+        assert!(players[1].is_reflecting());
+
+        let transfer = WeightTransfer::new_single(6, 1, 6, 1.);
+        let mut expected = WeightedDie::fair_die();
+        transfer.use_item_on_die(&mut expected);
+
+        assert_eq!(use_item_on(&transfer, 0, 1, &mut players), 0);
+        assert_eq!(players[0].die().weights(), expected.weights());
+        assert_eq!(
+            players[1].die().weights(),
+            WeightedDie::fair_die().weights()
+        );
+        // Reflection is used up after one item
+        assert!(!players[1].is_reflecting());
+        assert_eq!(use_item_on(&transfer, 0, 1, &mut players), 1);
+
+        // Helpful items aren't sent back and leave the reflection in place
+        Reflect.use_item(&mut players[1]);
+        assert_eq!(use_item_on(&Rebalance, 0, 1, &mut players), 1);
+        assert_eq!(use_item_on(&SwapPositions, 0, 1, &mut players), 1);
+        assert!(players[1].is_reflecting());
+    }
+
+    #[test]
+    fn floor_guarantees_minimum() {
+        let mut die = WeightedDie::fair_die();
+        die.apply_transformation(&WeightTransform::superimpose_pair(6, 2, 5, 0.3));
+        Floor::new(3).use_item_on_die(&mut die);
+        let weights = die.weights();
+        assert!(weights[0].norm_sqr() + weights[1].norm_sqr() < 1e-12);
+        assert_eq!(die.lowest_possible_face(), 3);
+        let total: f64 = weights.iter().map(|w| w.norm_sqr()).sum();
+        assert!((total - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn entanglement_lasts_until_measured() {
+        let mut players: Vec<Player> = (0..2)
+            .map(|i| {
+                Player::spawn_at(
+                    Coordinates(0, 0),
+                    format!("Player {}", i),
+                    i,
+                    PlayerType::LocalHuman,
+                )
+            })
+            .collect();
+        use_item_on(&Entangle, 0, 1, &mut players);
+        assert_eq!(players[0].entanglement().unwrap().second(), 1);
+        players[0].end_turn();
+        assert!(players[0].entanglement().is_some());
+
+        players[0].entanglement_mut().unwrap().measure_first();
+        players[0].end_turn();
+        assert!(players[0].entanglement().is_none());
+    }
+
+    #[test]
+    fn swap_exchanges_positions() {
+        let mut players: Vec<Player> = (0..2)
+            .map(|i| {
+                Player::spawn_at(
+                    Coordinates(i, 0),
+                    format!("Player {}", i),
+                    i,
+                    PlayerType::LocalHuman,
+                )
+            })
+            .collect();
+        players[0].record_move(crate::map::EAST);
+        use_item_on(&SwapPositions, 0, 1, &mut players);
+        assert!(players[0].position() == Coordinates(1, 0));
+        assert!(players[1].position() == Coordinates(0, 0));
+        // The direction the player came from no longer matters on the new tile
+        assert_eq!(players[0].last_move(), 0);
+
+        // Swapping with a reflecting player leaves everyone in place
+        players[1].set_reflecting(true);
+        use_item_on(&SwapPositions, 0, 1, &mut players);
+        assert!(players[0].position() == Coordinates(1, 0));
+        assert!(players[1].position() == Coordinates(0, 0));
+    }
+
+    #[test]
+    fn charges_run_out() {
+        let mut player = Player::spawn_at(
+            Coordinates(0, 0),
+            "Player".to_string(),
+            0,
+            PlayerType::LocalHuman,
+        );
+        assert_eq!(Reflect.charges(), 1);
+        assert!(!Reflect.consume_charge());
+
+        player.pick_up(Box::new(
+            WeightTransfer::new_single(6, 1, 6, 0.5).with_charges(2),
+        ));
+        let mut item = player.take_item(0);
+        assert!(item.consume_charge());
+        assert_eq!(item.charges(), 1);
+        player.return_item(0, item);
+        let mut item = player.take_item(0);
+        assert!(!item.consume_charge());
+        assert!(player.inventory_empty());
+    }
+
+    #[test]
+    fn custom_transfer_uses_picked_parameters() {
+        let parameters = TransferParameters {
+            from: 2,
+            to: 5,
+            strength: 0.7,
+        };
+        assert!(parameters.is_valid(6));
+        assert!(!parameters.is_valid(4));
+        assert!(!TransferParameters {
+            to: 2,
+            ..parameters
+        }
+        .is_valid(6));
+        assert!(TransferParameters::new(6).is_valid(6));
+
+        let custom = WeightTransfer::new_custom(6, parameters);
+        assert!(matches!(custom.item_type(), ItemType::CustomTransfer));
+        let mut expected = WeightedDie::fair_die();
+        WeightTransfer::new_single(6, 2, 5, 0.7).use_item_on_die(&mut expected);
+        let mut die = WeightedDie::fair_die();
+        custom.use_item_on_die(&mut die);
+        assert_eq!(die.weights(), expected.weights());
+    }
+
+    #[test]
+    fn rebalance_restores_fair_die() {
+        let mut player = Player::spawn_at(
+            Coordinates(0, 0),
+            "Player".to_string(),
+            0,
+            PlayerType::LocalHuman,
+        );
+        assert!(Rebalance.item_benefit(&player).abs() < 1e-12);
+
+        WeightTransfer::new_single(6, 6, 1, 0.8).use_item(&mut player);
+        assert!(player.die().expected_value() < 3.5);
+        assert!(Rebalance.item_benefit(&player) > 0.);
+        Rebalance.use_item(&mut player);
+        assert!((player.die().expected_value() - 3.5).abs() < 1e-9);
+
+        WeightTransfer::new_single(6, 1, 6, 0.8).use_item(&mut player);
+        assert!(Rebalance.item_benefit(&player) < 0.);
+    }
+
+    #[test]
+    fn transfer_toward_high_face_is_beneficial() {
+        let player = Player::spawn_at(
+            Coordinates(0, 0),
+            "Player".to_string(),
+            0,
+            PlayerType::LocalHuman,
+        );
+        assert!(WeightTransfer::new_single(6, 1, 6, 0.8).item_benefit(&player) > 0.);
+        assert!(WeightTransfer::new_single(6, 6, 1, 0.8).item_benefit(&player) < 0.);
+    }
+
+    #[test]
+    fn swapping_a_loaded_face_up_is_beneficial() {
+        let mut player = Player::spawn_at(
+            Coordinates(0, 0),
+            "Player".to_string(),
+            0,
+            PlayerType::LocalHuman,
+        );
+        // A fair die gains nothing from swapping faces
+        let swap = WeightTransfer::new_swap(6, 1, 6);
+        assert!(swap.item_benefit(&player).abs() < 1e-12);
+        WeightTransfer::new_single(6, 6, 1, 0.8).use_item(&mut player);
+        assert!(swap.item_benefit(&player) > 0.);
+        assert!(matches!(swap.item_type(), ItemType::SwapFaces));
+    }
+
+    #[test]
+    fn seeded_items_repeat() {
+        let items = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| random_item(6, &mut rng).full_description().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(items(11), items(11));
+    }
+
+    #[test]
+    fn large_maps_favor_movement_items() {
+        let movement_items = |cells| {
+            let weight = movement_item_weight(cells, 1.);
+            let mut rng = StdRng::seed_from_u64(5);
+            (0..2000)
+                .filter(|_| {
+                    random_item_weighted(6, weight, &mut rng)
+                        .item_type()
+                        .is_movement()
+                })
+                .count()
+        };
+        let small = movement_items(15 * 15);
+        let large = movement_items(100 * 100);
+        assert!(large > 2 * small, "{} vs {}", large, small);
+        assert_eq!(movement_item_weight(15 * 15, 0.), 1.);
+    }
+
+    #[test]
+    fn saved_items_restore() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let item = random_item(6, &mut rng);
+            let restored = item.save().restore();
+            assert_eq!(restored.short_description(), item.short_description());
+            assert_eq!(restored.full_description(), item.full_description());
+            assert_eq!(
+                restored.item_type().to_string(),
+                item.item_type().to_string()
+            );
+        }
+    }
 }
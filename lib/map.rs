@@ -33,9 +33,19 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
 use crate::items;
-use crate::items::{random_item, HeldItem};
-use rand::Rng;
+use crate::items::{random_item_weighted, HeldItem};
+use image::{Rgba, RgbaImage};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "serde")]
+use std::io;
+#[cfg(feature = "serde")]
+use std::path::Path;
 use std::slice::Iter;
+use tracing::{debug, error, info, info_span, warn};
 
 pub type Direction = u8;
 pub const NORTH: u8 = 1 << 0;
@@ -58,13 +68,23 @@ pub const SOUTHEAST: u8 = SOUTH | EAST;
 pub const NORTHWEST: u8 = NORTH | WEST;
 pub const SOUTHWEST: u8 = SOUTH | WEST;
 
+/// Whether the direction is a diagonal step, combining one vertical and one
+/// horizontal direction
+pub fn is_diagonal(d: Direction) -> bool {
+    matches!(d, NORTHEAST | NORTHWEST | SOUTHEAST | SOUTHWEST)
+}
+
+/// Whether the two directions are opposite cardinal or diagonal directions.
+/// Anything else, including no direction at all or any other combination of
+/// directions, is never opposite.
 pub fn directions_are_opposite(a: Direction, b: Direction) -> bool {
     match a {
         NORTH => b == SOUTH,
         SOUTH => b == NORTH,
         EAST => b == WEST,
         WEST => b == EAST,
-        _ => panic!("Unsupported direction"),
+        _ if is_diagonal(a) => b == get_opposite_direction(a),
+        _ => false,
     }
 }
 
@@ -74,17 +94,33 @@ pub fn get_opposite_direction(d: Direction) -> Direction {
         SOUTH => NORTH,
         EAST => WEST,
         WEST => EAST,
-        _ => panic!("Unsupported direction"),
+        NORTHEAST => SOUTHWEST,
+        SOUTHWEST => NORTHEAST,
+        NORTHWEST => SOUTHEAST,
+        SOUTHEAST => NORTHWEST,
+        _ => {
+            error!(d, "No opposite for unsupported direction");
+            0
+        }
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum GridCell {
     Wall,
-    Path(Direction, items::PossibleItem),
+    Path(
+        Direction,
+        #[cfg_attr(feature = "serde", serde(with = "items::saved_item"))] items::PossibleItem,
+    ),
     Goal(Direction),
+    // Stepping onto a teleporter moves the player to the paired teleporter
+    Teleporter(Direction, Coordinates),
+    // Stepping onto a trap ends the player's move
+    Trap(Direction),
 }
 
 #[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Coordinates(pub usize, pub usize);
 
 impl Coordinates {
@@ -114,37 +150,260 @@ impl Coordinates {
                 }
                 self.0 -= 1
             }
-            _ => panic!("Cannot move in this direction"),
+            // Diagonal steps only happen if both halves stay on the map
+            NORTHEAST | NORTHWEST | SOUTHEAST | SOUTHWEST => {
+                let mut moved = *self;
+                if !moved.step(direction & LONGITUDINAL, width, height)
+                    || !moved.step(direction & LATITUDINAL, width, height)
+                {
+                    return false;
+                }
+                *self = moved;
+            }
+            _ => {
+                error!(direction, "Cannot move in this direction");
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Steps like `step`, except that leaving one edge of the map enters it
+    /// again from the opposite edge
+    pub fn step_wrapping(&mut self, direction: Direction, width: usize, height: usize) -> bool {
+        match direction {
+            NORTH => self.1 = (self.1 + 1) % height,
+            SOUTH => self.1 = (self.1 + height - 1) % height,
+            EAST => self.0 = (self.0 + 1) % width,
+            WEST => self.0 = (self.0 + width - 1) % width,
+            NORTHEAST | NORTHWEST | SOUTHEAST | SOUTHWEST => {
+                self.step_wrapping(direction & LONGITUDINAL, width, height);
+                self.step_wrapping(direction & LATITUDINAL, width, height);
+            }
+            _ => {
+                error!(direction, "Cannot move in this direction");
+                return false;
+            }
         }
         true
     }
 }
 
+/// The algorithm used to lay out the paths of a map
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum MapGenerator {
+    Corridors,
+    Staircases,
+    RecursiveBacktracker,
+    Prims,
+}
+pub const MAP_GENERATORS: [MapGenerator; 4] = [
+    MapGenerator::Corridors,
+    MapGenerator::Staircases,
+    MapGenerator::RecursiveBacktracker,
+    MapGenerator::Prims,
+];
+
+impl Display for MapGenerator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapGenerator::Corridors => write!(f, "Corridors"),
+            MapGenerator::Staircases => write!(f, "Staircases"),
+            MapGenerator::RecursiveBacktracker => write!(f, "Recursive backtracker maze"),
+            MapGenerator::Prims => write!(f, "Prim's maze"),
+        }
+    }
+}
+
+impl MapGenerator {
+    pub fn description(&self) -> &str {
+        match self {
+            MapGenerator::Corridors => {
+                "Connects the starting positions, goals and items with straight corridors"
+            }
+            MapGenerator::Staircases => {
+                "Connects the starting positions, goals and items with zigzagging corridors \
+                that can be crossed diagonally"
+            }
+            MapGenerator::RecursiveBacktracker => {
+                "Carves a maze covering the whole map with long, winding passages"
+            }
+            MapGenerator::Prims => "Carves a maze covering the whole map with many short dead ends",
+        }
+    }
+}
+
+impl MapGenerator {
+    // Whether the map starts out as walls and paths are carved between the
+    // cells that need to be connected
+    fn carves_corridors(&self) -> bool {
+        matches!(self, MapGenerator::Corridors | MapGenerator::Staircases)
+    }
+}
+
+impl Default for MapGenerator {
+    fn default() -> Self {
+        MapGenerator::Corridors
+    }
+}
+
+// Number of times map generation is attempted before giving up on producing
+// a map where every player can reach a goal
+const MAX_GENERATION_ATTEMPTS: usize = 10;
+
+// Smallest width and height of a generated map, so that there is room for
+// the goal and a starting position
+const MIN_MAP_SIDE: usize = 2;
+
+// Number of random cells tried when looking for an empty one before giving up
+const MAX_CELL_ATTEMPTS: usize = 1000;
+
 type Grid<T> = Vec<Vec<T>>;
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Map {
     grid: Grid<GridCell>,
     distances: Grid<Option<usize>>,
-    goal: Coordinates,
+    goals: Vec<Coordinates>,
     starting_points: Vec<Coordinates>,
-}
-
-macro_rules! dfs_compute_distances {
-    ($map:expr, $exits:ident, $dir:ident, $x:expr, $y:expr, $dist:expr) => {
-        if $exits & $dir != 0 {
-            let next = Coordinates($x, $y);
-            $map.compute_distances(next, $dist);
-        }
-    };
+    // Whether the edges of the map wrap around to the opposite edge
+    #[cfg_attr(feature = "serde", serde(default))]
+    wraps: bool,
 }
 
 impl Map {
+    #[allow(clippy::too_many_arguments)]
     pub fn generate_random_map(
         map_width: usize,
         map_height: usize,
         players: usize,
         item_density: f64,
         travel_distance: usize,
+        die_faces: usize,
+        teleporter_density: f64,
+        trap_density: f64,
+        num_goals: usize,
+        generator: MapGenerator,
+    ) -> Self {
+        Map::generate_with_rng(
+            map_width,
+            map_height,
+            players,
+            item_density,
+            0.,
+            travel_distance,
+            die_faces,
+            teleporter_density,
+            trap_density,
+            num_goals,
+            generator,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Generates a map like `generate_random_map`, always producing the same
+    /// map for the same seed and parameters
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_random_map_seeded(
+        map_width: usize,
+        map_height: usize,
+        players: usize,
+        item_density: f64,
+        item_bias: f64,
+        travel_distance: usize,
+        die_faces: usize,
+        teleporter_density: f64,
+        trap_density: f64,
+        num_goals: usize,
+        generator: MapGenerator,
+        seed: u64,
+    ) -> Self {
+        info!(seed, "Using map seed");
+        Map::generate_with_rng(
+            map_width,
+            map_height,
+            players,
+            item_density,
+            item_bias,
+            travel_distance,
+            die_faces,
+            teleporter_density,
+            trap_density,
+            num_goals,
+            generator,
+            &mut StdRng::seed_from_u64(seed),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn generate_with_rng<R: Rng + ?Sized>(
+        map_width: usize,
+        map_height: usize,
+        players: usize,
+        item_density: f64,
+        item_bias: f64,
+        travel_distance: usize,
+        die_faces: usize,
+        teleporter_density: f64,
+        trap_density: f64,
+        num_goals: usize,
+        generator: MapGenerator,
+        rng: &mut R,
+    ) -> Self {
+        let (map_width, map_height) = (map_width.max(MIN_MAP_SIDE), map_height.max(MIN_MAP_SIDE));
+        // Every player needs a cell of their own besides the goal
+        let max_players = map_width * map_height - 1;
+        let players = if players == 0 || players > max_players {
+            warn!(players, max_players, "Clamping number of players");
+            players.clamp(1, max_players)
+        } else {
+            players
+        };
+        let mut attempt = 1;
+        loop {
+            let map = Map::generate_once(
+                map_width,
+                map_height,
+                players,
+                item_density,
+                item_bias,
+                travel_distance,
+                die_faces,
+                teleporter_density,
+                trap_density,
+                num_goals,
+                generator,
+                rng,
+            );
+            if map.is_solvable() {
+                return map;
+            }
+            if attempt == MAX_GENERATION_ATTEMPTS {
+                error!(attempt, "Failed to generate a solvable map");
+                return map;
+            }
+            warn!(attempt, "Generated map is not solvable, regenerating");
+            attempt += 1;
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn generate_once<R: Rng + ?Sized>(
+        map_width: usize,
+        map_height: usize,
+        players: usize,
+        item_density: f64,
+        item_bias: f64,
+        travel_distance: usize,
+        die_faces: usize,
+        teleporter_density: f64,
+        trap_density: f64,
+        num_goals: usize,
+        generator: MapGenerator,
+        rng: &mut R,
     ) -> Self {
+        let _span = info_span!("generate_random_map", map_width, map_height, players).entered();
+        debug!(generator = %generator, "Generating map");
         let mut grid = Grid::with_capacity(map_height);
         let mut distances = Grid::with_capacity(map_height);
         for row in 0..map_height {
@@ -158,117 +417,346 @@ impl Map {
         let mut map = Map {
             grid,
             distances,
-            goal: Coordinates(0, 0),
+            goals: vec![],
             starting_points: vec![],
+            wraps: false,
         };
 
         // Randomly place goal
-        let goal = map.get_random_cell();
-        map.goal = goal;
+        let goal = map.get_random_cell(rng);
+        map.goals.push(goal);
         map.set_cell(goal, GridCell::Goal(0));
+        debug!(x = goal.0, y = goal.1, "Placed goal");
 
-        // Set random starting positions for players
-        for _ in 0..players {
-            let start = map.get_random_cell_with_distance(goal, travel_distance);
-            map.connect_cells(start, goal);
+        // Maze generators cover the whole map in paths up front, so everything
+        // placed afterwards is already connected
+        match generator {
+            MapGenerator::Corridors | MapGenerator::Staircases => {}
+            MapGenerator::RecursiveBacktracker => map.carve_backtracker_maze(goal, rng),
+            MapGenerator::Prims => map.carve_prims_maze(goal, rng),
+        }
 
+        // The starting positions can't be further from the goal than the
+        // furthest corner of the map, and can't be on the goal itself
+        let max_distance = map.max_distance_from(goal);
+        let travel_distance = if travel_distance > max_distance || travel_distance == 0 {
+            warn!(travel_distance, max_distance, "Clamping travel distance");
+            travel_distance.clamp(1, max_distance)
+        } else {
+            travel_distance
+        };
+
+        // Set random starting positions for players, making sure no two
+        // players spawn on the same cell
+        for _ in 0..players {
+            let start = map.get_unique_start(goal, travel_distance, rng);
+            debug!(x = start.0, y = start.1, "Placed starting position");
             map.starting_points.push(start);
         }
 
+        // Any further goals are connected to the first so that all of them
+        // can be reached
+        let diagonal = generator == MapGenerator::Staircases;
+        let free_cells = (map_width * map_height - 1).saturating_sub(map.starting_points.len());
+        for _ in 1..num_goals.min(free_cells) {
+            let mut extra = map.get_random_cell(rng);
+            while map.goals.contains(&extra) || map.starting_points.contains(&extra) {
+                extra = map.get_random_cell(rng);
+            }
+            let exits = match map.cell_at(extra) {
+                GridCell::Path(exits, _) => *exits,
+                _ => 0,
+            };
+            map.set_cell(extra, GridCell::Goal(exits));
+            if generator.carves_corridors() {
+                map.connect_cells(extra, goal, diagonal);
+            }
+            debug!(x = extra.0, y = extra.1, "Placed extra goal");
+            map.goals.push(extra);
+        }
+
+        // Each player is given a path to the nearest goal
+        if generator.carves_corridors() {
+            for start in map.starting_points.clone() {
+                let nearest = map.nearest_goal(start);
+                map.connect_cells(start, nearest, diagonal);
+            }
+        }
+
         let total_squares = (map_width * map_height) as f64;
         let item_squares = (total_squares * item_density).round() as usize;
+        let movement_weight = items::movement_item_weight(map_width * map_height, item_bias);
+        let mut items_placed = 0;
         for _ in 0..(item_squares / 2) {
-            let square1 = map.get_random_empty_cell();
-            let item1 = random_item();
-            let square2 = map.get_random_empty_cell();
-            if square1 == square2 {
-                continue;
-            }
-            let item2 = random_item();
+            let square1 = map.get_random_empty_cell(rng);
+            let item1 = random_item_weighted(die_faces, movement_weight, rng);
+            let square2 = map.get_random_empty_cell(rng);
+            let (square1, square2) = match (square1, square2) {
+                (Some(square1), Some(square2)) if square1 != square2 => (square1, square2),
+                _ => continue,
+            };
+            let item2 = random_item_weighted(die_faces, movement_weight, rng);
 
-            map.connect_cells(square1, square2);
-            map.place_item(square1, item1);
-            map.place_item(square2, item2);
+            if generator.carves_corridors() {
+                map.connect_cells(square1, square2, diagonal);
+            }
+            // A square that already holds an item keeps it
+            items_placed += [
+                map.place_item(square1, item1),
+                map.place_item(square2, item2),
+            ]
+            .into_iter()
+            .filter(Result::is_ok)
+            .count();
         }
+        map.relocate_misplaced_items(rng);
+        map.repair_exits();
+
+        let teleporter_squares = (total_squares * teleporter_density).round() as usize;
+        let teleporters_placed = map.place_teleporters(teleporter_squares / 2, rng);
+        let traps_placed = map.place_traps((total_squares * trap_density).round() as usize, rng);
 
-        map.compute_distances(goal, 0);
+        map.compute_distances();
+        info!(
+            items_placed,
+            teleporters_placed, traps_placed, "Generated map"
+        );
 
         map
     }
 
-    fn compute_distances(&mut self, mut cell: Coordinates, mut distance: usize) {
-        let Coordinates(mut x, mut y) = cell;
-        // Optimize recursion depth by searching straight paths iteratively
-        loop {
-            // First determine the possible travel directions from the current cell
-            // and store the distance of this cell from the goal
-            let mut to_check = 0;
-            self.distances[y][x] = match self.cell_at(cell) {
-                GridCell::Wall => None,
-                GridCell::Path(exits, _) | GridCell::Goal(exits) => {
-                    to_check = *exits;
-                    Some(distance)
-                }
+    #[cfg(test)]
+    pub(crate) fn from_grid(grid: Grid<GridCell>) -> Self {
+        let distances = vec![vec![None; grid[0].len()]; grid.len()];
+        let mut map = Map {
+            grid,
+            distances,
+            goals: vec![],
+            starting_points: vec![],
+            wraps: false,
+        };
+        map.goals = map
+            .iter()
+            .filter(|(_, cell)| matches!(cell, GridCell::Goal(_)))
+            .map(|(position, _)| position)
+            .collect();
+        map.compute_distances();
+        map
+    }
+
+    // Breadth-first search outward from all goals at once, so each cell gets
+    // its distance to the nearest goal. A player only ends up standing on a
+    // teleporter by stepping onto its partner, so the cells leading to a
+    // teleporter are the neighbors of its partner.
+    fn compute_distances(&mut self) {
+        for row in self.distances.iter_mut() {
+            row.fill(None);
+        }
+        let mut queue = VecDeque::new();
+        for goal in &self.goals {
+            self.distances[goal.1][goal.0] = Some(0);
+            queue.push_back((*goal, 0));
+        }
+        while let Some((cell, distance)) = queue.pop_front() {
+            let entrance = match self.cell_at(cell) {
+                GridCell::Teleporter(_, partner) => *partner,
+                _ => cell,
             };
-            if to_check == 0 {
-                return;
-            }
-            // Eliminate directions that do not need to be searched
-            // because they lead out of bounds
-            if x == 0 {
-                to_check &= !WEST;
-            } else if let Some(d) = self.distances[y][x - 1] {
-                if d <= distance + 1 {
-                    to_check &= !WEST;
+            for direction in [NORTH, SOUTH, EAST, WEST] {
+                let mut neighbor = entrance;
+                if !self.step(&mut neighbor, direction) {
+                    continue;
                 }
-            }
-            if x + 1 >= self.width() {
-                to_check &= !EAST;
-            } else if let Some(d) = self.distances[y][x + 1] {
-                if d <= distance + 1 {
-                    to_check &= !EAST;
+                let exits = match self.cell_at(neighbor) {
+                    GridCell::Wall => continue,
+                    GridCell::Path(exits, _)
+                    | GridCell::Goal(exits)
+                    | GridCell::Teleporter(exits, _)
+                    | GridCell::Trap(exits) => *exits,
+                };
+                if exits & get_opposite_direction(direction) == 0 {
+                    continue;
+                }
+                let Coordinates(x, y) = neighbor;
+                if self.distances[y][x].is_none() {
+                    self.distances[y][x] = Some(distance + 1);
+                    queue.push_back((neighbor, distance + 1));
                 }
             }
-            if y == 0 {
-                to_check &= !SOUTH;
-            } else if let Some(d) = self.distances[y - 1][x] {
-                if d <= distance + 1 {
-                    to_check &= !SOUTH;
+        }
+    }
+
+    // Turns pairs of empty path cells into teleporters leading to each other.
+    // Starting positions are left alone. Returns the number of teleporters.
+    fn place_teleporters<R: Rng + ?Sized>(&mut self, pairs: usize, rng: &mut R) -> usize {
+        let mut candidates: Vec<Coordinates> = self
+            .iter()
+            .filter(|(pos, cell)| {
+                matches!(cell, GridCell::Path(_, None)) && !self.starting_points.contains(pos)
+            })
+            .map(|(pos, _)| pos)
+            .collect();
+        candidates.shuffle(rng);
+        let mut placed = 0;
+        for pair in candidates.chunks_exact(2).take(pairs) {
+            let (a, b) = (pair[0], pair[1]);
+            for (cell, partner) in [(a, b), (b, a)] {
+                if let GridCell::Path(exits, _) = *self.cell_at(cell) {
+                    self.set_cell(cell, GridCell::Teleporter(exits, partner));
                 }
             }
-            if y + 1 >= self.height() {
-                to_check &= !NORTH;
-            } else if let Some(d) = self.distances[y + 1][x] {
-                if d <= distance + 1 {
-                    to_check &= !NORTH;
+            debug!(x1 = a.0, y1 = a.1, x2 = b.0, y2 = b.1, "Placed teleporters");
+            placed += 2;
+        }
+        placed
+    }
+
+    // Turns empty path cells into traps. Starting positions are left alone.
+    // Returns the number of traps placed.
+    fn place_traps<R: Rng + ?Sized>(&mut self, count: usize, rng: &mut R) -> usize {
+        let mut candidates: Vec<Coordinates> = self
+            .iter()
+            .filter(|(pos, cell)| {
+                matches!(cell, GridCell::Path(_, None)) && !self.starting_points.contains(pos)
+            })
+            .map(|(pos, _)| pos)
+            .collect();
+        candidates.shuffle(rng);
+        candidates.truncate(count);
+        for cell in &candidates {
+            if let GridCell::Path(exits, _) = *self.cell_at(*cell) {
+                self.set_cell(*cell, GridCell::Trap(exits));
+            }
+        }
+        candidates.len()
+    }
+
+    /// Determines whether a goal can be reached from every starting position
+    /// by following the exits of each cell
+    pub fn is_solvable(&self) -> bool {
+        self.starting_points
+            .iter()
+            .all(|start| self.goal_reachable_from(*start))
+    }
+
+    // Flood fill outward from the given cell, moving the way players do
+    fn goal_reachable_from(&self, start: Coordinates) -> bool {
+        let mut visited = vec![vec![false; self.width()]; self.height()];
+        let mut stack = vec![start];
+        visited[start.1][start.0] = true;
+        while let Some(cell) = stack.pop() {
+            let exits = match self.cell_at(cell) {
+                GridCell::Wall => continue,
+                GridCell::Goal(_) => return true,
+                GridCell::Path(exits, _)
+                | GridCell::Teleporter(exits, _)
+                | GridCell::Trap(exits) => *exits,
+            };
+            for direction in [NORTH, SOUTH, EAST, WEST] {
+                let mut neighbor = cell;
+                if exits & direction == 0 || !self.step(&mut neighbor, direction) {
+                    continue;
+                }
+                let Coordinates(x, y) = self.landing_cell(neighbor);
+                if !visited[y][x] {
+                    visited[y][x] = true;
+                    stack.push(Coordinates(x, y));
                 }
             }
-            // If there is only one direction to search, do so iteratively;
-            // otherwise, recursively search all possible paths
-            match to_check {
-                NORTH => y += 1,
-                SOUTH => y -= 1,
-                EAST => x += 1,
-                WEST => x -= 1,
-                0 => return,
-                _ => {
-                    dfs_compute_distances!(self, to_check, NORTH, x, y + 1, distance + 1);
-                    dfs_compute_distances!(self, to_check, SOUTH, x, y - 1, distance + 1);
-                    dfs_compute_distances!(self, to_check, EAST, x + 1, y, distance + 1);
-                    dfs_compute_distances!(self, to_check, WEST, x - 1, y, distance + 1);
-                    return;
+        }
+        false
+    }
+
+    /// Where a player stepping onto the given cell ends up
+    pub fn landing_cell(&self, cell: Coordinates) -> Coordinates {
+        match self.cell_at(cell) {
+            GridCell::Teleporter(_, partner) => *partner,
+            _ => cell,
+        }
+    }
+
+    // Exits must lead to a neighboring cell which has a matching exit back.
+    // Any exits that don't are removed, and paths left without any exits are
+    // turned back into walls. Returns the number of cells that were repaired.
+    fn repair_exits(&mut self) -> usize {
+        let mut repaired = 0;
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let position = Coordinates(x, y);
+                let exits = match self.cell_at(position) {
+                    GridCell::Wall => continue,
+                    GridCell::Path(exits, _)
+                    | GridCell::Goal(exits)
+                    | GridCell::Teleporter(exits, _)
+                    | GridCell::Trap(exits) => *exits,
+                };
+                let mut valid = 0;
+                for direction in [NORTH, SOUTH, EAST, WEST] {
+                    if exits & direction == 0 {
+                        continue;
+                    }
+                    let mut neighbor = position;
+                    if !self.step(&mut neighbor, direction) {
+                        continue;
+                    }
+                    let backwards = get_opposite_direction(direction);
+                    match self.cell_at(neighbor) {
+                        GridCell::Path(other, _)
+                        | GridCell::Goal(other)
+                        | GridCell::Teleporter(other, _)
+                        | GridCell::Trap(other)
+                            if other & backwards != 0 =>
+                        {
+                            valid |= direction
+                        }
+                        _ => {}
+                    }
+                }
+                if valid == exits {
+                    continue;
+                }
+                error!(x, y, exits, valid, "Repairing invalid exits");
+                repaired += 1;
+                if valid == 0 && matches!(self.cell_at(position), GridCell::Path(_, _)) {
+                    self.set_cell(position, GridCell::Wall);
+                } else if let GridCell::Path(exits, _)
+                | GridCell::Goal(exits)
+                | GridCell::Teleporter(exits, _)
+                | GridCell::Trap(exits) = self.cell_at_mut(position)
+                {
+                    *exits = valid;
                 }
             }
-            distance += 1;
-            cell = Coordinates(x, y);
         }
+        repaired
     }
 
-    fn place_item(&mut self, coordinates: Coordinates, item: HeldItem) {
-        if let GridCell::Path(_, cell) = self.cell_at_mut(coordinates) {
-            cell.replace(item);
-        } else {
-            panic!("Cannot place item on non-path square");
+    // Later paths may be connected through starting positions, so make sure
+    // no player starts the game on top of an item
+    fn relocate_misplaced_items<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        for start in self.starting_points.clone() {
+            let item = match self.cell_at_mut(start) {
+                GridCell::Path(_, item) => item.take(),
+                _ => None,
+            };
+            if let Some(item) = item {
+                warn!(
+                    x = start.0,
+                    y = start.1,
+                    "Relocating item on starting position"
+                );
+                let free: Vec<Coordinates> = self
+                    .iter()
+                    .filter(|(pos, cell)| {
+                        matches!(cell, GridCell::Path(_, None))
+                            && !self.starting_points.contains(pos)
+                    })
+                    .map(|(pos, _)| pos)
+                    .collect();
+                if !free.is_empty() {
+                    let _ = self.place_item(free[rng.gen_range(0..free.len())], item);
+                }
+            }
         }
     }
 
@@ -281,7 +769,10 @@ impl Map {
         let Coordinates(x, y) = coordinates;
         match &mut self.grid[y][x] {
             GridCell::Wall => self.set_cell(coordinates, GridCell::Path(direction, None)),
-            GridCell::Path(existing, _) | GridCell::Goal(existing) => {
+            GridCell::Path(existing, _)
+            | GridCell::Goal(existing)
+            | GridCell::Teleporter(existing, _)
+            | GridCell::Trap(existing) => {
                 *existing |= direction;
             }
         }
@@ -295,35 +786,133 @@ impl Map {
         self.grid.len()
     }
 
-    fn get_random_empty_cell(&self) -> Coordinates {
-        let mut cell = self.get_random_cell();
-        loop {
-            match self.cell_at(cell) {
-                GridCell::Goal(_) => {}
-                _ => {
-                    if self.starting_points.contains(&cell) {
-                        cell = self.get_random_cell();
-                    } else {
-                        break;
-                    }
-                }
+    /// Moves the coordinates one cell in the given direction, wrapping around
+    /// the edges on toroidal maps. Returns whether the coordinates moved.
+    pub fn step(&self, coordinates: &mut Coordinates, direction: Direction) -> bool {
+        if self.wraps {
+            coordinates.step_wrapping(direction, self.width(), self.height())
+        } else {
+            coordinates.step(direction, self.width(), self.height())
+        }
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wraps
+    }
+
+    /// The fewest steps between two cells, ignoring walls. On toroidal maps
+    /// the way around the edge counts if it's shorter.
+    pub fn grid_distance(&self, a: Coordinates, b: Coordinates) -> usize {
+        let along = |p: usize, q: usize, side: usize| {
+            let direct = p.max(q) - p.min(q);
+            if self.wraps {
+                direct.min(side - direct)
+            } else {
+                direct
+            }
+        };
+        along(a.0, b.0, self.width()) + along(a.1, b.1, self.height())
+    }
+
+    /// Makes the edges of the map wrap around, opening a passage across the
+    /// edge wherever the cells on both sides are open. The starting positions
+    /// keep the distances they were placed at, so the wrapped passages can
+    /// make for shortcuts.
+    pub fn make_toroidal(&mut self) {
+        self.wraps = true;
+        let (width, height) = (self.width(), self.height());
+        let open = |map: &Map, x: usize, y: usize| !matches!(map.grid[y][x], GridCell::Wall);
+        for x in 0..width {
+            if open(self, x, height - 1) && open(self, x, 0) {
+                self.carve(Coordinates(x, height - 1), NORTH);
             }
         }
-        cell
+        for y in 0..height {
+            if open(self, width - 1, y) && open(self, 0, y) {
+                self.carve(Coordinates(width - 1, y), EAST);
+            }
+        }
+        self.compute_distances();
+    }
+
+    // A random cell that is neither a goal nor a starting position, or `None`
+    // if none was found after `MAX_CELL_ATTEMPTS` tries
+    fn get_random_empty_cell<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Coordinates> {
+        (0..MAX_CELL_ATTEMPTS)
+            .map(|_| self.get_random_cell(rng))
+            .find(|cell| {
+                !matches!(self.cell_at(*cell), GridCell::Goal(_))
+                    && !self.starting_points.contains(cell)
+            })
     }
 
-    fn get_random_cell(&self) -> Coordinates {
-        let mut rng = rand::thread_rng();
+    // A starting position at the given distance from the goal that no other
+    // player starts on. If every such cell is taken, the free cell with the
+    // closest distance is used instead.
+    fn get_unique_start<R: Rng + ?Sized>(
+        &self,
+        goal: Coordinates,
+        distance: usize,
+        rng: &mut R,
+    ) -> Coordinates {
+        let found = (0..MAX_CELL_ATTEMPTS)
+            .map(|_| self.get_random_cell_with_distance(goal, distance, rng))
+            .find(|start| !self.starting_points.contains(start));
+        if let Some(start) = found {
+            return start;
+        }
+        warn!(distance, "No free start at the travel distance");
+        let Coordinates(x0, y0) = goal;
+        self.iter()
+            .map(|(pos, _)| pos)
+            .filter(|pos| *pos != goal && !self.starting_points.contains(pos))
+            .min_by_key(|Coordinates(x, y)| {
+                let d = x0.max(*x) - x0.min(*x) + y0.max(*y) - y0.min(*y);
+                d.max(distance) - d.min(distance)
+            })
+            .expect("Map has no room for another player")
+    }
+
+    fn get_random_cell<R: Rng + ?Sized>(&self, rng: &mut R) -> Coordinates {
         let x = rng.gen_range(0..self.width());
         let y = rng.gen_range(0..self.height());
         Coordinates(x, y)
     }
 
-    fn get_random_cell_with_distance(&self, target: Coordinates, distance: usize) -> Coordinates {
+    // The goal with the shortest Manhattan distance to the given cell
+    fn nearest_goal(&self, from: Coordinates) -> Coordinates {
+        let Coordinates(x0, y0) = from;
+        *self
+            .goals
+            .iter()
+            .min_by_key(|Coordinates(x, y)| x0.max(*x) - x0.min(*x) + y0.max(*y) - y0.min(*y))
+            .expect("Map has no goals")
+    }
+
+    fn max_distance_from(&self, target: Coordinates) -> usize {
+        let Coordinates(x0, y0) = target;
+        x0.max(self.width() - 1 - x0) + y0.max(self.height() - 1 - y0)
+    }
+
+    fn get_random_cell_with_distance<R: Rng + ?Sized>(
+        &self,
+        target: Coordinates,
+        distance: usize,
+        rng: &mut R,
+    ) -> Coordinates {
         let Coordinates(x0, y0) = target;
-        let mut rng = rand::thread_rng();
-        let x_low = if x0 < distance { 0 } else { x0 - distance };
-        let x = rng.gen_range(x_low..=(x0 + distance).min(self.width() - 1));
+        // Only consider columns from which the remaining distance can still be
+        // covered vertically
+        let max_dy = y0.max(self.height() - 1 - y0);
+        let columns = (0..self.width())
+            .filter(|x| {
+                let dx = x0.max(*x) - x0.min(*x);
+                dx <= distance && distance - dx <= max_dy
+            })
+            .collect::<Vec<_>>();
+        let x = *columns
+            .choose(rng)
+            .expect("Distance exceeds the size of the map");
         let dx = x0.max(x) - x0.min(x);
         let dy = distance - dx;
         if y0 + dy >= self.height() {
@@ -337,10 +926,89 @@ impl Map {
         }
     }
 
-    fn connect_cells(&mut self, start: Coordinates, end: Coordinates) {
+    // Opens a passage between two neighboring cells
+    fn carve(&mut self, from: Coordinates, direction: Direction) -> Coordinates {
+        let mut to = from;
+        self.step(&mut to, direction);
+        self.supplement_cell(from, direction);
+        self.supplement_cell(to, get_opposite_direction(direction));
+        to
+    }
+
+    // The directions in which the given cell has a neighbor not yet part of
+    // the maze
+    fn unvisited_neighbors(&self, cell: Coordinates, visited: &Grid<bool>) -> Vec<Direction> {
+        [NORTH, SOUTH, EAST, WEST]
+            .into_iter()
+            .filter(|direction| {
+                let mut neighbor = cell;
+                self.step(&mut neighbor, *direction) && !visited[neighbor.1][neighbor.0]
+            })
+            .collect()
+    }
+
+    // Depth-first search from the given cell, carving a passage to a random
+    // unvisited neighbor and backtracking at dead ends
+    fn carve_backtracker_maze<R: Rng + ?Sized>(&mut self, from: Coordinates, rng: &mut R) {
+        let mut visited = vec![vec![false; self.width()]; self.height()];
+        visited[from.1][from.0] = true;
+        let mut stack = vec![from];
+        while let Some(&cell) = stack.last() {
+            match self.unvisited_neighbors(cell, &visited).choose(rng) {
+                Some(&direction) => {
+                    let next = self.carve(cell, direction);
+                    visited[next.1][next.0] = true;
+                    stack.push(next);
+                }
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    // Grows the maze from the given cell by repeatedly carving a random
+    // passage from the maze to a cell outside of it
+    fn carve_prims_maze<R: Rng + ?Sized>(&mut self, from: Coordinates, rng: &mut R) {
+        let mut visited = vec![vec![false; self.width()]; self.height()];
+        visited[from.1][from.0] = true;
+        let mut frontier: Vec<(Coordinates, Direction)> = self
+            .unvisited_neighbors(from, &visited)
+            .into_iter()
+            .map(|direction| (from, direction))
+            .collect();
+        while !frontier.is_empty() {
+            let (cell, direction) = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+            let mut next = cell;
+            self.step(&mut next, direction);
+            if visited[next.1][next.0] {
+                continue;
+            }
+            self.carve(cell, direction);
+            visited[next.1][next.0] = true;
+            for direction in self.unvisited_neighbors(next, &visited) {
+                frontier.push((next, direction));
+            }
+        }
+    }
+
+    // Connects two cells with an L-shaped corridor. Diagonal connections
+    // start with a staircase of single steps that players can cut across
+    // diagonally, leaving a straight corridor for the rest of the way.
+    fn connect_cells(&mut self, start: Coordinates, end: Coordinates, diagonal: bool) {
         if start == end {
             return;
         }
+        if diagonal {
+            let mut current = start;
+            while current.0 != end.0 && current.1 != end.1 {
+                let horizontal = if current.0 < end.0 { EAST } else { WEST };
+                let vertical = if current.1 < end.1 { NORTH } else { SOUTH };
+                current = self.carve(current, horizontal);
+                current = self.carve(current, vertical);
+            }
+            return self.connect_cells(current, end, false);
+        }
         let Coordinates(x0, y0) = start;
         let Coordinates(x1, y1) = end;
 
@@ -412,9 +1080,52 @@ impl Map {
         self.starting_points.iter()
     }
 
+    // Whether every player starts on a different cell
+    pub fn starting_positions_unique(&self) -> bool {
+        self.starting_points
+            .iter()
+            .enumerate()
+            .all(|(i, start)| !self.starting_points[i + 1..].contains(start))
+    }
+
+    pub fn goals(&self) -> Iter<'_, Coordinates> {
+        self.goals.iter()
+    }
+
+    /// The number of steps from the given cell to the nearest goal, or `None`
+    /// if no goal can be reached from there or the cell is outside the map
     pub fn distance_to_goal(&self, coordinates: Coordinates) -> Option<usize> {
         let Coordinates(x, y) = coordinates;
-        self.distances[y][x]
+        *self.distances.get(y)?.get(x)?
+    }
+
+    /// Whether a player can cut the corner from the given cell to its
+    /// diagonal neighbor in the given direction. This needs a path around
+    /// the corner through one of the two cells in between.
+    pub fn can_step_diagonally(&self, from: Coordinates, direction: Direction) -> bool {
+        let vertical = direction & LONGITUDINAL;
+        let horizontal = direction & LATITUDINAL;
+        [(vertical, horizontal), (horizontal, vertical)]
+            .into_iter()
+            .any(|(first, second)| {
+                let mut corner = from;
+                let mut to = from;
+                self.exits_of(from) & first != 0
+                    && self.step(&mut corner, first)
+                    && self.exits_of(corner) & second != 0
+                    && self.step(&mut to, direction)
+            })
+    }
+
+    // The directions in which a player can leave the given cell
+    fn exits_of(&self, coordinates: Coordinates) -> Direction {
+        match self.cell_at(coordinates) {
+            GridCell::Wall => 0,
+            GridCell::Path(exits, _)
+            | GridCell::Goal(exits)
+            | GridCell::Teleporter(exits, _)
+            | GridCell::Trap(exits) => *exits,
+        }
     }
 
     pub fn cell_at(&self, coordinates: Coordinates) -> &GridCell {
@@ -426,15 +1137,407 @@ impl Map {
         let Coordinates(x, y) = coordinates;
         &mut self.grid[y][x]
     }
+
+    /// Finds every cell a player could end their move on after taking exactly
+    /// `steps` steps, given the direction of their last step. Players only
+    /// turn back at dead ends, and stepping onto a goal or a trap ends the
+    /// move early. With `diagonal`, players can also cut corners.
+    pub fn reachable_in(
+        &self,
+        start: Coordinates,
+        last_move: Direction,
+        steps: u32,
+        diagonal: bool,
+    ) -> Vec<Coordinates> {
+        let mut endpoints = vec![];
+        let mut frontier = vec![(start, last_move)];
+        for _ in 0..steps {
+            let mut next = vec![];
+            for (cell, last) in frontier {
+                let exits = match self.cell_at(cell) {
+                    GridCell::Path(exits, _)
+                    | GridCell::Teleporter(exits, _)
+                    | GridCell::Trap(exits) => *exits,
+                    _ => 0,
+                };
+                let allowed: Vec<(Direction, Coordinates)> = [NORTH, SOUTH, EAST, WEST]
+                    .into_iter()
+                    .filter(|direction| exits & direction != 0)
+                    .filter_map(|direction| {
+                        let mut to = cell;
+                        self.step(&mut to, direction).then(|| (direction, to))
+                    })
+                    .collect();
+                let forward: Vec<_> = allowed
+                    .iter()
+                    .copied()
+                    .filter(|(direction, _)| {
+                        last == 0 || *direction != get_opposite_direction(last)
+                    })
+                    .collect();
+                let mut moves = if forward.is_empty() { allowed } else { forward };
+                if diagonal {
+                    moves.extend(
+                        [NORTHEAST, NORTHWEST, SOUTHEAST, SOUTHWEST]
+                            .into_iter()
+                            .filter(|direction| self.can_step_diagonally(cell, *direction))
+                            .filter_map(|direction| {
+                                let mut to = cell;
+                                self.step(&mut to, direction).then(|| (direction, to))
+                            }),
+                    );
+                }
+                for (direction, to) in moves {
+                    let to = self.landing_cell(to);
+                    match self.cell_at(to) {
+                        GridCell::Goal(_) | GridCell::Trap(_) => {
+                            if !endpoints.contains(&to) {
+                                endpoints.push(to);
+                            }
+                        }
+                        _ => {
+                            if !next.contains(&(to, direction)) {
+                                next.push((to, direction));
+                            }
+                        }
+                    }
+                }
+            }
+            frontier = next;
+        }
+        for (cell, _) in frontier {
+            if !endpoints.contains(&cell) {
+                endpoints.push(cell);
+            }
+        }
+        endpoints
+    }
+
+    /// Simulates walking from the given position in the given direction for at
+    /// most `steps` tiles, following corridors the same way moving players do.
+    /// The walk stops early at junctions, dead ends and the goal.
+    pub fn corridor_from(
+        &self,
+        start: Coordinates,
+        direction: Direction,
+        steps: u32,
+    ) -> Vec<Coordinates> {
+        let mut tiles = vec![];
+        let mut position = start;
+        // Cutting a corner is a single step that doesn't lead on anywhere
+        if is_diagonal(direction) {
+            if steps > 0
+                && self.can_step_diagonally(start, direction)
+                && self.step(&mut position, direction)
+            {
+                tiles.push(position);
+            }
+            return tiles;
+        }
+        let mut direction = direction;
+        while tiles.len() < steps as usize {
+            match self.cell_at(position) {
+                GridCell::Path(exits, _)
+                | GridCell::Teleporter(exits, _)
+                | GridCell::Trap(exits)
+                    if exits & direction != 0 => {}
+                _ => break,
+            }
+            if !self.step(&mut position, direction) {
+                break;
+            }
+            tiles.push(position);
+            match self.cell_at(position) {
+                GridCell::Path(exits, _) => {
+                    let available = exits & !get_opposite_direction(direction);
+                    match available {
+                        NORTH | SOUTH | EAST | WEST => direction = available,
+                        _ => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+        tiles
+    }
+
+    /// Puts an item on the given cell. Only paths without an item can hold
+    /// one, so the item is handed back for any other cell.
+    pub fn place_item(&mut self, position: Coordinates, item: HeldItem) -> Result<(), HeldItem> {
+        match self.cell_at_mut(position) {
+            GridCell::Path(_, slot) if slot.is_none() => {
+                *slot = Some(item);
+                Ok(())
+            }
+            _ => Err(item),
+        }
+    }
+
+    /// Takes every item off the map
+    pub fn remove_items(&mut self) {
+        for cell in self.grid.iter_mut().flatten() {
+            if let GridCell::Path(_, item) = cell {
+                *item = None;
+            }
+        }
+    }
+
+    pub fn can_place_item(&self, position: Coordinates) -> bool {
+        matches!(self.cell_at(position), GridCell::Path(_, None))
+    }
+
+    /// Removes and returns all items on cells at most `radius` steps away
+    /// (Manhattan distance) from the given coordinates. On toroidal maps the
+    /// distance is measured across the edges.
+    pub fn take_items_within(
+        &mut self,
+        center: Coordinates,
+        radius: usize,
+    ) -> Vec<(Coordinates, HeldItem)> {
+        let radius = radius as isize;
+        let mut items = vec![];
+        for dy in -radius..=radius {
+            let reach = radius - dy.abs();
+            for dx in -reach..=reach {
+                let position = match self.offset(center, dx, dy) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                if let GridCell::Path(_, item) = self.cell_at_mut(position) {
+                    if let Some(item) = item.take() {
+                        items.push((position, item));
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    // The cell the given offset away, wrapping around the edges on toroidal
+    // maps, or `None` if it's off the map
+    fn offset(&self, from: Coordinates, dx: isize, dy: isize) -> Option<Coordinates> {
+        let along = |p: usize, d: isize, side: usize| {
+            let moved = p as isize + d;
+            if self.wraps {
+                Some(moved.rem_euclid(side as isize) as usize)
+            } else {
+                (0..side as isize).contains(&moved).then(|| moved as usize)
+            }
+        };
+        Some(Coordinates(
+            along(from.0, dx, self.width())?,
+            along(from.1, dy, self.height())?,
+        ))
+    }
+}
+
+// Colors used when drawing the map as an image
+const WALL_COLOR: Rgba<u8> = Rgba([40, 40, 40, 255]);
+const PATH_COLOR: Rgba<u8> = Rgba([200, 200, 200, 255]);
+const GOAL_COLOR: Rgba<u8> = Rgba([230, 190, 40, 255]);
+const TELEPORTER_COLOR: Rgba<u8> = Rgba([150, 80, 200, 255]);
+const TRAP_COLOR: Rgba<u8> = Rgba([200, 50, 50, 255]);
+const ITEM_COLOR: Rgba<u8> = Rgba([60, 130, 220, 255]);
+const START_COLOR: Rgba<u8> = Rgba([60, 180, 80, 255]);
+
+impl Map {
+    /// Draws the map with one `tile_px` square per cell. Passable cells are
+    /// drawn as corridors leading to their exits, like the tiles in the game,
+    /// and the top row of the image is the northernmost row of the map.
+    pub fn render_to_image(&self, tile_px: u32) -> RgbaImage {
+        let tile_px = tile_px.max(3);
+        let width = self.width() as u32;
+        let height = self.height() as u32;
+        let mut image = RgbaImage::from_pixel(width * tile_px, height * tile_px, WALL_COLOR);
+        // Corridors take up the middle third of a tile
+        let third = tile_px / 3;
+        let fill = |image: &mut RgbaImage, Coordinates(x, y), (x0, y0, x1, y1), color| {
+            let left = x as u32 * tile_px;
+            let top = (height - 1 - y as u32) * tile_px;
+            for py in top + y0..top + y1 {
+                for px in left + x0..left + x1 {
+                    image.put_pixel(px, py, color);
+                }
+            }
+        };
+        for (position, cell) in self.iter() {
+            let (exits, color) = match cell {
+                GridCell::Wall => continue,
+                GridCell::Path(exits, _) => (*exits, PATH_COLOR),
+                GridCell::Goal(exits) => (*exits, GOAL_COLOR),
+                GridCell::Teleporter(exits, _) => (*exits, TELEPORTER_COLOR),
+                GridCell::Trap(exits) => (*exits, TRAP_COLOR),
+            };
+            let (near, far) = (third, tile_px - third);
+            fill(&mut image, position, (near, near, far, far), color);
+            for (direction, area) in [
+                (NORTH, (near, 0, far, near)),
+                (SOUTH, (near, far, far, tile_px)),
+                (EAST, (far, near, tile_px, far)),
+                (WEST, (0, near, near, far)),
+            ] {
+                if exits & direction != 0 {
+                    fill(&mut image, position, area, color);
+                }
+            }
+            if let GridCell::Path(_, Some(_)) = cell {
+                let (near, far) = (near + third / 3, far - third / 3);
+                fill(&mut image, position, (near, near, far, far), ITEM_COLOR);
+            }
+        }
+        for start in self.starting_points.iter() {
+            let area = (third, third, tile_px - third, tile_px - third);
+            fill(&mut image, *start, area, START_COLOR);
+        }
+        image
+    }
+}
+
+// The parts of a map that are written to map files. The distances to the
+// goals are computed again when the map is loaded.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct MapFile {
+    grid: Grid<GridCell>,
+    goals: Vec<Coordinates>,
+    starting_points: Vec<Coordinates>,
+    #[serde(default)]
+    wraps: bool,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+#[serde(rename = "MapFile")]
+struct MapFileRef<'a> {
+    grid: &'a Grid<GridCell>,
+    goals: &'a [Coordinates],
+    starting_points: &'a [Coordinates],
+    wraps: bool,
+}
+
+#[cfg(feature = "serde")]
+impl Map {
+    /// Writes the layout, items, goals, starting positions and edge wrapping
+    /// of the map to a RON file
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = MapFileRef {
+            grid: &self.grid,
+            goals: &self.goals,
+            starting_points: &self.starting_points,
+            wraps: self.wraps,
+        };
+        let text = ron::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        std::fs::write(path, text)
+    }
+
+    /// Reads a map written by `to_file`. Maps that aren't rectangular, refer
+    /// to cells outside of the grid or can't be solved are rejected.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Map> {
+        let text = std::fs::read_to_string(path)?;
+        let file: MapFile = ron::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Map::from_map_file(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn from_map_file(file: MapFile) -> Result<Map, String> {
+        let MapFile {
+            grid,
+            goals,
+            starting_points,
+            wraps,
+        } = file;
+        let width = grid.first().map_or(0, Vec::len);
+        if width == 0 || grid.iter().any(|row| row.len() != width) {
+            return Err("The map must be a non-empty rectangle".to_string());
+        }
+        let height = grid.len();
+        let in_bounds = |Coordinates(x, y): &Coordinates| *x < width && *y < height;
+        let teleporters_in_bounds = grid.iter().flatten().all(|cell| match cell {
+            GridCell::Teleporter(_, partner) => in_bounds(partner),
+            _ => true,
+        });
+        if !teleporters_in_bounds {
+            return Err("A teleporter leads outside of the map".to_string());
+        }
+        if goals.is_empty()
+            || !goals
+                .iter()
+                .all(|goal| in_bounds(goal) && matches!(grid[goal.1][goal.0], GridCell::Goal(_)))
+        {
+            return Err("The goals don't match the goal cells on the map".to_string());
+        }
+        if starting_points.is_empty()
+            || !starting_points
+                .iter()
+                .all(|start| in_bounds(start) && !matches!(grid[start.1][start.0], GridCell::Wall))
+        {
+            return Err("Every starting position must be on a path".to_string());
+        }
+        let mut map = Map {
+            distances: vec![vec![None; width]; height],
+            grid,
+            goals,
+            starting_points,
+            wraps,
+        };
+        map.compute_distances();
+        if !map.is_solvable() {
+            return Err("The goal can't be reached from every starting position".to_string());
+        }
+        Ok(map)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::map::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    #[derive(Default)]
+    struct EventRecorder(Arc<Mutex<Vec<String>>>);
+
+    struct MessageVisitor(String);
+
+    impl Visit for MessageVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl Subscriber for EventRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            if *event.metadata().level() == Level::INFO {
+                let mut visitor = MessageVisitor(String::new());
+                event.record(&mut visitor);
+                self.0.lock().unwrap().push(visitor.0);
+            }
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
 
     #[test]
     fn generate_map() {
-        let map = Map::generate_random_map(10, 10, 3, 0., 5);
+        let map = Map::generate_random_map(10, 10, 3, 0., 5, 6, 0., 0., 1, MapGenerator::Corridors);
         let mut render = [[' '; 10]; 10];
         for (position, cell) in map.iter() {
             let Coordinates(x, y) = position;
@@ -457,6 +1560,8 @@ mod tests {
                     _ => '?',
                 },
                 GridCell::Goal(_) => '*',
+                GridCell::Teleporter(..) => '@',
+                GridCell::Trap(_) => 'x',
             };
         }
 
@@ -493,4 +1598,699 @@ mod tests {
             });
         println!("@@@@@@@@@@@@\n{}@@@@@@@@@@@@", rendered);
     }
+
+    #[test]
+    fn travel_distance_is_clamped() {
+        let map =
+            Map::generate_random_map(10, 10, 3, 0., 100, 6, 0., 0., 1, MapGenerator::Corridors);
+        let Coordinates(gx, gy) = map.goals[0];
+        let max_distance = map.max_distance_from(map.goals[0]);
+        assert!(max_distance <= 18);
+        assert_eq!(map.starting_points.len(), 3);
+        for Coordinates(x, y) in map.starting_positions() {
+            let distance = gx.max(*x) - gx.min(*x) + gy.max(*y) - gy.min(*y);
+            assert_eq!(distance, max_distance);
+        }
+
+        // Players never start on the goal, even on the smallest map
+        let map = Map::generate_random_map(2, 2, 1, 0., 0, 6, 0., 0., 1, MapGenerator::Corridors);
+        let Coordinates(gx, gy) = map.goals[0];
+        let Coordinates(x, y) = map.starting_points[0];
+        assert_eq!(gx.max(x) - gx.min(x) + gy.max(y) - gy.min(y), 1);
+    }
+
+    #[test]
+    fn generation_events() {
+        let recorder = EventRecorder::default();
+        let events = recorder.0.clone();
+        tracing::subscriber::with_default(recorder, || {
+            Map::generate_random_map(10, 10, 3, 0.1, 5, 6, 0., 0., 1, MapGenerator::Corridors);
+        });
+        assert_eq!(*events.lock().unwrap(), vec!["Generated map".to_string()]);
+    }
+
+    #[test]
+    fn no_items_on_start_or_goal() {
+        for _ in 0..200 {
+            let map =
+                Map::generate_random_map(20, 20, 4, 0.8, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+            for start in map.starting_positions() {
+                assert!(!matches!(map.cell_at(*start), GridCell::Path(_, Some(_))));
+            }
+            assert!(matches!(map.cell_at(map.goals[0]), GridCell::Goal(_)));
+        }
+    }
+
+    #[test]
+    fn pick_up_adjacent_items() {
+        let mut map =
+            Map::generate_random_map(20, 20, 2, 0.3, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        let (item_pos, neighbor) = map
+            .iter()
+            .find_map(|(pos, cell)| match cell {
+                GridCell::Path(exits, Some(_)) => {
+                    let mut neighbor = pos;
+                    for direction in [NORTH, SOUTH, EAST, WEST] {
+                        if exits & direction != 0 {
+                            neighbor.step(direction, map.width(), map.height());
+                            break;
+                        }
+                    }
+                    Some((pos, neighbor))
+                }
+                _ => None,
+            })
+            .expect("No items on map");
+        assert!(item_pos != neighbor);
+
+        // Only the exact tile is checked with radius 0
+        let collected = map.take_items_within(neighbor, 0);
+        assert!(collected.iter().all(|(pos, _)| *pos == neighbor));
+        assert!(matches!(map.cell_at(item_pos), GridCell::Path(_, Some(_))));
+
+        let collected = map.take_items_within(neighbor, 1);
+        assert!(collected.iter().any(|(pos, _)| *pos == item_pos));
+        assert!(matches!(map.cell_at(item_pos), GridCell::Path(_, None)));
+    }
+
+    #[test]
+    fn corridor_stops_at_junction() {
+        let map = Map {
+            grid: vec![
+                vec![
+                    GridCell::Path(EAST, None),
+                    GridCell::Path(LATITUDINAL, None),
+                    GridCell::Path(NORTHWEST, None),
+                    GridCell::Wall,
+                ],
+                vec![
+                    GridCell::Wall,
+                    GridCell::Wall,
+                    GridCell::Path(LONGITUDINAL | EAST, None),
+                    GridCell::Path(WEST, None),
+                ],
+                vec![
+                    GridCell::Wall,
+                    GridCell::Wall,
+                    GridCell::Goal(SOUTH),
+                    GridCell::Wall,
+                ],
+            ],
+            distances: vec![vec![None; 4]; 3],
+            goals: vec![Coordinates(2, 2)],
+            starting_points: vec![Coordinates(0, 0)],
+            wraps: false,
+        };
+        assert!(
+            map.corridor_from(Coordinates(0, 0), EAST, 5)
+                == vec![Coordinates(1, 0), Coordinates(2, 0), Coordinates(2, 1)]
+        );
+        assert!(
+            map.corridor_from(Coordinates(0, 0), EAST, 2)
+                == vec![Coordinates(1, 0), Coordinates(2, 0)]
+        );
+        assert!(map.corridor_from(Coordinates(2, 1), NORTH, 3) == vec![Coordinates(2, 2)]);
+        assert!(map.corridor_from(Coordinates(0, 0), NORTH, 3).is_empty());
+    }
+
+    #[test]
+    fn corrupted_exits_are_repaired() {
+        let mut map =
+            Map::generate_random_map(20, 20, 2, 0.1, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        assert_eq!(map.repair_exits(), 0);
+
+        // Add an exit leading into a wall or off the map
+        let (position, original, corrupted) = map
+            .iter()
+            .find_map(|(pos, cell)| match cell {
+                GridCell::Path(exits, _) => [NORTH, SOUTH, EAST, WEST]
+                    .into_iter()
+                    .find(|direction| {
+                        let mut neighbor = pos;
+                        exits & direction == 0
+                            && (!neighbor.step(*direction, map.width(), map.height())
+                                || matches!(map.cell_at(neighbor), GridCell::Wall))
+                    })
+                    .map(|direction| (pos, *exits, *exits | direction)),
+                _ => None,
+            })
+            .expect("No path cell next to a wall");
+        if let GridCell::Path(exits, _) = map.cell_at_mut(position) {
+            *exits = corrupted;
+        }
+
+        assert_eq!(map.repair_exits(), 1);
+        assert!(matches!(map.cell_at(position), GridCell::Path(exits, _) if *exits == original));
+    }
+
+    #[test]
+    fn distances_on_hand_built_map() {
+        let map = Map::from_grid(vec![
+            vec![
+                GridCell::Goal(EAST),
+                GridCell::Path(WEST | EAST, None),
+                GridCell::Path(WEST | NORTH, None),
+            ],
+            vec![GridCell::Wall, GridCell::Wall, GridCell::Path(SOUTH, None)],
+            vec![
+                GridCell::Path(EAST, None),
+                GridCell::Path(WEST, None),
+                GridCell::Wall,
+            ],
+        ]);
+        assert_eq!(map.distance_to_goal(Coordinates(0, 0)), Some(0));
+        assert_eq!(map.distance_to_goal(Coordinates(1, 0)), Some(1));
+        assert_eq!(map.distance_to_goal(Coordinates(2, 1)), Some(3));
+        // Walls, disconnected paths and cells outside the map have no distance
+        assert_eq!(map.distance_to_goal(Coordinates(1, 1)), None);
+        assert_eq!(map.distance_to_goal(Coordinates(0, 2)), None);
+        assert_eq!(map.distance_to_goal(Coordinates(5, 5)), None);
+    }
+
+    #[test]
+    fn teleporters_are_paired() {
+        let map =
+            Map::generate_random_map(20, 20, 2, 0.1, 10, 6, 0.1, 0., 1, MapGenerator::Corridors);
+        let teleporters: Vec<(Coordinates, Coordinates)> = map
+            .iter()
+            .filter_map(|(pos, cell)| match cell {
+                GridCell::Teleporter(_, partner) => Some((pos, *partner)),
+                _ => None,
+            })
+            .collect();
+        assert!(!teleporters.is_empty());
+        for (pos, partner) in teleporters {
+            assert!(pos != partner);
+            assert!(!map.starting_points.contains(&pos));
+            assert!(matches!(map.cell_at(partner), GridCell::Teleporter(_, back) if *back == pos));
+        }
+    }
+
+    #[test]
+    fn distances_through_teleporters() {
+        // The left corridor can only reach the goal through the teleporters
+        let map = Map::from_grid(vec![
+            vec![
+                GridCell::Path(NORTH, None),
+                GridCell::Wall,
+                GridCell::Goal(NORTH),
+            ],
+            vec![
+                GridCell::Teleporter(SOUTH, Coordinates(2, 1)),
+                GridCell::Wall,
+                GridCell::Teleporter(SOUTH, Coordinates(0, 1)),
+            ],
+        ]);
+        assert_eq!(map.landing_cell(Coordinates(0, 1)), Coordinates(2, 1));
+        assert_eq!(map.landing_cell(Coordinates(0, 0)), Coordinates(0, 0));
+        // Players on the left teleporter arrived from the right one, so they
+        // have to step off and back on to return
+        assert_eq!(map.distance_to_goal(Coordinates(2, 1)), Some(1));
+        assert_eq!(map.distance_to_goal(Coordinates(0, 0)), Some(2));
+        assert_eq!(map.distance_to_goal(Coordinates(0, 1)), Some(3));
+    }
+
+    #[test]
+    fn trap_density() {
+        let map =
+            Map::generate_random_map(20, 20, 2, 0., 10, 6, 0., 0.02, 1, MapGenerator::Corridors);
+        let traps = map
+            .iter()
+            .filter(|(_, cell)| matches!(cell, GridCell::Trap(_)))
+            .count();
+        // Paths to the starting positions are long enough to hold every trap
+        assert_eq!(traps, 8);
+        for start in map.starting_positions() {
+            assert!(!matches!(map.cell_at(*start), GridCell::Trap(_)));
+        }
+
+        let map =
+            Map::generate_random_map(20, 20, 2, 0., 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        assert!(map
+            .iter()
+            .all(|(_, cell)| !matches!(cell, GridCell::Trap(_))));
+    }
+
+    #[test]
+    fn distance_to_nearest_goal() {
+        let map = Map::from_grid(vec![vec![
+            GridCell::Goal(EAST),
+            GridCell::Path(LATITUDINAL, None),
+            GridCell::Path(LATITUDINAL, None),
+            GridCell::Path(LATITUDINAL, None),
+            GridCell::Goal(WEST),
+        ]]);
+        assert_eq!(map.distance_to_goal(Coordinates(1, 0)), Some(1));
+        assert_eq!(map.distance_to_goal(Coordinates(2, 0)), Some(2));
+        assert_eq!(map.distance_to_goal(Coordinates(3, 0)), Some(1));
+        assert_eq!(map.nearest_goal(Coordinates(3, 0)), Coordinates(4, 0));
+    }
+
+    #[test]
+    fn every_goal_is_reachable() {
+        for _ in 0..50 {
+            let map =
+                Map::generate_random_map(20, 20, 3, 0.1, 10, 6, 0., 0., 3, MapGenerator::Corridors);
+            assert_eq!(map.goals.len(), 3);
+            for goal in &map.goals {
+                assert!(matches!(map.cell_at(*goal), GridCell::Goal(_)));
+                assert!(!map.starting_points.contains(goal));
+            }
+            for start in map.starting_positions() {
+                assert!(map.distance_to_goal(*start).is_some());
+            }
+        }
+    }
+
+    fn describe(map: &Map) -> Vec<String> {
+        map.iter()
+            .map(|(Coordinates(x, y), cell)| match cell {
+                GridCell::Wall => format!("{},{} wall", x, y),
+                GridCell::Path(exits, item) => format!(
+                    "{},{} path {} {}",
+                    x,
+                    y,
+                    exits,
+                    item.as_ref().map_or("", |item| item.full_description())
+                ),
+                GridCell::Goal(exits) => format!("{},{} goal {}", x, y, exits),
+                GridCell::Teleporter(exits, Coordinates(tx, ty)) => {
+                    format!("{},{} teleporter {} {},{}", x, y, exits, tx, ty)
+                }
+                GridCell::Trap(exits) => format!("{},{} trap {}", x, y, exits),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeded_maps_are_reproducible() {
+        let generate = |seed| {
+            Map::generate_random_map_seeded(
+                30,
+                30,
+                3,
+                0.3,
+                0.,
+                15,
+                6,
+                0.02,
+                0.02,
+                2,
+                MapGenerator::Corridors,
+                seed,
+            )
+        };
+        let first = generate(42);
+        let second = generate(42);
+        assert_eq!(describe(&first), describe(&second));
+        assert!(first.starting_points == second.starting_points);
+        assert_ne!(describe(&first), describe(&generate(43)));
+    }
+
+    #[test]
+    fn generated_maps_are_solvable() {
+        for _ in 0..200 {
+            let map = Map::generate_random_map(
+                30,
+                30,
+                4,
+                0.2,
+                20,
+                6,
+                0.02,
+                0.02,
+                2,
+                MapGenerator::Corridors,
+            );
+            assert!(map.is_solvable());
+        }
+    }
+
+    #[test]
+    fn boxed_in_start_is_not_solvable() {
+        let mut grid = vec![];
+        for _ in 0..3 {
+            grid.push((0..3).map(|_| GridCell::Wall).collect::<Vec<_>>());
+        }
+        grid[0][0] = GridCell::Path(EAST, None);
+        grid[0][1] = GridCell::Path(WEST, None);
+        grid[2][2] = GridCell::Goal(0);
+        let mut map = Map::from_grid(grid);
+        map.starting_points.push(Coordinates(0, 0));
+        assert!(!map.is_solvable());
+
+        map.set_cell(Coordinates(1, 0), GridCell::Path(WEST | NORTH, None));
+        map.set_cell(Coordinates(1, 1), GridCell::Path(SOUTH | EAST, None));
+        map.set_cell(Coordinates(2, 1), GridCell::Path(WEST | NORTH, None));
+        assert!(map.is_solvable());
+    }
+
+    #[test]
+    fn mazes_cover_the_map() {
+        for generator in [MapGenerator::RecursiveBacktracker, MapGenerator::Prims] {
+            for _ in 0..20 {
+                let map = Map::generate_random_map(20, 15, 3, 0.2, 10, 6, 0., 0., 2, generator);
+                assert!(map.is_solvable());
+                // A perfect maze is a spanning tree of the grid, so it has one
+                // passage fewer than it has cells
+                let mut exits = 0;
+                let mut items = 0;
+                for (_, cell) in map.iter() {
+                    match cell {
+                        GridCell::Wall => panic!("Maze left a wall"),
+                        GridCell::Path(directions, item) => {
+                            exits += directions.count_ones();
+                            items += item.is_some() as usize;
+                        }
+                        GridCell::Goal(directions)
+                        | GridCell::Teleporter(directions, _)
+                        | GridCell::Trap(directions) => exits += directions.count_ones(),
+                    }
+                }
+                assert_eq!(exits / 2, 20 * 15 - 1);
+                assert!(items > 0);
+                assert!(map
+                    .iter()
+                    .all(|(position, _)| map.distance_to_goal(position).is_some()));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn map_survives_saving() {
+        let map = Map::generate_random_map(
+            20,
+            20,
+            2,
+            0.3,
+            10,
+            6,
+            0.02,
+            0.02,
+            2,
+            MapGenerator::Corridors,
+        );
+        let loaded: Map = ron::from_str(&ron::to_string(&map).unwrap()).unwrap();
+        assert_eq!(describe(&loaded), describe(&map));
+        assert!(loaded.distances == map.distances);
+        assert!(loaded.starting_points == map.starting_points);
+        assert!(!loaded.wraps());
+
+        let mut toroidal = map;
+        toroidal.make_toroidal();
+        toroidal.to_file(&path).unwrap();
+        let loaded = Map::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.wraps());
+        assert!(loaded.distances == toroidal.distances);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn map_file_round_trip() {
+        let map = Map::generate_random_map(
+            20,
+            20,
+            2,
+            0.3,
+            10,
+            6,
+            0.02,
+            0.02,
+            1,
+            MapGenerator::Corridors,
+        );
+        let path = std::env::temp_dir().join("zinkd_map_file_round_trip.ron");
+        map.to_file(&path).unwrap();
+        let loaded = Map::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(describe(&loaded), describe(&map));
+        assert!(loaded.distances == map.distances);
+        assert!(loaded.starting_points == map.starting_points);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn invalid_map_files_are_rejected() {
+        let file = |grid, starting_points| MapFile {
+            grid,
+            goals: vec![Coordinates(1, 0)],
+            starting_points,
+            wraps: false,
+        };
+        let ragged = vec![
+            vec![GridCell::Path(EAST, None), GridCell::Goal(WEST)],
+            vec![GridCell::Wall],
+        ];
+        assert!(Map::from_map_file(file(ragged, vec![Coordinates(0, 0)])).is_err());
+
+        let walled_off = vec![vec![GridCell::Path(0, None), GridCell::Goal(0)]];
+        assert!(Map::from_map_file(file(walled_off, vec![Coordinates(0, 0)])).is_err());
+
+        let connected = || vec![vec![GridCell::Path(EAST, None), GridCell::Goal(WEST)]];
+        assert!(Map::from_map_file(file(connected(), vec![Coordinates(5, 0)])).is_err());
+        assert!(Map::from_map_file(file(connected(), vec![Coordinates(0, 0)])).is_ok());
+    }
+
+    #[test]
+    fn reachable_cells_for_roll() {
+        let map = Map::from_grid(vec![
+            vec![GridCell::Wall, GridCell::Path(NORTH, None), GridCell::Wall],
+            vec![
+                GridCell::Path(EAST, None),
+                GridCell::Path(OMNIDIRECTIONAL, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![GridCell::Wall, GridCell::Goal(SOUTH), GridCell::Wall],
+        ]);
+        let start = Coordinates(1, 0);
+        assert!(map.reachable_in(start, 0, 1, false) == vec![Coordinates(1, 1)]);
+
+        let reachable = map.reachable_in(start, 0, 2, false);
+        assert_eq!(reachable.len(), 3);
+        for cell in [Coordinates(1, 2), Coordinates(0, 1), Coordinates(2, 1)] {
+            assert!(reachable.contains(&cell));
+        }
+        // Players never turn straight back at the junction, so they can only
+        // return there after the dead ends
+        let reachable = map.reachable_in(start, 0, 3, false);
+        assert_eq!(reachable.len(), 2);
+        assert!(reachable.contains(&Coordinates(1, 2)));
+        assert!(reachable.contains(&Coordinates(1, 1)));
+
+        // Cutting the corners around the junction reaches its side passages
+        // in a single step
+        let reachable = map.reachable_in(start, 0, 1, true);
+        assert_eq!(reachable.len(), 3);
+        for cell in [Coordinates(1, 1), Coordinates(0, 1), Coordinates(2, 1)] {
+            assert!(reachable.contains(&cell));
+        }
+    }
+
+    #[test]
+    fn tiny_maps_are_still_generated() {
+        for generator in MAP_GENERATORS {
+            let map = Map::generate_random_map(3, 3, 6, 0.5, 10, 6, 0.1, 0.1, 2, generator);
+            assert_eq!(map.starting_points.len(), 6);
+            for start in map.starting_positions() {
+                assert!(!map.goals.contains(start));
+            }
+        }
+
+        // Degenerate settings are clamped to the smallest playable map
+        let map = Map::generate_random_map(0, 1, 0, 0.5, 0, 6, 0., 0., 1, MapGenerator::Corridors);
+        assert_eq!((map.width(), map.height()), (MIN_MAP_SIDE, MIN_MAP_SIDE));
+        assert_eq!(map.starting_points.len(), 1);
+        assert!(map.is_solvable());
+    }
+
+    #[test]
+    fn six_players_spawn_apart() {
+        for generator in MAP_GENERATORS {
+            // Only four cells are a single step from the goal
+            for distance in [1, 3, 10] {
+                let map =
+                    Map::generate_random_map(12, 12, 6, 0.1, distance, 6, 0., 0., 1, generator);
+                assert_eq!(map.starting_points.len(), 6);
+                assert!(map.starting_positions_unique());
+                for start in map.starting_positions() {
+                    assert!(!map.goals.contains(start));
+                }
+            }
+        }
+
+        let mut map = Map::generate_random_map(5, 5, 2, 0., 2, 6, 0., 0., 1, MapGenerator::Prims);
+        map.starting_points.push(map.starting_points[0]);
+        assert!(!map.starting_positions_unique());
+    }
+
+    #[test]
+    fn staircases_can_be_crossed_diagonally() {
+        let mut corner = Coordinates(0, 0);
+        assert!(corner.step(NORTHEAST, 2, 2));
+        assert!(corner == Coordinates(1, 1));
+        assert!(!corner.step(NORTHEAST, 2, 2));
+        assert!(corner == Coordinates(1, 1));
+
+        let mut map =
+            Map::generate_random_map(5, 5, 1, 0., 4, 6, 0., 0., 1, MapGenerator::Corridors);
+        for row in map.grid.iter_mut() {
+            row.fill_with(|| GridCell::Wall);
+        }
+        map.connect_cells(Coordinates(0, 0), Coordinates(3, 4), true);
+        let mut position = Coordinates(0, 0);
+        for _ in 0..3 {
+            assert!(map.can_step_diagonally(position, NORTHEAST));
+            position.step(NORTHEAST, map.width(), map.height());
+        }
+        assert!(position == Coordinates(3, 3));
+        assert!(!map.can_step_diagonally(position, NORTHEAST));
+        assert!(matches!(
+            map.cell_at(Coordinates(3, 4)),
+            GridCell::Path(SOUTH, _)
+        ));
+    }
+
+    #[test]
+    fn items_can_be_removed() {
+        let mut map =
+            Map::generate_random_map(20, 20, 2, 0.5, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        assert!(map
+            .iter()
+            .any(|(_, cell)| matches!(cell, GridCell::Path(_, Some(_)))));
+        map.remove_items();
+        assert!(!map
+            .iter()
+            .any(|(_, cell)| matches!(cell, GridCell::Path(_, Some(_)))));
+
+        let map =
+            Map::generate_random_map(20, 20, 2, 0., 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        assert!(!map
+            .iter()
+            .any(|(_, cell)| matches!(cell, GridCell::Path(_, Some(_)))));
+    }
+
+    #[test]
+    fn steps_wrap_around_edges() {
+        let (width, height) = (3, 4);
+        let wrapped = |from: Coordinates, direction: Direction| {
+            let mut position = from;
+            assert!(position.step_wrapping(direction, width, height));
+            position
+        };
+        assert!(wrapped(Coordinates(1, 3), NORTH) == Coordinates(1, 0));
+        assert!(wrapped(Coordinates(1, 0), SOUTH) == Coordinates(1, 3));
+        assert!(wrapped(Coordinates(2, 1), EAST) == Coordinates(0, 1));
+        assert!(wrapped(Coordinates(0, 1), WEST) == Coordinates(2, 1));
+        assert!(wrapped(Coordinates(2, 3), NORTHEAST) == Coordinates(0, 0));
+        assert!(wrapped(Coordinates(1, 1), NORTH) == Coordinates(1, 2));
+    }
+
+    #[test]
+    fn toroidal_maps_connect_edges() {
+        let mut map = Map::from_grid(vec![
+            vec![
+                GridCell::Goal(EAST),
+                GridCell::Path(LATITUDINAL, None),
+                GridCell::Path(LATITUDINAL, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![
+                GridCell::Wall,
+                GridCell::Wall,
+                GridCell::Wall,
+                GridCell::Wall,
+            ],
+        ]);
+        let far_end = Coordinates(3, 0);
+        assert_eq!(map.distance_to_goal(far_end), Some(3));
+        assert_eq!(map.grid_distance(far_end, Coordinates(0, 0)), 3);
+        let mut position = far_end;
+        assert!(!map.step(&mut position, EAST));
+
+        map.make_toroidal();
+        assert!(map.wraps());
+        assert_eq!(map.distance_to_goal(far_end), Some(1));
+        assert_eq!(map.grid_distance(far_end, Coordinates(0, 0)), 1);
+        assert!(map.step(&mut position, EAST));
+        assert!(position == Coordinates(0, 0));
+        // Walls on either side of an edge keep it closed
+        assert!(matches!(
+            map.cell_at(Coordinates(0, 0)),
+            GridCell::Goal(LATITUDINAL)
+        ));
+    }
+
+    #[test]
+    fn items_are_picked_up_across_the_seam() {
+        let mut map = Map::from_grid(vec![
+            vec![
+                GridCell::Goal(EAST),
+                GridCell::Path(LATITUDINAL, None),
+                GridCell::Path(LATITUDINAL, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![
+                GridCell::Wall,
+                GridCell::Wall,
+                GridCell::Wall,
+                GridCell::Wall,
+            ],
+        ]);
+        let across = Coordinates(3, 0);
+        let item = items::random_item(6, &mut rand::thread_rng());
+        assert!(map.place_item(across, item).is_ok());
+        assert!(map.take_items_within(Coordinates(0, 0), 1).is_empty());
+
+        map.make_toroidal();
+        let collected = map.take_items_within(Coordinates(0, 0), 1);
+        assert_eq!(collected.len(), 1);
+        assert!(collected[0].0 == across);
+    }
+
+    #[test]
+    fn opposite_directions() {
+        for (a, b) in [(NORTH, SOUTH), (SOUTH, NORTH), (EAST, WEST), (WEST, EAST)] {
+            assert!(directions_are_opposite(a, b));
+            assert!(!directions_are_opposite(a, a));
+        }
+        assert!(!directions_are_opposite(NORTH, EAST));
+        assert!(!directions_are_opposite(0, NORTH));
+        assert!(!directions_are_opposite(NORTH, 0));
+        assert!(!directions_are_opposite(LONGITUDINAL, SOUTH));
+        assert!(!directions_are_opposite(NORTH, LONGITUDINAL));
+        assert!(!directions_are_opposite(LONGITUDINAL, LATITUDINAL));
+        assert!(directions_are_opposite(NORTHEAST, SOUTHWEST));
+        assert!(directions_are_opposite(SOUTHEAST, NORTHWEST));
+        assert!(!directions_are_opposite(NORTHEAST, NORTHWEST));
+        assert_eq!(get_opposite_direction(LONGITUDINAL), 0);
+        assert_eq!(get_opposite_direction(0), 0);
+    }
+
+    #[test]
+    fn render_map_image() {
+        let mut map = Map::from_grid(vec![
+            vec![GridCell::Wall, GridCell::Path(NORTH, None), GridCell::Wall],
+            vec![
+                GridCell::Path(EAST, None),
+                GridCell::Path(OMNIDIRECTIONAL, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![GridCell::Wall, GridCell::Goal(SOUTH), GridCell::Wall],
+        ]);
+        map.starting_points = vec![Coordinates(0, 1)];
+        let image = map.render_to_image(9);
+        assert_eq!(image.dimensions(), (27, 27));
+        // The bottom row of the map is drawn at the bottom of the image
+        assert_eq!(*image.get_pixel(4, 22), WALL_COLOR);
+        assert_eq!(*image.get_pixel(13, 22), PATH_COLOR);
+        assert_eq!(*image.get_pixel(13, 19), PATH_COLOR);
+        assert_eq!(*image.get_pixel(13, 25), WALL_COLOR);
+        assert_eq!(*image.get_pixel(13, 4), GOAL_COLOR);
+        assert_eq!(*image.get_pixel(13, 7), GOAL_COLOR);
+        assert_eq!(*image.get_pixel(13, 1), WALL_COLOR);
+        assert_eq!(*image.get_pixel(4, 13), START_COLOR);
+        assert_eq!(*image.get_pixel(7, 13), PATH_COLOR);
+    }
 }
@@ -32,28 +32,45 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::map::{Coordinates, Direction, GridCell, Map, EAST, NORTH, SOUTH, WEST};
+use crate::map::{
+    get_opposite_direction, Coordinates, Direction, GridCell, Map, EAST, NORTH, SOUTH, WEST,
+};
 use crate::player::Player;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::{Display, Formatter};
+use tracing::debug;
 
 #[derive(Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum MoveAlgorithm {
     ShortestPath,
+    AStar,
+    RandomWalk,
 }
-pub const MOVE_ALGORITHMS: [MoveAlgorithm; 1] = [MoveAlgorithm::ShortestPath];
+pub const MOVE_ALGORITHMS: [MoveAlgorithm; 3] = [
+    MoveAlgorithm::ShortestPath,
+    MoveAlgorithm::AStar,
+    MoveAlgorithm::RandomWalk,
+];
 
 #[derive(Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum ItemAlgorithm {
     HighestGain,
+    Sabotage,
 }
-pub const ITEM_ALGORITHMS: [ItemAlgorithm; 1] = [ItemAlgorithm::HighestGain];
+pub const ITEM_ALGORITHMS: [ItemAlgorithm; 2] =
+    [ItemAlgorithm::HighestGain, ItemAlgorithm::Sabotage];
 
 impl Display for MoveAlgorithm {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             MoveAlgorithm::ShortestPath => write!(f, "Shortest Path"),
+            MoveAlgorithm::AStar => write!(f, "A*"),
+            MoveAlgorithm::RandomWalk => write!(f, "Random Walk"),
         }
     }
 }
@@ -62,23 +79,115 @@ impl Display for ItemAlgorithm {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ItemAlgorithm::HighestGain => write!(f, "Highest gain"),
+            ItemAlgorithm::Sabotage => write!(f, "Sabotage"),
         }
     }
 }
 
 impl MoveAlgorithm {
-    pub fn compute_move(&self, start: Coordinates, map: &Map) -> Direction {
+    pub fn description(&self) -> &'static str {
         match self {
-            MoveAlgorithm::ShortestPath => shortest_path(start, map),
+            MoveAlgorithm::ShortestPath => {
+                "Always heads towards the goal along the shortest route, \
+                preferring to keep going straight when several routes are \
+                equally short. Hard to beat."
+            }
+            MoveAlgorithm::AStar => {
+                "Plans its whole route to the goal at the start of each turn \
+                and sticks to it, never wandering into dead ends. Hard to beat."
+            }
+            MoveAlgorithm::RandomWalk => {
+                "Wanders the map at random, only turning back at dead ends. \
+                Easy to beat."
+            }
+        }
+    }
+
+    /// Decides the next step for a player at the given position. Algorithms
+    /// which plan ahead keep their plan in `route` between steps.
+    pub fn compute_move<R: Rng + ?Sized>(
+        &self,
+        start: Coordinates,
+        last_move: Direction,
+        map: &Map,
+        route: &mut PlannedRoute,
+        rng: &mut R,
+    ) -> Direction {
+        match self {
+            MoveAlgorithm::ShortestPath => shortest_path(start, last_move, map, rng),
+            MoveAlgorithm::AStar => {
+                if let Some(direction) = route.next_move(start) {
+                    return direction;
+                }
+                *route = a_star(start, map);
+                let best_direction = route.next_move(start).unwrap_or(0);
+                debug!(best_direction, remaining = route.len(), "Planned route");
+                best_direction
+            }
+            MoveAlgorithm::RandomWalk => random_walk(start, last_move, map, rng),
         }
     }
 }
 
 impl ItemAlgorithm {
-    pub fn choose_item(&self, user: &Player, players: &[Player]) -> Option<(usize, usize)> {
+    pub fn description(&self) -> &'static str {
+        match self {
+            ItemAlgorithm::HighestGain => {
+                "Uses whichever item most improves its own die, and only on \
+                itself. Never targets other players."
+            }
+            ItemAlgorithm::Sabotage => {
+                "Uses whichever item most worsens the die of the opponent \
+                closest to the goal. Never uses items on itself."
+            }
+        }
+    }
+
+    pub fn choose_item(
+        &self,
+        user: &Player,
+        players: &[Player],
+        map: &Map,
+    ) -> Option<(usize, usize)> {
         match self {
             ItemAlgorithm::HighestGain => highest_self_benefit(user, players),
+            ItemAlgorithm::Sabotage => sabotage_leader(user, players, map),
+        }
+    }
+}
+
+/// A sequence of moves planned by a computer player, along with the cell each
+/// move is expected to lead to
+#[derive(Default)]
+pub struct PlannedRoute {
+    from: Option<Coordinates>,
+    moves: VecDeque<(Direction, Coordinates)>,
+}
+
+impl PlannedRoute {
+    /// Discards the plan so that the next move computes a new one
+    pub fn clear(&mut self) {
+        self.from = None;
+        self.moves.clear();
+    }
+
+    /// The number of moves left in the plan
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    // The plan is only followed if the player is where it expects them to be
+    fn next_move(&mut self, position: Coordinates) -> Option<Direction> {
+        if self.from != Some(position) {
+            return None;
         }
+        let (direction, to) = self.moves.pop_front()?;
+        self.from = Some(to);
+        Some(direction)
     }
 }
 
@@ -93,31 +202,436 @@ pub fn highest_self_benefit(user: &Player, _players: &[Player]) -> Option<(usize
             best_item = Some(i);
         }
     }
+    debug!(
+        player = user.player_number(),
+        ?best_item,
+        max_gain,
+        "Chose item"
+    );
     match best_item {
         None => None,
         Some(idx) => Some((idx, user.player_number())),
     }
 }
 
+/// Lists the player numbers of the user's opponents who have yet to reach a
+/// goal, closest to a goal first. Opponents who can't reach a goal at all
+/// are left out.
+pub fn opponents_by_distance(user: &Player, players: &[Player], map: &Map) -> Vec<usize> {
+    let mut opponents: Vec<(usize, usize)> = players
+        .iter()
+        .filter(|player| player.team() != user.team())
+        .filter_map(|player| match map.distance_to_goal(player.position()) {
+            Some(0) | None => None,
+            Some(distance) => Some((distance, player.player_number())),
+        })
+        .collect();
+    opponents.sort();
+    opponents.into_iter().map(|(_, number)| number).collect()
+}
+
+// Uses the most harmful item on the leading opponent. Reflecting opponents are
+// left alone since the item would come straight back.
+pub fn sabotage_leader(user: &Player, players: &[Player], map: &Map) -> Option<(usize, usize)> {
+    let leader = *opponents_by_distance(user, players, map).first()?;
+    let target = &players[leader];
+    if target.is_reflecting() {
+        debug!(
+            player = user.player_number(),
+            leader, "Leader is reflecting"
+        );
+        return None;
+    }
+    let mut best_item = None;
+    let mut min_benefit = 0.;
+    for (i, item) in user.items().enumerate() {
+        let benefit = item.item_benefit(target);
+        if benefit < min_benefit {
+            min_benefit = benefit;
+            best_item = Some(i);
+        }
+    }
+    debug!(
+        player = user.player_number(),
+        leader,
+        ?best_item,
+        min_benefit,
+        "Chose item"
+    );
+    best_item.map(|idx| (idx, leader))
+}
+
 // Path computations
-fn shortest_path(start: Coordinates, map: &Map) -> Direction {
+
+/// Picks one of several equally good directions. Continuing in the current
+/// direction of travel is preferred so that computer players don't jitter
+/// between paths; otherwise the choice is random to avoid favoring any
+/// particular direction.
+pub fn break_tie<R: Rng + ?Sized>(
+    candidates: &[Direction],
+    last_move: Direction,
+    rng: &mut R,
+) -> Direction {
+    if candidates.contains(&last_move) {
+        last_move
+    } else {
+        *candidates.choose(rng).unwrap_or(&0)
+    }
+}
+
+fn shortest_path<R: Rng + ?Sized>(
+    start: Coordinates,
+    last_move: Direction,
+    map: &Map,
+    rng: &mut R,
+) -> Direction {
     let mut min_distance = usize::MAX;
-    let mut best_direction = 0;
+    let mut candidates = vec![];
     let exits = match map.cell_at(start) {
         GridCell::Wall => panic!("Cannot navigate from inside a wall"),
-        GridCell::Path(directions, _) => *directions,
+        GridCell::Path(directions, _)
+        | GridCell::Teleporter(directions, _)
+        | GridCell::Trap(directions) => *directions,
         GridCell::Goal(_) => 0,
     };
     for direction in [NORTH, EAST, SOUTH, WEST] {
         if exits & direction != 0 {
             let mut cell = start.clone();
-            cell.step(direction, map.width(), map.height());
-            let distance = map.distance_to_goal(cell).unwrap();
+            map.step(&mut cell, direction);
+            // Paths cut off from the goal are never worth taking
+            let distance = match map.distance_to_goal(map.landing_cell(cell)) {
+                Some(distance) => distance,
+                None => continue,
+            };
             if distance < min_distance {
                 min_distance = distance;
-                best_direction = direction;
+                candidates.clear();
+            }
+            if distance == min_distance {
+                candidates.push(direction);
             }
         }
     }
+    let best_direction = break_tie(&candidates, last_move, rng);
+    debug!(best_direction, min_distance, "Chose move");
     best_direction
 }
+
+// Picks any exit leading somewhere on the map, avoiding the direction the
+// player came from unless it is the only way to go
+fn random_walk<R: Rng + ?Sized>(
+    start: Coordinates,
+    last_move: Direction,
+    map: &Map,
+    rng: &mut R,
+) -> Direction {
+    let exits = match map.cell_at(start) {
+        GridCell::Wall => panic!("Cannot navigate from inside a wall"),
+        GridCell::Path(directions, _)
+        | GridCell::Teleporter(directions, _)
+        | GridCell::Trap(directions) => *directions,
+        GridCell::Goal(_) => 0,
+    };
+    let available: Vec<Direction> = [NORTH, EAST, SOUTH, WEST]
+        .into_iter()
+        .filter(|direction| {
+            let mut cell = start;
+            exits & direction != 0
+                && map.step(&mut cell, *direction)
+                && !matches!(map.cell_at(cell), GridCell::Wall)
+        })
+        .collect();
+    let forward: Vec<Direction> = match last_move {
+        0 => available.clone(),
+        last => {
+            let backwards = get_opposite_direction(last);
+            available
+                .iter()
+                .copied()
+                .filter(|d| *d != backwards)
+                .collect()
+        }
+    };
+    let candidates = if forward.is_empty() {
+        available
+    } else {
+        forward
+    };
+    let direction = *candidates.choose(rng).unwrap_or(&0);
+    debug!(direction, "Chose random move");
+    direction
+}
+
+// A* search from the start to the nearest goal. The Manhattan distance to the
+// nearest goal, measured around the edges on toroidal maps, is used as the
+// heuristic, unless teleporters could make that overestimate the remaining
+// distance.
+fn a_star(start: Coordinates, map: &Map) -> PlannedRoute {
+    let has_teleporters = map
+        .iter()
+        .any(|(_, cell)| matches!(cell, GridCell::Teleporter(_, _)));
+    let heuristic = |Coordinates(x, y): Coordinates| {
+        if has_teleporters {
+            return 0;
+        }
+        map.goals()
+            .map(|goal| map.grid_distance(Coordinates(x, y), *goal))
+            .min()
+            .unwrap_or(0)
+    };
+
+    let mut cost = vec![vec![usize::MAX; map.width()]; map.height()];
+    let mut came_from = vec![vec![None; map.width()]; map.height()];
+    let mut open = BinaryHeap::new();
+    cost[start.1][start.0] = 0;
+    open.push(Reverse((heuristic(start), 0, start.0, start.1)));
+    let mut reached = None;
+    while let Some(Reverse((_, steps, x, y))) = open.pop() {
+        let cell = Coordinates(x, y);
+        if steps > cost[y][x] {
+            continue;
+        }
+        let exits = match map.cell_at(cell) {
+            GridCell::Wall => continue,
+            GridCell::Goal(_) => {
+                reached = Some(cell);
+                break;
+            }
+            GridCell::Path(directions, _)
+            | GridCell::Teleporter(directions, _)
+            | GridCell::Trap(directions) => *directions,
+        };
+        for direction in [NORTH, EAST, SOUTH, WEST] {
+            let mut next = cell;
+            if exits & direction == 0 || !map.step(&mut next, direction) {
+                continue;
+            }
+            if let GridCell::Wall = map.cell_at(next) {
+                continue;
+            }
+            let next = map.landing_cell(next);
+            let Coordinates(nx, ny) = next;
+            if steps + 1 < cost[ny][nx] {
+                cost[ny][nx] = steps + 1;
+                came_from[ny][nx] = Some((cell, direction));
+                open.push(Reverse((steps + 1 + heuristic(next), steps + 1, nx, ny)));
+            }
+        }
+    }
+
+    let mut route = PlannedRoute::default();
+    let mut cell = match reached {
+        Some(goal) => goal,
+        None => return route,
+    };
+    while let Some((previous, direction)) = came_from[cell.1][cell.0] {
+        route.moves.push_front((direction, cell));
+        cell = previous;
+    }
+    route.from = Some(start);
+    route
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::items::WeightTransfer;
+    use crate::npc::*;
+    use crate::player::PlayerType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn ties_prefer_straight() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            assert_eq!(break_tie(&[NORTH, EAST, SOUTH], EAST, &mut rng), EAST);
+            assert_eq!(break_tie(&[WEST], NORTH, &mut rng), WEST);
+        }
+        let first = break_tie(&[NORTH, SOUTH], EAST, &mut StdRng::seed_from_u64(7));
+        let second = break_tie(&[NORTH, SOUTH], EAST, &mut StdRng::seed_from_u64(7));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn algorithms_are_described() {
+        for algo in MOVE_ALGORITHMS {
+            assert!(!algo.description().is_empty());
+        }
+        for algo in ITEM_ALGORITHMS {
+            assert!(!algo.description().is_empty());
+        }
+    }
+
+    #[test]
+    fn shortest_path_ignores_unreachable_cells() {
+        let map = Map::from_grid(vec![
+            vec![
+                GridCell::Goal(EAST),
+                GridCell::Path(WEST | EAST, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![
+                GridCell::Path(EAST, None),
+                GridCell::Path(WEST, None),
+                GridCell::Wall,
+            ],
+        ]);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(shortest_path(Coordinates(1, 0), EAST, &map, &mut rng), WEST);
+        // No direction leads to the goal, so the player stays put
+        assert_eq!(shortest_path(Coordinates(0, 1), 0, &map, &mut rng), 0);
+    }
+
+    #[test]
+    fn a_star_avoids_dead_ends() {
+        // The goal is at the top left; heading east from the start leads into
+        // a dead end that looks closer to the goal
+        let map = Map::from_grid(vec![
+            vec![
+                GridCell::Path(NORTH | EAST, None),
+                GridCell::Path(WEST | EAST, None),
+                GridCell::Path(WEST | NORTH, None),
+            ],
+            vec![
+                GridCell::Path(NORTH | SOUTH, None),
+                GridCell::Wall,
+                GridCell::Path(SOUTH | NORTH, None),
+            ],
+            vec![
+                GridCell::Goal(SOUTH),
+                GridCell::Path(EAST, None),
+                GridCell::Path(SOUTH | WEST, None),
+            ],
+        ]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut route = PlannedRoute::default();
+        let mut position = Coordinates(2, 2);
+        let mut last_move = 0;
+        for expected in [SOUTH, SOUTH, WEST, WEST, NORTH, NORTH] {
+            let direction =
+                MoveAlgorithm::AStar.compute_move(position, last_move, &map, &mut route, &mut rng);
+            assert_eq!(direction, expected);
+            assert_eq!(
+                direction,
+                shortest_path(position, last_move, &map, &mut rng)
+            );
+            position.step(direction, map.width(), map.height());
+            last_move = direction;
+        }
+        assert!(route.is_empty());
+    }
+
+    #[test]
+    fn a_star_replans_when_off_route() {
+        let map = Map::from_grid(vec![vec![
+            GridCell::Goal(EAST),
+            GridCell::Path(WEST | EAST, None),
+            GridCell::Path(WEST | EAST, None),
+            GridCell::Path(WEST, None),
+        ]]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut route = PlannedRoute::default();
+        let algorithm = MoveAlgorithm::AStar;
+        assert_eq!(
+            algorithm.compute_move(Coordinates(3, 0), 0, &map, &mut route, &mut rng),
+            WEST
+        );
+        assert_eq!(route.len(), 2);
+        // The player was moved somewhere the plan didn't expect
+        assert_eq!(
+            algorithm.compute_move(Coordinates(1, 0), 0, &map, &mut route, &mut rng),
+            WEST
+        );
+        assert!(route.is_empty());
+        assert_eq!(
+            algorithm.compute_move(Coordinates(0, 0), 0, &map, &mut route, &mut rng),
+            0
+        );
+    }
+
+    #[test]
+    fn random_walk_follows_exits() {
+        let map = Map::from_grid(vec![
+            vec![
+                GridCell::Path(NORTH | EAST, None),
+                GridCell::Path(WEST | EAST | NORTH, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![
+                GridCell::Goal(SOUTH),
+                GridCell::Path(SOUTH, None),
+                GridCell::Wall,
+            ],
+        ]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut route = PlannedRoute::default();
+        let algorithm = MoveAlgorithm::RandomWalk;
+        for _ in 0..50 {
+            // Coming from the west, the player never turns back
+            let direction =
+                algorithm.compute_move(Coordinates(1, 0), EAST, &map, &mut route, &mut rng);
+            assert!(direction == EAST || direction == NORTH);
+            // Dead ends only lead back
+            let direction =
+                algorithm.compute_move(Coordinates(2, 0), EAST, &map, &mut route, &mut rng);
+            assert_eq!(direction, WEST);
+        }
+        assert_eq!(
+            algorithm.compute_move(Coordinates(0, 1), SOUTH, &map, &mut route, &mut rng),
+            0
+        );
+    }
+
+    fn corridor_map() -> Map {
+        Map::from_grid(vec![vec![
+            GridCell::Goal(EAST),
+            GridCell::Path(WEST | EAST, None),
+            GridCell::Path(WEST | EAST, None),
+            GridCell::Path(WEST | EAST, None),
+            GridCell::Path(WEST, None),
+        ]])
+    }
+
+    #[test]
+    fn opponents_ranked_by_distance() {
+        let map = corridor_map();
+        let mut players: Vec<Player> = [4, 3, 1, 0, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| {
+                Player::spawn_at(Coordinates(x, 0), String::new(), i, PlayerType::LocalHuman)
+            })
+            .collect();
+        players[4].set_team(players[0].team());
+        // The teammate and the player who already finished are left out
+        assert_eq!(
+            opponents_by_distance(&players[0], &players, &map),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn sabotage_targets_leader() {
+        let map = corridor_map();
+        let mut players: Vec<Player> = [4, 3, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| {
+                Player::spawn_at(Coordinates(x, 0), String::new(), i, PlayerType::LocalHuman)
+            })
+            .collect();
+        players[0].pick_up(Box::new(WeightTransfer::new_single(6, 1, 6, 1.)));
+        let algorithm = ItemAlgorithm::Sabotage;
+        // Helping an opponent is never sabotage
+        assert!(algorithm.choose_item(&players[0], &players, &map).is_none());
+
+        players[0].pick_up(Box::new(WeightTransfer::new_single(6, 6, 1, 1.)));
+        assert_eq!(
+            algorithm.choose_item(&players[0], &players, &map),
+            Some((1, 2))
+        );
+
+        players[2].set_reflecting(true);
+        assert!(algorithm.choose_item(&players[0], &players, &map).is_none());
+    }
+}
@@ -32,13 +32,19 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::dice::{WeightTransform, WeightedDie};
+use crate::dice::{EntangledPair, WeightTransform, WeightedDie, Weights};
 use crate::items::{HeldItem, ItemType};
-use crate::map::{Coordinates, Direction, GridCell, Map};
+use crate::map::{
+    get_opposite_direction, is_diagonal, Coordinates, Direction, GridCell, Map, EAST, NORTH, SOUTH,
+    WEST,
+};
 use crate::npc::{ItemAlgorithm, MoveAlgorithm};
 use bevy::ecs::component::Component;
+use rand::seq::SliceRandom;
+use rand::Rng;
 use std::fmt::{Display, Formatter};
 use std::slice::Iter;
+use tracing::error;
 
 #[derive(Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -56,15 +62,88 @@ impl Display for PlayerType {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum TeamWinCondition {
+    AllMembers,
+    AnyMember,
+}
+pub const TEAM_WIN_CONDITIONS: [TeamWinCondition; 2] =
+    [TeamWinCondition::AllMembers, TeamWinCondition::AnyMember];
+
+impl Display for TeamWinCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TeamWinCondition::AllMembers => write!(f, "All members reach the goal"),
+            TeamWinCondition::AnyMember => write!(f, "Any member reaches the goal"),
+        }
+    }
+}
+
+impl Default for TeamWinCondition {
+    fn default() -> Self {
+        TeamWinCondition::AllMembers
+    }
+}
+
+/// When a game without shared teams ends
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum WinCondition {
+    FirstToFinish,
+    AllButLast,
+    AllFinish,
+}
+pub const WIN_CONDITIONS: [WinCondition; 3] = [
+    WinCondition::FirstToFinish,
+    WinCondition::AllButLast,
+    WinCondition::AllFinish,
+];
+
+impl Display for WinCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WinCondition::FirstToFinish => write!(f, "The first player reaches the goal"),
+            WinCondition::AllButLast => write!(f, "Only one player is left"),
+            WinCondition::AllFinish => write!(f, "Every player reaches the goal"),
+        }
+    }
+}
+
+impl Default for WinCondition {
+    fn default() -> Self {
+        WinCondition::AllButLast
+    }
+}
+
 #[derive(Component)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Player {
     name: String,
     position: Coordinates,
+    #[cfg_attr(feature = "serde", serde(with = "crate::items::saved_items"))]
     inventory: Vec<HeldItem>,
     die: WeightedDie,
     player_number: usize,
     ptype: PlayerType,
     moves: Vec<Direction>,
+    reflecting: bool,
+    team: usize,
+    phasing: bool,
+    boosted: bool,
+    entanglement: Option<EntangledPair>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_inventory: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    has_scouted: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    total_rolls: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    sum_of_rolls: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    items_used: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    tiles_traveled: u32,
 }
 
 impl Player {
@@ -82,6 +161,17 @@ impl Player {
             player_number,
             ptype,
             moves: vec![],
+            reflecting: false,
+            team: player_number + 1,
+            phasing: false,
+            boosted: false,
+            entanglement: None,
+            max_inventory: 0,
+            has_scouted: false,
+            total_rolls: 0,
+            sum_of_rolls: 0,
+            items_used: 0,
+            tiles_traveled: 0,
         }
     }
 
@@ -97,6 +187,24 @@ impl Player {
         self.player_number
     }
 
+    pub fn team(&self) -> usize {
+        self.team
+    }
+
+    pub fn set_team(&mut self, team: usize) {
+        self.team = team;
+    }
+
+    /// Limits the number of items the player can carry. Zero means there is
+    /// no limit.
+    pub fn set_max_inventory(&mut self, max_inventory: usize) {
+        self.max_inventory = max_inventory;
+    }
+
+    pub fn inventory_full(&self) -> bool {
+        self.max_inventory > 0 && self.inventory.len() >= self.max_inventory
+    }
+
     pub fn pick_up(&mut self, item: HeldItem) {
         self.inventory.push(item);
     }
@@ -105,29 +213,119 @@ impl Player {
         self.position
     }
 
+    /// Puts the player on the given tile without walking there. The moves
+    /// made so far this turn no longer apply to the new position.
+    pub fn place_at(&mut self, position: Coordinates) {
+        self.position = position;
+        self.moves.clear();
+    }
+
+    /// Moves the player one tile in the given direction. Stepping onto a
+    /// teleporter moves the player on to its partner as part of the same step.
+    /// Diagonal steps cut across a corner of the corridor.
     pub fn step(&mut self, direction: Direction, map: &Map) -> bool {
         let mut current = self.position;
         match map.cell_at(current) {
+            _ if self.phasing => {}
             GridCell::Wall => panic!("Somehow the player is in a wall"),
-            GridCell::Path(exits, _) => {
+            _ if is_diagonal(direction) => {
+                if !map.can_step_diagonally(current, direction) {
+                    return false;
+                }
+            }
+            GridCell::Path(exits, _) | GridCell::Teleporter(exits, _) | GridCell::Trap(exits) => {
                 if direction & exits == 0 {
                     return false;
                 }
             }
             GridCell::Goal(_) => {}
         }
-        if !current.step(direction, map.width(), map.height()) {
+        if !map.step(&mut current, direction) {
             return false;
         }
         match map.cell_at(current) {
-            GridCell::Wall => panic!("Path allowed walking into a wall"),
+            GridCell::Wall if !self.phasing => panic!("Path allowed walking into a wall"),
             _ => {
-                self.position = current;
+                self.position = map.landing_cell(current);
+                self.tiles_traveled += 1;
                 true
             }
         }
     }
 
+    pub fn is_phasing(&self) -> bool {
+        self.phasing
+    }
+
+    pub fn set_phasing(&mut self, phasing: bool) {
+        self.phasing = phasing;
+    }
+
+    pub fn is_boosted(&self) -> bool {
+        self.boosted
+    }
+
+    pub fn set_boosted(&mut self, boosted: bool) {
+        self.boosted = boosted;
+    }
+
+    /// Whether the player has used a scouting item and may look at the
+    /// whole map
+    pub fn has_scouted(&self) -> bool {
+        self.has_scouted
+    }
+
+    pub fn set_scouted(&mut self, scouted: bool) {
+        self.has_scouted = scouted;
+    }
+
+    /// Computes the number of steps the player may take for the given roll,
+    /// consuming any boost
+    pub fn steps_for_roll(&mut self, rolled: u32) -> u32 {
+        if std::mem::take(&mut self.boosted) {
+            rolled * 2
+        } else {
+            rolled
+        }
+    }
+
+    /// Determines the directions the player may move in from their current
+    /// position. Players may only turn back if there is no other way to go.
+    pub fn allowed_moves(&self, map: &Map) -> Direction {
+        let exits = match map.cell_at(self.position) {
+            _ if self.phasing => NORTH | SOUTH | EAST | WEST,
+            GridCell::Path(exits, _) | GridCell::Teleporter(exits, _) | GridCell::Trap(exits) => {
+                *exits
+            }
+            _ => 0,
+        };
+        let mut allowed = 0;
+        for direction in [NORTH, SOUTH, EAST, WEST] {
+            let mut next = self.position;
+            if exits & direction != 0 && map.step(&mut next, direction) {
+                allowed |= direction;
+            }
+        }
+        let backwards = match self.last_move() {
+            0 => 0,
+            last => get_opposite_direction(last),
+        };
+        let forward = allowed & !backwards;
+        if forward != 0 {
+            forward
+        } else {
+            allowed
+        }
+    }
+
+    /// Called once the player has finished moving. Phasing only lasts for a
+    /// single move, unless the player would otherwise be stuck inside a wall.
+    pub fn end_move(&mut self, map: &Map) {
+        if !matches!(map.cell_at(self.position), GridCell::Wall) {
+            self.phasing = false;
+        }
+    }
+
     pub fn inventory_empty(&self) -> bool {
         self.inventory.is_empty()
     }
@@ -141,6 +339,23 @@ impl Player {
         self.inventory.remove(index)
     }
 
+    /// Leaves the item on the player's tile to make room in the inventory.
+    /// Returns whether the item was dropped; items can only be left on paths
+    /// that don't already hold an item.
+    pub fn drop_item(&mut self, index: usize, map: &mut Map) -> bool {
+        debug_assert!(index < self.inventory.len());
+        if !map.can_place_item(self.position) {
+            return false;
+        }
+        let item = self.inventory.remove(index);
+        map.place_item(self.position, item).is_ok()
+    }
+
+    /// Puts an item that still has charges left back where it was taken from
+    pub fn return_item(&mut self, index: usize, item: HeldItem) {
+        self.inventory.insert(index.min(self.inventory.len()), item);
+    }
+
     pub fn use_item_on_die(&self, die: &mut WeightedDie, index: usize) {
         debug_assert!(index < self.inventory.len());
         self.inventory[index].use_item_on_die(die);
@@ -155,6 +370,24 @@ impl Player {
         self.die.apply_transformation(transform);
     }
 
+    pub fn set_die_weights(&mut self, weights: Weights) {
+        if let Err(err) = self.die.set_weights(weights) {
+            error!(player = self.player_number, %err, "Ignoring invalid die weights");
+        }
+    }
+
+    pub fn set_probability_floor(&mut self, floor: f64) {
+        self.die.set_probability_floor(floor);
+    }
+
+    pub fn is_reflecting(&self) -> bool {
+        self.reflecting
+    }
+
+    pub fn set_reflecting(&mut self, reflecting: bool) {
+        self.reflecting = reflecting;
+    }
+
     pub fn die(&self) -> &WeightedDie {
         &self.die
     }
@@ -163,15 +396,404 @@ impl Player {
         self.die.roll()
     }
 
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
+        self.die.roll_with(rng)
+    }
+
+    pub fn roll_and_collapse(&mut self) -> u32 {
+        self.die.roll_and_collapse()
+    }
+
+    pub fn roll_and_collapse_with<R: Rng + ?Sized>(&mut self, rng: &mut R) -> u32 {
+        self.die.roll_and_collapse_with(rng)
+    }
+
+    /// Counts a roll of the given value towards the player's statistics
+    pub fn record_roll(&mut self, rolled: u32) {
+        self.total_rolls += 1;
+        self.sum_of_rolls += rolled;
+    }
+
+    pub fn record_item_use(&mut self) {
+        self.items_used += 1;
+    }
+
+    pub fn total_rolls(&self) -> u32 {
+        self.total_rolls
+    }
+
+    /// The mean of all the player's rolls, or `None` if they haven't rolled
+    pub fn average_roll(&self) -> Option<f64> {
+        (self.total_rolls > 0).then(|| self.sum_of_rolls as f64 / self.total_rolls as f64)
+    }
+
+    pub fn items_used(&self) -> u32 {
+        self.items_used
+    }
+
+    /// The number of steps the player has taken, counting teleports as part
+    /// of the step onto the teleporter
+    pub fn tiles_traveled(&self) -> u32 {
+        self.tiles_traveled
+    }
+
+    /// The entanglement between this player's die and another player's,
+    /// held by the player whose roll is measured first
+    pub fn entanglement(&self) -> Option<&EntangledPair> {
+        self.entanglement.as_ref()
+    }
+
+    pub fn entanglement_mut(&mut self) -> Option<&mut EntangledPair> {
+        self.entanglement.as_mut()
+    }
+
+    pub fn set_entanglement(&mut self, pair: EntangledPair) {
+        self.entanglement = Some(pair);
+    }
+
     pub fn append_move(&mut self, direction: Direction) {
         self.moves.push(direction);
     }
 
+    /// Records a step, adding the direction to the move list only if it
+    /// differs from the previous one
+    pub fn record_move(&mut self, direction: Direction) {
+        if direction != self.last_move() {
+            self.append_move(direction);
+        }
+    }
+
     pub fn last_move(&self) -> Direction {
         *self.moves.last().unwrap_or(&0)
     }
 
     pub fn end_turn(&mut self) {
         self.moves.clear();
+        if self
+            .entanglement
+            .as_ref()
+            .map_or(false, |pair| pair.measured().is_some())
+        {
+            self.entanglement = None;
+        }
+    }
+}
+
+pub fn turn_order<R: Rng + ?Sized>(players: usize, shuffle: bool, rng: &mut R) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..players).collect();
+    if shuffle {
+        order.shuffle(rng);
+    }
+    order
+}
+
+pub fn next_in_turn_order(order: &[usize], current: usize, finished: &[usize]) -> usize {
+    let start = order.iter().position(|p| *p == current).unwrap_or(0);
+    for offset in 1..=order.len() {
+        let next = order[(start + offset) % order.len()];
+        if !finished.contains(&next) {
+            return next;
+        }
+    }
+    current
+}
+
+/// Returns the team which has won, if any. `teams` holds the team of each
+/// player and `finished` the players who have reached the goal.
+pub fn winning_team(
+    teams: &[usize],
+    finished: &[usize],
+    condition: TeamWinCondition,
+) -> Option<usize> {
+    finished.iter().map(|player| teams[*player]).find(|team| {
+        let mut members = (0..teams.len()).filter(|player| teams[*player] == *team);
+        match condition {
+            TeamWinCondition::AllMembers => members.all(|player| finished.contains(&player)),
+            TeamWinCondition::AnyMember => true,
+        }
+    })
+}
+
+/// Determines whether the game is over. If every player is on their own team,
+/// the win condition decides how many players have to finish. Otherwise the
+/// game ends as soon as one team has won.
+pub fn game_is_over(
+    teams: &[usize],
+    finished: &[usize],
+    team_condition: TeamWinCondition,
+    condition: WinCondition,
+) -> bool {
+    if has_shared_teams(teams) {
+        return winning_team(teams, finished, team_condition).is_some();
+    }
+    match condition {
+        WinCondition::FirstToFinish => !finished.is_empty(),
+        // A solo game ends once the only player finishes
+        WinCondition::AllButLast => !finished.is_empty() && finished.len() + 1 >= teams.len(),
+        WinCondition::AllFinish => finished.len() >= teams.len(),
+    }
+}
+
+pub fn has_shared_teams(teams: &[usize]) -> bool {
+    teams
+        .iter()
+        .enumerate()
+        .any(|(i, team)| teams[..i].contains(team))
+}
+
+/// Computes the place of each finisher given the time (e.g. turn and step) at
+/// which they finished. Players who finished at the same time share a place
+/// and the following place is skipped.
+pub fn finishing_places<T: PartialOrd>(finish_times: &[T]) -> Vec<usize> {
+    finish_times
+        .iter()
+        .map(|time| 1 + finish_times.iter().filter(|other| *other < time).count())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{MapGenerator, NORTHEAST, NORTHWEST, SOUTHWEST};
+    use crate::player::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn seeded_turn_order() {
+        let first = turn_order(6, true, &mut StdRng::seed_from_u64(42));
+        let second = turn_order(6, true, &mut StdRng::seed_from_u64(42));
+        assert_eq!(first, second);
+        assert_eq!(
+            turn_order(6, false, &mut StdRng::seed_from_u64(42)),
+            (0..6).collect::<Vec<_>>()
+        );
+
+        let mut sorted = first.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rotation_follows_order() {
+        let order = vec![2, 0, 3, 1];
+        assert_eq!(next_in_turn_order(&order, 2, &[]), 0);
+        assert_eq!(next_in_turn_order(&order, 0, &[]), 3);
+        assert_eq!(next_in_turn_order(&order, 1, &[]), 2);
+        // Players who have finished are skipped
+        assert_eq!(next_in_turn_order(&order, 0, &[3]), 1);
+        assert_eq!(next_in_turn_order(&order, 1, &[2, 0]), 3);
+    }
+
+    #[test]
+    fn last_move_tracks_direction_changes() {
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        assert_eq!(player.last_move(), 0);
+        for step in [NORTH, NORTH, EAST, EAST, EAST, SOUTH, EAST] {
+            player.record_move(step);
+        }
+        assert_eq!(player.last_move(), EAST);
+        assert_eq!(player.moves, vec![NORTH, EAST, SOUTH, EAST]);
+        player.end_turn();
+        assert_eq!(player.last_move(), 0);
+    }
+
+    #[test]
+    fn simultaneous_finishers_share_place() {
+        let times = [(2, 3), (2, 3), (3, 1), (4, 2)];
+        assert_eq!(finishing_places(&times), vec![1, 1, 3, 4]);
+        assert_eq!(finishing_places(&[(1, 4), (2, 1)]), vec![1, 2]);
+    }
+
+    #[test]
+    fn team_game_ends_when_team_finishes() {
+        let teams = [1, 1, 2, 2];
+        let all = TeamWinCondition::AllMembers;
+        let last = WinCondition::AllButLast;
+        assert!(!game_is_over(&teams, &[0], all, last));
+        assert!(!game_is_over(&teams, &[0, 2], all, last));
+        assert!(game_is_over(&teams, &[0, 2, 3], all, last));
+        assert_eq!(winning_team(&teams, &[0, 2, 3], all), Some(2));
+        assert!(game_is_over(&teams, &[1, 0], all, last));
+
+        let any = TeamWinCondition::AnyMember;
+        assert!(game_is_over(&teams, &[3], any, last));
+        assert_eq!(winning_team(&teams, &[3], any), Some(2));
+
+        // Without shared teams, the game continues until one player is left
+        let solo = [1, 2, 3];
+        assert!(!game_is_over(&solo, &[0], all, last));
+        assert!(game_is_over(&solo, &[0, 2], all, last));
+    }
+
+    #[test]
+    fn win_condition_decides_solo_game_end() {
+        let solo = [1, 2, 3];
+        let all = TeamWinCondition::AllMembers;
+        assert!(!game_is_over(&solo, &[], all, WinCondition::FirstToFinish));
+        assert!(game_is_over(&solo, &[1], all, WinCondition::FirstToFinish));
+        assert!(!game_is_over(&solo, &[1, 0], all, WinCondition::AllFinish));
+        assert!(game_is_over(
+            &solo,
+            &[1, 0, 2],
+            all,
+            WinCondition::AllFinish
+        ));
+
+        // Team games are decided by the team win condition alone
+        let teams = [1, 1, 2];
+        assert!(!game_is_over(
+            &teams,
+            &[0],
+            all,
+            WinCondition::FirstToFinish
+        ));
+    }
+
+    #[test]
+    fn phasing_ignores_exits() {
+        let map =
+            Map::generate_random_map(20, 20, 2, 0.1, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        let (start, direction) = map
+            .iter()
+            .find_map(|(pos, cell)| match cell {
+                GridCell::Path(exits, _) => [NORTH, SOUTH, EAST, WEST]
+                    .into_iter()
+                    .find(|direction| {
+                        let mut neighbor = pos;
+                        exits & direction == 0
+                            && neighbor.step(*direction, map.width(), map.height())
+                    })
+                    .map(|direction| (pos, direction)),
+                _ => None,
+            })
+            .expect("No path cell with a blocked direction");
+
+        let mut player = Player::spawn_at(start, String::new(), 0, PlayerType::LocalHuman);
+        assert!(!player.step(direction, &map));
+        assert!(player.position() == start);
+
+        player.set_phasing(true);
+        assert!(player.step(direction, &map));
+        assert!(player.position() != start);
+
+        // Phasing wears off after the move unless the player is stuck in a wall
+        player.end_move(&map);
+        let in_wall = matches!(map.cell_at(player.position()), GridCell::Wall);
+        assert_eq!(player.is_phasing(), in_wall);
+    }
+
+    #[test]
+    fn turning_back_only_at_dead_ends() {
+        let map = Map::from_grid(vec![
+            vec![
+                GridCell::Path(EAST, None),
+                GridCell::Path(WEST | EAST, None),
+                GridCell::Path(WEST, None),
+            ],
+            vec![GridCell::Wall, GridCell::Wall, GridCell::Wall],
+        ]);
+        let mut player =
+            Player::spawn_at(Coordinates(1, 0), String::new(), 0, PlayerType::LocalHuman);
+        assert_eq!(player.allowed_moves(&map), WEST | EAST);
+        player.record_move(EAST);
+        assert_eq!(player.allowed_moves(&map), EAST);
+
+        // At a dead end, the only way out is back
+        assert!(player.step(EAST, &map));
+        assert_eq!(player.allowed_moves(&map), WEST);
+
+        // Players stuck in a wall have nowhere to go
+        let stuck = Player::spawn_at(Coordinates(0, 1), String::new(), 0, PlayerType::LocalHuman);
+        assert_eq!(stuck.allowed_moves(&map), 0);
+    }
+
+    #[test]
+    fn full_inventory_makes_room_by_dropping() {
+        let mut map = Map::from_grid(vec![vec![
+            GridCell::Path(EAST, None),
+            GridCell::Path(WEST, None),
+        ]]);
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        player.pick_up(Box::new(crate::items::Boost));
+        assert!(!player.inventory_full());
+        player.set_max_inventory(1);
+        assert!(player.inventory_full());
+
+        assert!(player.drop_item(0, &mut map));
+        assert!(player.inventory_empty());
+        assert!(matches!(
+            map.cell_at(Coordinates(0, 0)),
+            GridCell::Path(_, Some(_))
+        ));
+
+        // The tile already holds the dropped item
+        player.pick_up(Box::new(crate::items::Phase));
+        assert!(!player.drop_item(0, &mut map));
+        assert!(player.inventory_full());
+    }
+
+    #[test]
+    fn solo_game_ends_at_goal() {
+        let teams = [1];
+        let all = TeamWinCondition::AllMembers;
+        for condition in WIN_CONDITIONS {
+            assert!(!game_is_over(&teams, &[], all, condition));
+            assert!(game_is_over(&teams, &[0], all, condition));
+        }
+    }
+
+    #[test]
+    fn teleporting_takes_one_step() {
+        let map = Map::from_grid(vec![vec![
+            GridCell::Path(EAST, None),
+            GridCell::Teleporter(WEST, Coordinates(3, 0)),
+            GridCell::Wall,
+            GridCell::Teleporter(EAST, Coordinates(1, 0)),
+            GridCell::Path(WEST, None),
+        ]]);
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        assert!(player.step(EAST, &map));
+        assert!(player.position() == Coordinates(3, 0));
+        assert_eq!(player.allowed_moves(&map), EAST);
+        assert!(player.step(EAST, &map));
+        assert!(player.position() == Coordinates(4, 0));
+        assert_eq!(player.tiles_traveled(), 2);
+    }
+
+    #[test]
+    fn roll_statistics() {
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        assert_eq!(player.average_roll(), None);
+        player.record_roll(2);
+        player.record_roll(5);
+        assert_eq!(player.total_rolls(), 2);
+        assert_eq!(player.average_roll(), Some(3.5));
+    }
+
+    #[test]
+    fn diagonal_steps_cut_corners() {
+        let mut map = Map::from_grid(vec![
+            vec![
+                GridCell::Path(EAST, None),
+                GridCell::Path(WEST | NORTH, None),
+            ],
+            vec![GridCell::Wall, GridCell::Path(SOUTH, None)],
+        ]);
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        assert!(!player.step(NORTHWEST, &map));
+        assert!(player.step(NORTHEAST, &map));
+        assert!(player.position() == Coordinates(1, 1));
+        assert!(player.step(SOUTHWEST, &map));
+        assert!(player.position() == Coordinates(0, 0));
+
+        // Without a path around the corner there is nothing to cut across
+        *map.cell_at_mut(Coordinates(1, 0)) = GridCell::Path(WEST, None);
+        assert!(!player.step(NORTHEAST, &map));
+        assert!(player.position() == Coordinates(0, 0));
     }
 }
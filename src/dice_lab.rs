@@ -0,0 +1,206 @@
+// MIT/Apache 2.0 dual license
+// Apache 2.0
+// Copyright 2022 Arc676/Alessandro Vinciguerra <alesvinciguerra@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// MIT
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::game::{die_probability_labels, die_weight_labels, get_painter};
+use crate::settings::GameSettings;
+use crate::AppState;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use rand::Rng;
+use zinkd::dice::WeightedDie;
+use zinkd::items::{random_item, HeldItem, Item, ItemType};
+
+// Number of rolls made at once by the "Roll many" button
+const BATCH_ROLLS: u32 = 100;
+
+/// A die to practice using items on outside of a game
+pub struct DiceLab {
+    die: WeightedDie,
+    tally: Vec<u32>,
+    applied: Vec<String>,
+}
+
+impl DiceLab {
+    fn new(faces: usize) -> Self {
+        DiceLab {
+            die: WeightedDie::fair_die_with_faces(faces),
+            tally: vec![0; faces],
+            applied: vec![],
+        }
+    }
+
+    fn apply(&mut self, item: &HeldItem) {
+        item.use_item_on_die(&mut self.die);
+        self.applied.push(item.short_description().to_string());
+    }
+
+    fn roll(&mut self, times: u32) {
+        for _ in 0..times {
+            let rolled = self.die.roll();
+            self.tally[rolled as usize - 1] += 1;
+        }
+    }
+
+    fn total_rolls(&self) -> u32 {
+        self.tally.iter().sum()
+    }
+}
+
+// Draws random items until one of them is a weight transfer
+fn random_weight_transfer<R: Rng + ?Sized>(faces: usize, rng: &mut R) -> HeldItem {
+    loop {
+        let item = random_item(faces, rng);
+        if matches!(
+            item.item_type(),
+            ItemType::WeightTransfer
+                | ItemType::DoubleWeightTransfer
+                | ItemType::WeightTransferPair
+                | ItemType::SwapFaces
+                | ItemType::CustomTransfer
+        ) {
+            return item;
+        }
+    }
+}
+
+// Draws a bar for each face with its share of the rolls so far
+fn draw_histogram(painter: &egui::Painter, to_screen: egui::emath::RectTransform, tally: &[u32]) {
+    use bevy_egui::egui::*;
+    let total = tally.iter().sum::<u32>().max(1) as f32;
+    let spacing = (tally.len() + 1) as f32;
+    for (i, count) in tally.iter().enumerate() {
+        let face = (i + 1) as f32;
+        let share = *count as f32 / total;
+        painter.rect_filled(
+            Rect::from([
+                to_screen
+                    * Pos2 {
+                        x: (face - 0.25) / spacing,
+                        y: 0.9 - share,
+                    },
+                to_screen
+                    * Pos2 {
+                        x: (face + 0.25) / spacing,
+                        y: 0.9,
+                    },
+            ]),
+            0.,
+            Color32::GOLD,
+        );
+        painter.text(
+            to_screen
+                * Pos2 {
+                    x: face / spacing,
+                    y: 0.85 - share,
+                },
+            Align2::CENTER_CENTER,
+            count,
+            TextStyle::Small,
+            Color32::WHITE,
+        );
+    }
+}
+
+pub fn setup_dice_lab(mut commands: Commands, settings: Res<GameSettings>) {
+    commands.insert_resource(DiceLab::new(settings.die_faces()));
+}
+
+pub fn dice_lab_ui(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    mut lab: ResMut<DiceLab>,
+) {
+    egui::CentralPanel::default().show(egui_context.ctx_mut(), |ui| {
+        ui.heading("Dice laboratory");
+        ui.label("Try out weight transfers on a die and roll it to see how the odds change.");
+        ui.horizontal(|ui| {
+            if ui.button("Apply a random weight transfer").clicked() {
+                let item = random_weight_transfer(lab.die.faces(), &mut rand::thread_rng());
+                lab.apply(&item);
+            }
+            if ui.button("Roll").clicked() {
+                lab.roll(1);
+            }
+            if ui.button(format!("Roll {} times", BATCH_ROLLS)).clicked() {
+                lab.roll(BATCH_ROLLS);
+            }
+            if ui.button("Reset").clicked() {
+                let faces = lab.die.faces();
+                *lab = DiceLab::new(faces);
+            }
+            if ui.button("Back to Main").clicked() {
+                state.set(AppState::MainMenu).unwrap();
+            }
+        });
+        if lab.applied.is_empty() {
+            ui.label("The die is fair");
+        } else {
+            ui.label(format!("Applied: {}", lab.applied.join(", ")));
+        }
+        ui.label(format!("Rolls: {}", lab.total_rolls()));
+
+        ui.columns(2, |columns| {
+            columns[0].label("Weights");
+            let (_, painter, to_screen) = get_painter(&mut columns[0]);
+            die_weight_labels(&painter, to_screen, lab.die.faces());
+            lab.die
+                .visualize_weights(&painter, to_screen, egui::Color32::BLUE);
+            die_probability_labels(&painter, to_screen, &lab.die);
+
+            columns[1].label("Results");
+            let (_, painter, to_screen) = get_painter(&mut columns[1]);
+            die_weight_labels(&painter, to_screen, lab.tally.len());
+            draw_histogram(&painter, to_screen, &lab.tally);
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice_lab::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn lab_tallies_every_roll() {
+        let mut lab = DiceLab::new(6);
+        let mut rng = StdRng::seed_from_u64(5);
+        let item = random_weight_transfer(6, &mut rng);
+        lab.apply(&item);
+        assert_eq!(lab.applied.len(), 1);
+        lab.roll(BATCH_ROLLS);
+        assert_eq!(lab.total_rolls(), BATCH_ROLLS);
+        assert!((lab.die.probabilities().iter().sum::<f64>() - 1.).abs() < 1e-9);
+    }
+}
@@ -32,19 +32,30 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use crate::settings::GameSettings;
+use crate::history::{self, MatchHistory, MatchRecord};
+use crate::replay::{self, Ghost, LastReplay, Replay, ReplayEvent, ReplayOutcome, ReplaySession};
+use crate::settings::{
+    self, GameSettings, KeyBindings, PlayerSprite, ScreenCorner, TimeAttackRecord,
+};
 use crate::AppState;
 use bevy::prelude::*;
+use bevy::window::WindowResized;
 use bevy::{ecs::component::Component, input::mouse::MouseWheel};
-use bevy_egui::{egui, EguiContext};
+use bevy_egui::{egui, EguiClipboard, EguiContext};
+use directories_next::ProjectDirs;
 use itertools::izip;
-use std::f32::consts::{FRAC_PI_2, PI};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+use std::fs::{create_dir_all, File};
+use std::io::{Read, Write};
 use std::time::Duration;
-use zinkd::dice::WeightedDie;
-use zinkd::items::ItemType;
+use zinkd::dice::{format_amplitude, EntangledPair, WeightedDie};
+use zinkd::items::{use_item_on, Item, ItemType, TransferParameters, WeightTransfer};
 use zinkd::map::Direction;
 use zinkd::map::*;
-use zinkd::player::{Player, PlayerType};
+use zinkd::npc::PlannedRoute;
+use zinkd::player::{self, Player, PlayerType, TeamWinCondition, WinCondition};
 
 #[derive(Component)]
 pub struct MainCamera;
@@ -55,6 +66,18 @@ pub struct EntityTooltip(String);
 #[derive(Component)]
 pub struct PlayerNumber(usize);
 
+#[derive(Component)]
+pub struct GhostSprite;
+
+#[derive(Component)]
+pub struct DirectionIndicator(usize);
+
+#[derive(Component)]
+pub struct StepNumber;
+
+#[derive(Component)]
+pub struct ReachableHighlight;
+
 impl PartialEq<usize> for PlayerNumber {
     fn eq(&self, other: &usize) -> bool {
         self.0 == *other
@@ -62,6 +85,7 @@ impl PartialEq<usize> for PlayerNumber {
 }
 
 #[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 enum GameAction {
     WaitForInput,
     UsingItem,
@@ -78,11 +102,14 @@ impl Default for GameAction {
 enum ItemEffect {
     DieTransform(WeightedDie, WeightedDie),
     PlayerAction(String),
+    // The user's and the target's dice
+    Entangle(WeightedDie, WeightedDie),
 }
 
 enum ItemAction {
     NoAction,
-    UseItem,
+    // Carries the player the item was actually applied to
+    UseItem(usize),
     CancelItem,
 }
 
@@ -93,38 +120,324 @@ struct ItemUsePreview {
     item_type: ItemType,
     target_player: usize,
     effect: Option<ItemEffect>,
+    // The faces and strength picked for a custom weight transfer
+    transfer: Option<TransferParameters>,
 }
 
 pub type PlayerList = Vec<Player>;
 
+// Slides a player's sprite from one tile to the next over the time it takes
+// to walk one tile
+struct WalkAnimation {
+    player: usize,
+    from: Vec2,
+    to: Vec2,
+    elapsed: f32,
+}
+
+impl WalkAnimation {
+    fn position(&self, duration: f32) -> Vec2 {
+        if duration <= 0. {
+            return self.to;
+        }
+        self.from.lerp(self.to, (self.elapsed / duration).min(1.))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum SoundEffect {
+    Roll,
+    Step,
+    PickUp,
+    Goal,
+}
+
+#[derive(Default)]
+struct SoundEffects {
+    roll: Handle<AudioSource>,
+    step: Handle<AudioSource>,
+    pick_up: Handle<AudioSource>,
+    goal: Handle<AudioSource>,
+}
+
+impl SoundEffects {
+    fn load(asset_server: &AssetServer) -> Self {
+        SoundEffects {
+            roll: asset_server.load("sfx/farkle.wav"),
+            step: asset_server.load("sfx/step.wav"),
+            pick_up: asset_server.load("sfx/pickup.wav"),
+            goal: asset_server.load("sfx/goal.wav"),
+        }
+    }
+
+    fn handle(&self, effect: SoundEffect) -> Handle<AudioSource> {
+        match effect {
+            SoundEffect::Roll => self.roll.clone(),
+            SoundEffect::Step => self.step.clone(),
+            SoundEffect::PickUp => self.pick_up.clone(),
+            SoundEffect::Goal => self.goal.clone(),
+        }
+    }
+}
+
+// The parts of the game state whose changes are accompanied by a sound
+#[derive(Clone, PartialEq)]
+struct SoundTriggers {
+    rolled: bool,
+    positions: Vec<Coordinates>,
+    picked_up_item: Option<String>,
+    winners: usize,
+}
+
+impl SoundTriggers {
+    fn capture(game_state: &GameState, players: &[Player]) -> Self {
+        SoundTriggers {
+            rolled: game_state.rolled_value.is_some(),
+            positions: players.iter().map(Player::position).collect(),
+            picked_up_item: game_state.picked_up_item.clone(),
+            winners: game_state.winners.len(),
+        }
+    }
+
+    fn effects_since(&self, before: &SoundTriggers) -> Vec<SoundEffect> {
+        let mut effects = vec![];
+        if self.rolled && !before.rolled {
+            effects.push(SoundEffect::Roll);
+        }
+        if self.positions != before.positions {
+            effects.push(SoundEffect::Step);
+        }
+        if self.picked_up_item.is_some() && self.picked_up_item != before.picked_up_item {
+            effects.push(SoundEffect::PickUp);
+        }
+        if self.winners > before.winners {
+            effects.push(SoundEffect::Goal);
+        }
+        effects
+    }
+}
+
+// Older entries are dropped from the event log once it is this long
+const MAX_LOG_ENTRIES: usize = 100;
+
+// Fields that only matter to the UI or can be rebuilt from the settings are
+// not saved
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct GameState {
     player_count: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
     paused: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    confirming_end: bool,
+    turn_order: Vec<usize>,
     active_player: usize,
     player_names: Vec<String>,
+    player_sprites: Vec<PlayerSprite>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    player_colors: Vec<[u8; 3]>,
     inspector_player: usize,
     current_action: GameAction,
+    #[cfg_attr(feature = "serde", serde(skip))]
     hover_item: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     item_preview: ItemUsePreview,
+    #[cfg_attr(feature = "serde", serde(skip))]
     inventory_visible: bool,
+    // The inventory row picked with the keyboard
+    #[cfg_attr(feature = "serde", serde(skip))]
+    selected_item: Option<usize>,
     picked_up_item: Option<String>,
     rolled_value: Option<u32>,
     winners: Vec<usize>,
     winner_names: Vec<String>,
+    finish_times: Vec<(u32, u32)>,
+    turn_number: u32,
     game_over: bool,
     camera_follows_player: bool,
     camera_default_zoom: f32,
     camera_auto_zoom: bool,
     camera_zoom: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
     left_panel_width: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
     right_panel_width: f32,
     time_since_last_move: Duration,
     current_move: Option<Direction>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    walk_animation: Option<WalkAnimation>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    planned_route: PlannedRoute,
     tile_walk_time: f32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    reduced_motion: bool,
+    pickup_radius: usize,
+    show_amplitudes: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    inspecting: bool,
+    show_directions: bool,
+    reveal_opponent_dice: bool,
+    hide_opponent_dice: bool,
+    reveal_opponent_inventories: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    hide_standings: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    peek_remaining: Duration,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scouting: bool,
+    ui_scale: f32,
+    show_step_numbers: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    numbered_tiles: Vec<Coordinates>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    highlighted_tiles: Vec<Coordinates>,
+    teams: Vec<usize>,
+    team_win_condition: TeamWinCondition,
+    win_condition: WinCondition,
+    friendly_fire: bool,
+    die_size: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    die_atlas: DieAtlas,
+    die_corner: ScreenCorner,
+    status_message: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    event_log: Vec<String>,
+    time_attack: bool,
+    elapsed: Duration,
+    time_attack_best: Option<TimeAttackRecord>,
+    time_attack_result: Option<TimeAttackRecord>,
+    items_before_roll: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    diagonal_movement: bool,
+    // Pure races are played without any items
+    #[cfg_attr(feature = "serde", serde(default))]
+    pure_race: bool,
+    collapse_on_roll: bool,
+    // Each player's dice generator in fair mode. Resumed games roll freely.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    fair_dice: Vec<StdRng>,
+    used_item_before_roll: bool,
+    turn_time_limit: Option<f32>,
+    turn_elapsed: Duration,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    show_help: bool,
+    total_steps: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sounds: SoundEffects,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sound_triggers: Option<SoundTriggers>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    item_texture: Handle<Image>,
+    // Games resumed from a save aren't recorded
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replay: Option<ReplaySession>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ghost: Option<Ghost>,
+}
+
+/// A game loaded from disk, waiting to be resumed
+#[derive(Default)]
+pub struct SavedGame(Option<(GameState, Map, PlayerList)>);
+
+impl SavedGame {
+    pub fn exists(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Forgets the loaded game so that the next game starts from scratch
+    pub fn discard(&mut self) {
+        self.0 = None;
+    }
 }
 
 impl GameState {
+    // Games saved before players had colours leave every sprite untinted
+    fn player_color(&self, player: usize) -> [u8; 3] {
+        self.player_colors
+            .get(player)
+            .copied()
+            .unwrap_or([255, 255, 255])
+    }
+
+    fn is_over(&self) -> bool {
+        player::game_is_over(
+            &self.teams,
+            &self.winners,
+            self.team_win_condition,
+            self.win_condition,
+        )
+    }
+
+    fn playing_back(&self) -> bool {
+        self.replay
+            .as_ref()
+            .map_or(false, ReplaySession::is_playing_back)
+    }
+
+    fn record(&mut self, event: ReplayEvent) {
+        if let Some(session) = self.replay.as_mut() {
+            session.record(event);
+        }
+    }
+
+    fn can_use_items(&self) -> bool {
+        match self.current_action {
+            GameAction::HasMoved => true,
+            GameAction::WaitForInput => self.items_before_roll && !self.used_item_before_roll,
+            _ => false,
+        }
+    }
+
+    // Where to return to once the item preview is closed
+    fn action_after_item(&self) -> GameAction {
+        if self.rolled_value.is_none() {
+            GameAction::WaitForInput
+        } else {
+            GameAction::HasMoved
+        }
+    }
+
+    fn can_target(&self, user: usize, target: usize) -> bool {
+        user == target || self.friendly_fire || self.teams[user] != self.teams[target]
+    }
+
+    // Seconds the active player has left for their turn, if they are timed
+    fn turn_time_remaining(&self, ptype: PlayerType) -> Option<f32> {
+        match (self.turn_time_limit, ptype) {
+            (Some(limit), PlayerType::LocalHuman) => {
+                Some((limit - self.turn_elapsed.as_secs_f32()).max(0.))
+            }
+            _ => None,
+        }
+    }
+
+    fn log_event(&mut self, entry: String) {
+        self.event_log.push(entry);
+        if self.event_log.len() > MAX_LOG_ENTRIES {
+            self.event_log.remove(0);
+        }
+    }
+
+    // How long a step between two tiles is shown for. Turns are still paced by
+    // the walk time, so reduced motion doesn't speed up the game.
+    fn walk_duration(&self) -> f32 {
+        if self.reduced_motion {
+            0.
+        } else {
+            self.tile_walk_time
+        }
+    }
+
+    fn log_item_use(&mut self, user: usize, item: ItemType, target: usize) {
+        let target = if user == target {
+            "themselves"
+        } else {
+            &self.player_names[target]
+        };
+        let entry = format!("{} used {} on {}", self.player_names[user], item, target);
+        self.log_event(entry);
+    }
+
     fn get_player_name(&self, player: usize, active: usize) -> &str {
         if player == active {
             "yourself"
@@ -134,31 +447,88 @@ impl GameState {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 enum Control {
     Roll,
     Inventory,
     Move(Direction),
+    Stop,
     EndTurn,
 }
 
+impl Control {
+    fn description(&self) -> &'static str {
+        match self {
+            Control::Roll => "Roll the die",
+            Control::Inventory => "Show or hide your inventory",
+            Control::Move(direction) => match *direction {
+                NORTH => "Move north",
+                SOUTH => "Move south",
+                EAST => "Move east",
+                WEST => "Move west",
+                NORTHEAST => "Move northeast",
+                NORTHWEST => "Move northwest",
+                SOUTHEAST => "Move southeast",
+                SOUTHWEST => "Move southwest",
+                _ => "Move",
+            },
+            Control::Stop => "Stop moving and forfeit the remaining steps",
+            Control::EndTurn => "End the turn",
+        }
+    }
+}
+
 pub fn setup_game(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     settings: Res<GameSettings>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut saved: ResMut<SavedGame>,
+    mut last_replay: ResMut<LastReplay>,
+    mut key_bindings: ResMut<KeyBindings>,
 ) {
+    *key_bindings = settings.key_bindings().clone();
     commands
         .spawn()
         .insert_bundle(OrthographicCameraBundle::new_2d())
         .insert(MainCamera);
-    let map = Map::generate_random_map(
-        settings.map_width(),
-        settings.map_height(),
-        settings.players(),
-        settings.item_density(),
-        settings.travel_distance(),
+    let (game_state, map, players) = if let Some(replay) = last_replay.take_to_watch() {
+        info!(seed = replay.seed(), "Watching replay");
+        replay_game(replay)
+    } else if let Some(replay) = last_replay.take_to_race() {
+        info!(seed = replay.seed(), "Racing against ghost");
+        ghost_game(replay)
+    } else {
+        match saved.0.take() {
+            Some((mut game_state, map, players)) => {
+                info!(turn = game_state.turn_number, "Resuming saved game");
+                // Item previews aren't saved, so go back to before the item was
+                // chosen
+                if game_state.current_action == GameAction::UsingItem {
+                    game_state.current_action = game_state.action_after_item();
+                }
+                (game_state, map, players)
+            }
+            None => new_game(&settings),
+        }
+    };
+    spawn_game(
+        &mut commands,
+        &asset_server,
+        &settings,
+        &mut egui_context,
+        (game_state, map, players),
     );
+}
 
+// Spawns the sprites for the map and players and inserts the game's resources
+fn spawn_game(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    settings: &GameSettings,
+    egui_context: &mut EguiContext,
+    (mut game_state, map, players): (GameState, Map, PlayerList),
+) {
     let tile_size = Vec2::splat(96.);
     let coords_to_vec =
         |x: usize, y: usize, z: f32| Vec2::new(x as f32 * 96., y as f32 * 96.).extend(z);
@@ -176,9 +546,13 @@ pub fn setup_game(
     let mut sprites = vec![];
     for (Coordinates(x, y), cell) in map.iter() {
         let mut rotation = Quat::IDENTITY;
+        let mut color = Color::WHITE;
         let texture = match cell {
             GridCell::Wall => wall.clone(),
-            GridCell::Path(direction, _) | GridCell::Goal(direction) => match *direction {
+            GridCell::Path(direction, _)
+            | GridCell::Goal(direction)
+            | GridCell::Teleporter(direction, _)
+            | GridCell::Trap(direction) => match *direction {
                 OMNIDIRECTIONAL => omnidirectional.clone(),
                 LONGITUDINAL | LATITUDINAL => {
                     if *direction == LATITUDINAL {
@@ -214,32 +588,60 @@ pub fn setup_game(
                     corner.clone()
                 }
                 _ => {
+                    error!(x, y, direction, "Unknown direction");
+                    // Highlight render fallbacks in debug builds without
+                    // making them look like the goal
                     if cfg!(debug_assertions) {
-                        dbg!("Unknown direction {}", direction);
-                        rotation = Quat::from_rotation_z(PI);
-                        goal.clone()
-                    } else {
-                        wall.clone()
+                        color = Color::FUCHSIA;
                     }
+                    wall.clone()
                 }
             },
         };
-        if let GridCell::Path(_, Some(item)) = cell {
-            let translation = coords_to_vec(x, y, 0.5);
+        if let GridCell::Teleporter(_, Coordinates(to_x, to_y)) = cell {
+            let translation = coords_to_vec(x, y, 0.1);
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: goal.clone(),
+                    transform: Transform {
+                        translation,
+                        ..Default::default()
+                    },
+                    sprite: Sprite {
+                        color: Color::CYAN,
+                        custom_size: Some(tile_size / 2.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(EntityTooltip(format!("Teleporter to ({}, {})", to_x, to_y)));
+        }
+        if let GridCell::Trap(_) = cell {
+            let translation = coords_to_vec(x, y, 0.1);
             commands
                 .spawn_bundle(SpriteBundle {
-                    texture: item_sprite.clone(),
+                    texture: wall.clone(),
                     transform: Transform {
                         translation,
+                        rotation: Quat::from_rotation_z(FRAC_PI_4),
                         ..Default::default()
                     },
                     sprite: Sprite {
-                        custom_size: Some(tile_size),
+                        color: Color::ORANGE_RED,
+                        custom_size: Some(tile_size / 3.),
                         ..Default::default()
                     },
                     ..Default::default()
                 })
-                .insert(EntityTooltip(item.short_description().to_string()));
+                .insert(EntityTooltip("Trap".to_string()));
+        }
+        if let GridCell::Path(_, Some(item)) = cell {
+            spawn_item(
+                commands,
+                item_sprite.clone(),
+                Coordinates(x, y),
+                item.as_ref(),
+            );
         }
         let translation = coords_to_vec(x, y, 0.);
         sprites.push(SpriteBundle {
@@ -250,6 +652,7 @@ pub fn setup_game(
                 ..Default::default()
             },
             sprite: Sprite {
+                color,
                 custom_size: Some(tile_size),
                 ..Default::default()
             },
@@ -273,22 +676,12 @@ pub fn setup_game(
     }
     commands.spawn_batch(sprites);
 
-    let mut player_names = vec![];
-    let mut players = vec![];
-    for (num, sprite, name, ptype, spawn_pos) in izip!(
-        0..settings.players(),
-        settings.player_sprites_iter(),
-        settings.player_names_iter(),
-        settings.player_types_iter(),
-        map.starting_positions()
-    ) {
-        let Coordinates(x, y) = spawn_pos;
-        player_names.push(name.clone());
-        let player = Player::spawn_at(*spawn_pos, name.clone(), num, *ptype);
-        players.push(player);
-
+    for (player, sprite) in players.iter().zip(&game_state.player_sprites) {
+        let num = player.player_number();
+        let Coordinates(x, y) = player.position();
         let texture = asset_server.load(sprite.path());
-        let translation = coords_to_vec(*x, *y, 1.);
+        let translation = coords_to_vec(x, y, 1.);
+        let [r, g, b] = game_state.player_color(num);
 
         commands
             .spawn_bundle(SpriteBundle {
@@ -298,147 +691,1179 @@ pub fn setup_game(
                     ..Default::default()
                 },
                 sprite: Sprite {
+                    color: Color::rgb_u8(r, g, b),
                     custom_size: Some(tile_size / 2.),
                     ..Default::default()
                 },
                 ..Default::default()
             })
-            .insert(EntityTooltip(name.clone()))
+            .insert(EntityTooltip(player.name().to_string()))
             .insert(PlayerNumber(num));
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::YELLOW,
+                    custom_size: Some(Vec2::new(24., 8.)),
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(DirectionIndicator(num));
+    }
+    if let Some(ghost) = &game_state.ghost {
+        let Coordinates(x, y) = ghost.position();
+        let sprite = &game_state.player_sprites[ghost.player()];
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: asset_server.load(sprite.path()),
+                transform: Transform {
+                    translation: coords_to_vec(x, y, 0.9),
+                    ..Default::default()
+                },
+                sprite: Sprite {
+                    color: Color::rgba(1., 1., 1., 0.4),
+                    custom_size: Some(tile_size / 2.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(EntityTooltip("Ghost".to_string()))
+            .insert(GhostSprite);
     }
     commands.insert_resource(players);
     commands.insert_resource(map);
 
-    let texture = asset_server.load("sprites/DieFaces.png");
-    let texture_atlas = TextureAtlas::from_grid(texture, Vec2::splat(32.), 6, 1);
-    let texture_atlas = texture_atlases.add(texture_atlas);
-    // let translation = Vec2::new(width as f32 / 2. - 20., height as f32 / 2. - 20.).extend(0.);
-    commands.spawn_bundle(SpriteSheetBundle {
-        texture_atlas,
-        // transform: Transform {
-        //     translation,
-        //     ..Default::default()
-        // },
-        visibility: Visibility { is_visible: false },
-        ..Default::default()
-    });
+    game_state.die_atlas = DieAtlas::choose(
+        settings.custom_die_atlas(),
+        settings.die_atlas_columns(),
+        settings.die_atlas_rows(),
+        settings.die_faces(),
+    );
+    let die_faces = asset_server.load(game_state.die_atlas.path.as_str());
+    egui_context.set_egui_texture(DIE_FACES_TEXTURE, die_faces);
+    game_state.sounds = SoundEffects::load(asset_server);
+    game_state.item_texture = item_sprite;
+    commands.insert_resource(game_state);
+}
+
+fn spawn_item(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    Coordinates(x, y): Coordinates,
+    item: &dyn Item,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture,
+            transform: Transform::from_xyz(x as f32 * 96., y as f32 * 96., 0.5),
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(96.)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(EntityTooltip(item.short_description().to_string()));
+}
+
+// Loads a map designed or saved earlier, falling back to a generated map if it
+// can't be used
+#[cfg(feature = "serde")]
+fn load_map_file(path: &str, players: usize) -> Option<Map> {
+    match Map::from_file(path) {
+        Ok(map) if map.starting_positions().len() >= players => {
+            info!(path, "Loaded map from file");
+            Some(map)
+        }
+        Ok(_) => {
+            warn!(path, players, "Map file has too few starting positions");
+            None
+        }
+        Err(error) => {
+            warn!(path, %error, "Failed to load map file");
+            None
+        }
+    }
+}
+
+fn generate_map(settings: &GameSettings, seed: u64) -> Map {
+    let mut map = Map::generate_random_map_seeded(
+        settings.map_width(),
+        settings.map_height(),
+        settings.players(),
+        settings.item_density(),
+        settings.item_bias(),
+        settings.travel_distance(),
+        settings.die_faces(),
+        settings.teleporter_density(),
+        settings.trap_density(),
+        settings.num_goals(),
+        settings.map_generator(),
+        seed,
+    );
+    if settings.toroidal_map() {
+        map.make_toroidal();
+    }
+    map
+}
+
+// Sets up the map, players and game state for a new game. Games on generated
+// maps are recorded so that they can be watched again; every map is seeded so
+// that the replay can generate it again.
+fn new_game(settings: &GameSettings) -> (GameState, Map, PlayerList) {
+    let seed = settings.map_seed().unwrap_or_else(rand::random);
+    let turn_order = seeded_turn_order(settings.players(), settings.shuffle_turn_order(), seed);
+    #[cfg(feature = "serde")]
+    let custom_map = settings
+        .map_file()
+        .and_then(|path| load_map_file(path, settings.players()));
+    #[cfg(not(feature = "serde"))]
+    let custom_map = None;
+    if let Some(mut map) = custom_map {
+        if !settings.items_enabled() {
+            map.remove_items();
+        }
+        if settings.toroidal_map() {
+            map.make_toroidal();
+        }
+        return start_game(settings, map, turn_order);
+    }
+    let replay = Replay::new(seed, settings.clone(), turn_order.clone());
+    let (mut game_state, map, players) =
+        start_game(settings, generate_map(settings, seed), turn_order);
+    game_state.replay = Some(ReplaySession::recording(replay));
+    (game_state, map, players)
+}
+
+// The shuffle gets its own generator seeded from the map seed, so a game with
+// a fixed seed always starts with the same turn order
+fn seeded_turn_order(players: usize, shuffle: bool, seed: u64) -> Vec<usize> {
+    player::turn_order(players, shuffle, &mut StdRng::seed_from_u64(seed))
+}
+
+// Sets up a game that plays back a recorded replay on the same map
+fn replay_game(replay: Replay) -> (GameState, Map, PlayerList) {
+    let settings = replay.settings();
+    let map = generate_map(settings, replay.seed());
+    let (mut game_state, map, players) = start_game(settings, map, replay.turn_order().to_vec());
+    // Nobody is taking these turns, so there's nothing to time
+    game_state.turn_time_limit = None;
+    game_state.replay = Some(ReplaySession::playback(replay));
+    (game_state, map, players)
+}
+
+// Sets up a new game on the replay's map in which the first human player
+// races against a ghost of their recorded run. The new game is recorded too.
+fn ghost_game(replay: Replay) -> (GameState, Map, PlayerList) {
+    let settings = replay.settings();
+    let map = generate_map(settings, replay.seed());
+    let turn_order = replay.turn_order().to_vec();
+    let recording = Replay::new(replay.seed(), settings.clone(), turn_order.clone());
+    let (mut game_state, map, players) = start_game(settings, map, turn_order);
+    let player = settings
+        .player_types_iter()
+        .position(|ptype| matches!(ptype, PlayerType::LocalHuman))
+        .unwrap_or(0);
+    let nearest_goal = |from| {
+        map.goals()
+            .copied()
+            .min_by_key(|goal| map.grid_distance(from, *goal))
+            .unwrap_or(from)
+    };
+    let ghost = Ghost::new(&replay, player, players[player].position(), nearest_goal);
+    game_state.ghost = Some(ghost);
+    game_state.replay = Some(ReplaySession::recording(recording));
+    (game_state, map, players)
+}
 
-    commands.insert_resource(GameState {
+fn start_game(
+    settings: &GameSettings,
+    map: Map,
+    turn_order: Vec<usize>,
+) -> (GameState, Map, PlayerList) {
+    let mut player_names = vec![];
+    let mut player_sprites = vec![];
+    let mut player_colors = vec![];
+    let mut players = vec![];
+    let mut teams = vec![];
+    for (num, sprite, name, ptype, spawn_pos) in izip!(
+        0..settings.players(),
+        settings.player_sprites_iter(),
+        settings.player_names_iter(),
+        settings.player_types_iter(),
+        map.starting_positions()
+    ) {
+        player_names.push(name.clone());
+        player_sprites.push(*sprite);
+        player_colors.push(settings.player_color(num));
+        // Time attack is a race against the clock, not the computer
+        let ptype = if settings.time_attack() {
+            PlayerType::LocalHuman
+        } else {
+            *ptype
+        };
+        let mut player = Player::spawn_at(*spawn_pos, name.clone(), num, ptype);
+        player.set_team(settings.team(num));
+        let die = settings
+            .starting_die(num)
+            .unwrap_or_else(|| WeightedDie::fair_die_with_faces(settings.die_faces()));
+        player.set_die_weights(die.weights());
+        player.set_probability_floor(settings.probability_floor());
+        player.set_max_inventory(settings.max_inventory());
+        teams.push(player.team());
+        players.push(player);
+    }
+
+    let game_state = GameState {
         player_count: settings.players(),
+        active_player: turn_order[0],
+        inspector_player: turn_order[0],
+        turn_order,
         player_names,
+        player_sprites,
+        player_colors,
         camera_follows_player: true,
         camera_auto_zoom: true,
         camera_default_zoom: settings.default_zoom_level(),
         tile_walk_time: 1. / settings.walking_speed(),
+        reduced_motion: settings.reduced_motion(),
+        pickup_radius: settings.item_pickup_radius(),
+        show_directions: settings.show_directions(),
+        reveal_opponent_dice: settings.reveal_opponent_dice(),
+        hide_opponent_dice: settings.hide_opponent_dice(),
+        reveal_opponent_inventories: settings.reveal_opponent_inventories(),
+        hide_standings: !settings.show_standings(),
+        teams,
+        team_win_condition: settings.team_win_condition(),
+        win_condition: settings.win_condition(),
+        friendly_fire: settings.friendly_fire(),
+        die_size: settings.die_size(),
+        die_corner: settings.die_corner(),
+        ui_scale: settings.ui_scale() as f32,
+        peek_remaining: Duration::from_secs_f32(settings.map_peek_duration().max(0.)),
+        time_attack: settings.time_attack(),
+        time_attack_best: settings.time_attack_best(),
+        items_before_roll: settings.items_before_roll(),
+        diagonal_movement: settings.diagonal_movement(),
+        pure_race: !settings.items_enabled(),
+        collapse_on_roll: settings.collapse_on_roll(),
+        fair_dice: fair_dice_rngs(settings.fair_dice_seed(), settings.players()),
+        turn_time_limit: settings.turn_time_limit(),
         ..Default::default()
-    });
+    };
+    (game_state, map, players)
 }
 
-fn get_control(keyboard: &Res<Input<KeyCode>>) -> Option<Control> {
-    if keyboard.just_released(KeyCode::R) {
-        return Some(Control::Roll);
-    }
-    if keyboard.just_released(KeyCode::E) {
-        return Some(Control::Inventory);
-    }
-    if keyboard.just_released(KeyCode::W) {
-        return Some(Control::Move(NORTH));
-    }
-    if keyboard.just_released(KeyCode::A) {
-        return Some(Control::Move(WEST));
+// In fair mode every player rolls from the same seed, offset by their player
+// number. Items that change the weights still make the rolls diverge.
+fn fair_dice_rngs(seed: Option<u64>, players: usize) -> Vec<StdRng> {
+    match seed {
+        Some(seed) => (0..players as u64)
+            .map(|num| StdRng::seed_from_u64(seed.wrapping_add(num)))
+            .collect(),
+        None => vec![],
     }
-    if keyboard.just_released(KeyCode::S) {
-        return Some(Control::Move(SOUTH));
-    }
-    if keyboard.just_released(KeyCode::D) {
-        return Some(Control::Move(EAST));
-    }
-    if keyboard.just_released(KeyCode::Return) {
-        return Some(Control::EndTurn);
-    }
-    None
 }
 
-fn end_turn(game_state: &mut ResMut<GameState>) {
+fn turn_controls(bindings: &KeyBindings) -> [(KeyCode, Control); 12] {
+    [
+        (bindings.roll, Control::Roll),
+        (bindings.inventory, Control::Inventory),
+        (bindings.north, Control::Move(NORTH)),
+        (bindings.west, Control::Move(WEST)),
+        (bindings.south, Control::Move(SOUTH)),
+        (bindings.east, Control::Move(EAST)),
+        (bindings.north_east, Control::Move(NORTHEAST)),
+        (bindings.north_west, Control::Move(NORTHWEST)),
+        (bindings.south_east, Control::Move(SOUTHEAST)),
+        (bindings.south_west, Control::Move(SOUTHWEST)),
+        (bindings.stop, Control::Stop),
+        (bindings.end_turn, Control::EndTurn),
+    ]
+}
+
+// Keys that are handled outside of the turn controls and can't be rebound
+const OTHER_BINDINGS: [(KeyCode, &str); 11] = [
+    (KeyCode::M, "Look at the whole map after scouting it"),
+    (KeyCode::Z, "Zoom the camera automatically"),
+    (KeyCode::C, "Follow the active player with the camera"),
+    (KeyCode::Up, "Pan the camera north"),
+    (KeyCode::Down, "Pan the camera south"),
+    (KeyCode::Right, "Pan the camera east"),
+    (KeyCode::Left, "Pan the camera west"),
+    (KeyCode::F, "Fit all players in view"),
+    (
+        KeyCode::Return,
+        "Use the selected item while the inventory is open",
+    ),
+    (KeyCode::I, "Pause and inspect a computer player's turn"),
+    (KeyCode::F1, "Show or hide the list of controls"),
+];
+
+// Diagonal moves are ignored unless the game allows them, and there is no
+// inventory to open in a pure race
+fn get_control(
+    keyboard: &Res<Input<KeyCode>>,
+    bindings: &KeyBindings,
+    game_state: &GameState,
+) -> Option<Control> {
+    turn_controls(bindings)
+        .into_iter()
+        .filter(|(_, control)| match control {
+            Control::Move(direction) if is_diagonal(*direction) => game_state.diagonal_movement,
+            Control::Inventory => !game_state.pure_race,
+            _ => true,
+        })
+        .find(|(key, _)| keyboard.just_released(*key))
+        .map(|(_, control)| control)
+}
+
+fn help_entries(bindings: &KeyBindings) -> Vec<(String, &'static str)> {
+    turn_controls(bindings)
+        .into_iter()
+        .map(|(key, control)| (format!("{:?}", key), control.description()))
+        .chain(std::iter::once((
+            format!("{:?}", bindings.pause),
+            "Pause the game",
+        )))
+        .chain(
+            OTHER_BINDINGS
+                .iter()
+                .map(|(key, description)| (format!("{:?}", key), *description)),
+        )
+        .collect()
+}
+
+fn end_turn(game_state: &mut GameState) {
+    let ended = game_state.active_player;
+    if let Some(ghost) = game_state.ghost.as_mut() {
+        ghost.turn_ended(ended);
+    }
+    game_state.turn_number += 1;
     game_state.rolled_value = None;
     game_state.inventory_visible = false;
-    loop {
-        game_state.active_player = (game_state.active_player + 1) % game_state.player_count;
-        if !game_state.winners.contains(&game_state.active_player) {
-            break;
-        }
-    }
+    game_state.selected_item = None;
+    game_state.active_player = player::next_in_turn_order(
+        &game_state.turn_order,
+        game_state.active_player,
+        &game_state.winners,
+    );
+    info!(player = game_state.active_player, "Next turn");
     game_state.inspector_player = game_state.active_player;
     game_state.current_action = GameAction::WaitForInput;
     game_state.item_preview = ItemUsePreview::default();
     game_state.hover_item = None;
     game_state.picked_up_item = None;
+    game_state.status_message = None;
+    game_state.used_item_before_roll = false;
+    game_state.planned_route.clear();
+    game_state.turn_elapsed = Duration::ZERO;
 }
 
-pub fn update_die(
-    game_state: Res<GameState>,
-    mut query: Query<(&mut Visibility, &mut TextureAtlasSprite)>,
-) {
-    for (mut visibility, mut sprite) in query.iter_mut() {
-        match game_state.rolled_value {
-            None => visibility.is_visible = false,
-            Some(value) => {
-                visibility.is_visible = true;
-                sprite.index = value as usize - 1;
-            }
+// Names of the players who hadn't reached the goal when the game ended
+fn unfinished_players(game_state: &GameState) -> Vec<&str> {
+    game_state
+        .player_names
+        .iter()
+        .enumerate()
+        .filter(|(num, _)| !game_state.winners.contains(num))
+        .map(|(_, name)| name.as_str())
+        .collect()
+}
+
+// Ends the game and adds it to the match history
+fn finish_game(game_state: &mut GameState, history: &mut MatchHistory) {
+    game_state.game_over = true;
+    // Replays that were played back aren't new matches
+    match game_state
+        .replay
+        .as_mut()
+        .map(|session| session.finish(&game_state.winners))
+    {
+        Some(ReplayOutcome::Recorded(replay)) => replay::write_replay(replay),
+        Some(ReplayOutcome::Matched) => {
+            game_state.status_message =
+                Some("The replay ended the same way as the recorded game".to_string());
+            return;
+        }
+        Some(ReplayOutcome::Diverged) => {
+            warn!("Replay didn't end the same way as the recorded game");
+            game_state.status_message =
+                Some("The replay didn't end the same way as the recorded game".to_string());
+            return;
         }
+        None => {}
     }
+    let places = player::finishing_places(&game_state.finish_times);
+    let finishing_order = places
+        .into_iter()
+        .zip(game_state.winner_names.iter().cloned())
+        .collect();
+    history.add_record(MatchRecord::new(
+        game_state.player_names.clone(),
+        finishing_order,
+        game_state.turn_number + 1,
+    ));
 }
 
-pub fn entity_tooltips(
-    mut game_state: ResMut<GameState>,
-    windows: Res<Windows>,
-    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    item_query: Query<(&GlobalTransform, &EntityTooltip)>,
-) {
-    // https://bevy-cheatbook.github.io/cookbook/cursor2world.html
-    let (camera, camera_transform) = camera_query.single();
-
-    let threshold = 96.0 / 2.0f32.sqrt();
-
-    let wnd = windows.get(camera.window).unwrap();
+// Using an item before rolling still leaves the roll to be made, while using
+// one after moving ends the turn
+fn finish_item_use(game_state: &mut GameState) {
+    if game_state.rolled_value.is_none() {
+        game_state.used_item_before_roll = true;
+        game_state.current_action = GameAction::WaitForInput;
+    } else {
+        end_turn(game_state);
+    }
+}
 
-    if let Some(screen_pos) = wnd.cursor_position() {
-        let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+const DIE_FACES_TEXTURE: u64 = 0;
 
-        // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
-        let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0)).truncate();
+#[derive(Debug, PartialEq)]
+struct DieAtlas {
+    path: String,
+    columns: usize,
+    rows: usize,
+}
 
-        for (transform, EntityTooltip(description)) in item_query.iter() {
-            if world_pos.distance(transform.translation.truncate()) < threshold {
-                game_state.hover_item = Some(description.clone());
-                return;
-            }
+impl Default for DieAtlas {
+    fn default() -> Self {
+        DieAtlas {
+            path: "sprites/DieFaces.png".to_string(),
+            columns: 6,
+            rows: 1,
+        }
+    }
+}
+
+impl DieAtlas {
+    // Uses the custom atlas if one is set, exists, and has a frame for every
+    // face of the die. Otherwise falls back to the default die faces.
+    fn choose(custom: &str, columns: usize, rows: usize, faces: usize) -> Self {
+        if custom.is_empty() {
+            return DieAtlas::default();
+        }
+        if columns * rows < faces {
+            warn!(columns, rows, faces, "Custom die atlas has too few frames");
+            return DieAtlas::default();
+        }
+        // Assets are loaded relative to the asset directory, so custom images
+        // are referred to by their absolute path
+        let path = match std::fs::canonicalize(custom) {
+            Ok(path) if path.is_file() => path,
+            _ => {
+                warn!(path = custom, "Custom die atlas not found");
+                return DieAtlas::default();
+            }
+        };
+        DieAtlas {
+            path: path.to_string_lossy().to_string(),
+            columns,
+            rows,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    fn uv(&self, index: usize) -> egui::Rect {
+        let (columns, rows) = (self.columns as f32, self.rows as f32);
+        let column = (index % self.columns) as f32;
+        let row = (index / self.columns) as f32;
+        egui::Rect::from_min_max(
+            egui::pos2(column / columns, row / rows),
+            egui::pos2((column + 1.) / columns, (row + 1.) / rows),
+        )
+    }
+}
+
+// Index of the face to show in the die face sprite sheet
+fn die_face_index(rolled_value: Option<u32>) -> Option<usize> {
+    rolled_value.map(|value| value as usize - 1)
+}
+
+pub fn update_die(mut egui_context: ResMut<EguiContext>, game_state: Res<GameState>) {
+    let index = match die_face_index(game_state.rolled_value) {
+        Some(index) => index,
+        None => return,
+    };
+    let margin = 10.;
+    let (anchor, offset) = match game_state.die_corner {
+        ScreenCorner::TopLeft => (
+            egui::Align2::LEFT_TOP,
+            egui::vec2(game_state.left_panel_width + margin, margin),
+        ),
+        ScreenCorner::TopRight => (
+            egui::Align2::RIGHT_TOP,
+            egui::vec2(-game_state.right_panel_width - margin, margin),
+        ),
+        ScreenCorner::BottomLeft => (
+            egui::Align2::LEFT_BOTTOM,
+            egui::vec2(game_state.left_panel_width + margin, -margin),
+        ),
+        ScreenCorner::BottomRight => (
+            egui::Align2::RIGHT_BOTTOM,
+            egui::vec2(-game_state.right_panel_width - margin, -margin),
+        ),
+    };
+    egui::Area::new("Rolled die")
+        .anchor(anchor, offset)
+        .show(egui_context.ctx_mut(), |ui| {
+            // Dice with more faces than the atlas has frames show the number instead
+            if index < game_state.die_atlas.frames() {
+                ui.add(
+                    egui::Image::new(
+                        egui::TextureId::User(DIE_FACES_TEXTURE),
+                        egui::Vec2::splat(game_state.die_size),
+                    )
+                    .uv(game_state.die_atlas.uv(index)),
+                );
+            } else {
+                ui.add_sized(
+                    egui::Vec2::splat(game_state.die_size),
+                    egui::Label::new(
+                        egui::RichText::new((index + 1).to_string())
+                            .size(game_state.die_size / 2.)
+                            .strong(),
+                    ),
+                );
+            }
+        });
+}
+
+// Moves the ghost to where it stood after as many turns as the live player has
+// taken
+pub fn update_ghost(
+    game_state: Res<GameState>,
+    mut query: Query<&mut Transform, With<GhostSprite>>,
+) {
+    let ghost = match &game_state.ghost {
+        Some(ghost) => ghost,
+        None => return,
+    };
+    let Coordinates(x, y) = ghost.position();
+    for mut transform in query.iter_mut() {
+        transform.translation = Vec3::new(x as f32 * 96., y as f32 * 96., 0.9);
+    }
+}
+
+pub fn update_direction_indicators(
+    game_state: Res<GameState>,
+    players: Res<PlayerList>,
+    mut query: Query<(&DirectionIndicator, &mut Transform, &mut Visibility)>,
+) {
+    for (DirectionIndicator(num), mut transform, mut visibility) in query.iter_mut() {
+        let player = &players[*num];
+        let (offset, angle) = match player.last_move() {
+            NORTH => (Vec2::new(0., 1.), FRAC_PI_2),
+            SOUTH => (Vec2::new(0., -1.), -FRAC_PI_2),
+            EAST => (Vec2::new(1., 0.), 0.),
+            WEST => (Vec2::new(-1., 0.), PI),
+            NORTHEAST => (Vec2::new(1., 1.).normalize(), FRAC_PI_4),
+            NORTHWEST => (Vec2::new(-1., 1.).normalize(), 3. * FRAC_PI_4),
+            SOUTHEAST => (Vec2::new(1., -1.).normalize(), -FRAC_PI_4),
+            SOUTHWEST => (Vec2::new(-1., -1.).normalize(), -3. * FRAC_PI_4),
+            _ => {
+                visibility.is_visible = false;
+                continue;
+            }
+        };
+        visibility.is_visible = game_state.show_directions;
+        let Coordinates(x, y) = player.position();
+        let center = Vec2::new(x as f32 * 96., y as f32 * 96.);
+        transform.translation = (center + offset * 32.).extend(1.5);
+        transform.rotation = Quat::from_rotation_z(angle);
+    }
+}
+
+pub fn update_step_numbers(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game_state: ResMut<GameState>,
+    players: Res<PlayerList>,
+    map: Res<Map>,
+    query: Query<Entity, With<StepNumber>>,
+) {
+    let corridor = match (&game_state.current_action, game_state.current_move) {
+        (GameAction::Moving(_, remaining), Some(direction)) if game_state.show_step_numbers => {
+            let position = players[game_state.active_player].position();
+            map.corridor_from(position, direction, *remaining)
+        }
+        _ => vec![],
+    };
+    if corridor == game_state.numbered_tiles {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (i, Coordinates(x, y)) in corridor.iter().enumerate() {
+        let translation = Vec3::new(*x as f32 * 96., *y as f32 * 96., 2.);
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    (i + 1).to_string(),
+                    TextStyle {
+                        color: Color::WHITE,
+                        font_size: 48.,
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                transform: Transform::from_translation(translation),
+                ..Default::default()
+            })
+            .insert(StepNumber);
+    }
+    game_state.numbered_tiles = corridor;
+}
+
+// Highlights the tiles the active player could end up on with the number they
+// rolled until they take their first step
+pub fn update_reachable_highlights(
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    players: Res<PlayerList>,
+    map: Res<Map>,
+    query: Query<Entity, With<ReachableHighlight>>,
+) {
+    let reachable = match game_state.current_action {
+        GameAction::Moving(_, remaining) if remaining == game_state.total_steps => {
+            let player = &players[game_state.active_player];
+            map.reachable_in(
+                player.position(),
+                player.last_move(),
+                remaining,
+                game_state.diagonal_movement,
+            )
+        }
+        _ => vec![],
+    };
+    if reachable == game_state.highlighted_tiles {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for Coordinates(x, y) in reachable.iter() {
+        let translation = Vec3::new(*x as f32 * 96., *y as f32 * 96., 0.3);
+        commands
+            .spawn_bundle(SpriteBundle {
+                transform: Transform::from_translation(translation),
+                sprite: Sprite {
+                    color: Color::rgba(1., 1., 0.4, 0.3),
+                    custom_size: Some(Vec2::splat(96.)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(ReachableHighlight);
+    }
+    game_state.highlighted_tiles = reachable;
+}
+
+/// Converts a position in the window to world coordinates. The camera's
+/// projection always spans the window size, so the conversion only depends on
+/// the current window size and not on a projection matrix that is updated
+/// after the window is resized.
+fn screen_to_world(screen_pos: Vec2, window_size: Vec2, camera_pos: Vec2, scale: f32) -> Vec2 {
+    camera_pos + (screen_pos - window_size / 2.) * scale
+}
+
+pub fn entity_tooltips(
+    mut game_state: ResMut<GameState>,
+    windows: Res<Windows>,
+    camera_query: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
+    item_query: Query<(&GlobalTransform, &EntityTooltip)>,
+    map: Res<Map>,
+) {
+    let (camera_transform, projection) = camera_query.single();
+
+    let threshold = 96.0 / 2.0f32.sqrt();
+
+    let wnd = windows.get_primary().unwrap();
+
+    if let Some(screen_pos) = wnd.cursor_position() {
+        let window_size = Vec2::new(wnd.width(), wnd.height());
+        let world_pos = screen_to_world(
+            screen_pos,
+            window_size,
+            camera_transform.translation.truncate(),
+            projection.scale,
+        );
+
+        for (transform, EntityTooltip(description)) in item_query.iter() {
+            if world_pos.distance(transform.translation.truncate()) < threshold {
+                game_state.hover_item = Some(description.clone());
+                return;
+            }
+        }
+
+        // Tiles are centered on multiples of the tile size
+        let tile = (world_pos / 96.).round();
+        if tile.x >= 0. && tile.y >= 0. {
+            let (x, y) = (tile.x as usize, tile.y as usize);
+            if x < map.width() && y < map.height() {
+                game_state.hover_item = Some(tile_description(map.cell_at(Coordinates(x, y))));
+                return;
+            }
         }
     }
     game_state.hover_item = None;
 }
 
+// Lists the directions in which a tile can be left, such as "N, E"
+fn exit_names(exits: Direction) -> String {
+    [(NORTH, "N"), (SOUTH, "S"), (EAST, "E"), (WEST, "W")]
+        .into_iter()
+        .filter(|(direction, _)| exits & direction != 0)
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn tile_description(cell: &GridCell) -> String {
+    match cell {
+        GridCell::Wall => "Wall".to_string(),
+        GridCell::Path(exits, _) => format!("Path: {}", exit_names(*exits)),
+        GridCell::Goal(exits) => format!("Goal: {}", exit_names(*exits)),
+        GridCell::Teleporter(exits, Coordinates(x, y)) => {
+            format!("Teleporter to ({}, {}): {}", x, y, exit_names(*exits))
+        }
+        GridCell::Trap(exits) => format!("Trap: {}", exit_names(*exits)),
+    }
+}
+
+// The players still racing and their distances to the goal, closest first.
+// Players with no way to the goal are left out.
+fn standings(game_state: &GameState, players: &[Player], map: &Map) -> Vec<(usize, usize)> {
+    let mut standings: Vec<_> = game_state
+        .turn_order
+        .iter()
+        .filter(|num| !game_state.winners.contains(num))
+        .filter_map(|&num| {
+            map.distance_to_goal(players[num].position())
+                .map(|distance| (num, distance))
+        })
+        .collect();
+    standings.sort_by_key(|&(_, distance)| distance);
+    standings
+}
+
 fn clear_move(game_state: &mut GameState) {
     game_state.current_move = None;
     game_state.time_since_last_move = Duration::ZERO;
 }
 
-fn computer_use_item(game_state: &GameState, players: &mut PlayerList) {
+// Rolls the active player's die. In fair mode the player rolls with their own
+// seeded generator. Otherwise recorded games roll with the replay's seeded
+// generator so that playing them back gives the same rolls.
+fn roll_die(game_state: &mut GameState, players: &mut [Player]) -> u32 {
+    let num = game_state.active_player;
+    let collapse = game_state.collapse_on_roll;
+    let mut thread_rng = rand::thread_rng();
+    let rng: &mut dyn RngCore = match (
+        game_state.fair_dice.get_mut(num),
+        game_state.replay.as_mut(),
+    ) {
+        (Some(fair_rng), _) => fair_rng,
+        (None, Some(session)) => session.rng(),
+        (None, None) => &mut thread_rng,
+    };
+    let rolled = roll_player_die(num, collapse, players, rng);
+    players[num].record_roll(rolled);
+    game_state.record(ReplayEvent::Roll {
+        player: num,
+        rolled,
+    });
+    let entry = format!("{} rolled {}", game_state.player_names[num], rolled);
+    game_state.log_event(entry);
+    rolled
+}
+
+// If the die is entangled with another player's, the roll measures the pair
+// and conditions the other die. The pair is rebuilt from both dice as they are
+// now, so items used on either die since the entanglement still count.
+fn roll_player_die(
+    num: usize,
+    collapse: bool,
+    players: &mut [Player],
+    rng: &mut dyn RngCore,
+) -> u32 {
+    let unmeasured_partner = players[num]
+        .entanglement()
+        .filter(|pair| pair.measured().is_none())
+        .map(EntangledPair::second);
+    if let Some(partner) = unmeasured_partner {
+        let mut pair = EntangledPair::new(num, partner, players[num].die(), players[partner].die());
+        let (rolled, partner_roll) = pair.measure_first_with(rng);
+        let weights = pair.second_weights().unwrap();
+        info!(
+            player = num,
+            partner, rolled, partner_roll, "Measured entangled dice"
+        );
+        players[partner].set_die_weights(weights);
+        players[num].set_entanglement(pair);
+        return rolled;
+    }
+    let player = &mut players[num];
+    if collapse {
+        player.roll_and_collapse_with(rng)
+    } else {
+        player.roll_with(rng)
+    }
+}
+
+fn start_moving(game_state: &mut GameState, player: &mut Player, rolled: u32) {
+    let steps = player.steps_for_roll(rolled);
+    game_state.rolled_value = Some(rolled);
+    game_state.total_steps = steps;
+    game_state.current_action = GameAction::Moving(0, steps);
+}
+
+fn stop_moving(game_state: &mut GameState) {
+    game_state.current_action = GameAction::HasMoved;
+    clear_move(game_state);
+}
+
+// Ignore the direction from which the player came. If there is only one
+// direction in which the player can move, then move in that direction. A dead
+// end turns the player around so the remaining steps aren't lost. Otherwise
+// the player chooses at the junction.
+fn next_corridor_step(exits: Direction, step: Direction) -> Option<Direction> {
+    let backwards = get_opposite_direction(step);
+    match exits & !backwards {
+        available @ (NORTH | SOUTH | EAST | WEST) => Some(available),
+        0 if exits & backwards != 0 => Some(backwards),
+        _ => None,
+    }
+}
+
+// Whether the only way onward is back the way the player came
+fn must_turn_back(player: &Player, map: &Map) -> bool {
+    let last = player.last_move();
+    let allowed = player.allowed_moves(map);
+    last != 0 && allowed != 0 && allowed == allowed & get_opposite_direction(last)
+}
+
+// Ends the move early if the player has no valid moves left, so that they
+// don't get stuck with steps remaining
+fn resolve_no_moves(game_state: &mut GameState, player: &mut Player, map: &Map) -> bool {
+    if player.allowed_moves(map) != 0 {
+        return false;
+    }
+    warn!(player = player.player_number(), "No valid moves");
+    player.end_move(map);
+    stop_moving(game_state);
+    game_state.status_message = Some(format!(
+        "{} has nowhere left to go and stops here",
+        player.name()
+    ));
+    true
+}
+
+// Advances the walking timer and determines whether the active player may take
+// their next step. The timer is frozen while inspecting a computer's turn.
+fn advance_move_timer(game_state: &mut GameState, delta: Duration) -> bool {
+    if game_state.inspecting {
+        return false;
+    }
+    game_state.time_since_last_move += delta;
+    game_state.time_since_last_move.as_secs_f32() >= game_state.tile_walk_time
+}
+
+fn set_sprite_position(
+    player_query: &mut Query<(&PlayerNumber, &mut Transform, &mut Sprite)>,
+    player: usize,
+    position: Vec2,
+) {
+    for (num, mut transform, _) in player_query.iter_mut() {
+        if *num == player {
+            transform.translation = position.extend(1.);
+            break;
+        }
+    }
+}
+
+// Moves the walking player's sprite along towards the tile they stepped onto
+fn animate_walk(
+    game_state: &mut GameState,
+    player_query: &mut Query<(&PlayerNumber, &mut Transform, &mut Sprite)>,
+    delta: Duration,
+) {
+    let duration = game_state.walk_duration();
+    let animation = match &mut game_state.walk_animation {
+        Some(animation) => animation,
+        None => return,
+    };
+    animation.elapsed += delta.as_secs_f32();
+    set_sprite_position(player_query, animation.player, animation.position(duration));
+    if animation.elapsed >= duration {
+        game_state.walk_animation = None;
+    }
+}
+
+// Advances the turn timer. Returns whether the active player just ran out of
+// time, in which case the timer starts over.
+fn tick_turn_timer(game_state: &mut GameState, ptype: PlayerType, delta: Duration) -> bool {
+    if game_state.paused || game_state.game_over {
+        return false;
+    }
+    if game_state.turn_time_remaining(ptype).is_none() {
+        return false;
+    }
+    game_state.turn_elapsed += delta;
+    if game_state.turn_time_remaining(ptype) > Some(0.) {
+        return false;
+    }
+    game_state.turn_elapsed = Duration::ZERO;
+    true
+}
+
+// Ends the active player's turn, or the game if nobody is left to play
+fn finish_turn(game_state: &mut GameState, player: &mut Player, history: &mut MatchHistory) {
+    player.end_turn();
+    if game_state.is_over() {
+        info!("Game over");
+        finish_game(game_state, history);
+        history::write_history(history);
+    } else {
+        end_turn(game_state)
+    }
+}
+
+// Compares the live player's finishing turn with the ghost's
+fn race_result(ghost: &Ghost) -> String {
+    let turn = ghost.current_turn();
+    match ghost.finishing_turn() {
+        Some(ghost_turn) if ghost_turn < turn => {
+            format!("The ghost was faster, finishing on turn {}", ghost_turn)
+        }
+        Some(ghost_turn) if ghost_turn == turn => {
+            format!("You tied with the ghost on turn {}", turn)
+        }
+        Some(ghost_turn) => format!("You beat the ghost by {} turns", ghost_turn - turn),
+        None => "You beat the ghost, which never reached the goal".to_string(),
+    }
+}
+
+// Ends the turn on the player's request. If that would end the match, the
+// player is asked to confirm it first.
+fn request_end_turn(game_state: &mut GameState, player: &mut Player, history: &mut MatchHistory) {
+    if game_state.is_over() && !game_state.playing_back() {
+        game_state.confirming_end = true;
+        return;
+    }
+    game_state.record(ReplayEvent::EndTurn {
+        player: game_state.active_player,
+        at: Some(player.position()),
+    });
+    finish_turn(game_state, player, history);
+}
+
+/// How long a scouting player gets to look at the whole map at a time
+const SCOUT_PEEK_DURATION: Duration = Duration::from_secs(3);
+
+// Shows the whole map to a human player who has scouted it. Returns whether
+// the map is now being shown.
+fn start_scout_peek(game_state: &mut GameState, player: &Player) -> bool {
+    if player.get_type() != PlayerType::LocalHuman || !player.has_scouted() {
+        return false;
+    }
+    game_state.peek_remaining = SCOUT_PEEK_DURATION;
+    game_state.scouting = true;
+    true
+}
+
+// Counts down the map preview at the start of the game. Input is locked for
+// as long as the preview is still running.
+fn tick_map_peek(game_state: &mut GameState, delta: Duration) -> bool {
+    if game_state.peek_remaining.is_zero() {
+        return false;
+    }
+    game_state.peek_remaining = game_state.peek_remaining.saturating_sub(delta);
+    if game_state.peek_remaining.is_zero() {
+        game_state.scouting = false;
+    }
+    true
+}
+
+// Returns the user and the target of the item if one was used
+fn computer_use_item(
+    game_state: &mut GameState,
+    players: &mut PlayerList,
+    map: &Map,
+) -> Option<(usize, usize)> {
     let num = game_state.active_player;
     let choice = {
         let player = &players[num];
         if let PlayerType::Computer(_, algorithm) = player.get_type() {
-            algorithm.choose_item(player, players)
+            algorithm.choose_item(player, players, map)
         } else {
             None
         }
     };
-    if let Some((idx, target)) = choice {
-        let item = players[num].take_item(idx);
-        item.use_item(&mut players[target]);
+    let (idx, target) = choice?;
+    let mut item = players[num].take_item(idx);
+    info!(
+        user = num,
+        target,
+        item = item.short_description(),
+        "Computer used item"
+    );
+    let applied_to = use_item_on(item.as_ref(), num, target, players);
+    players[num].record_item_use();
+    game_state.log_item_use(num, item.item_type(), applied_to);
+    if item.consume_charge() {
+        players[num].return_item(idx, item);
+    }
+    game_state.record(ReplayEvent::UseItem {
+        user: num,
+        item: idx,
+        target,
+        finishes: false,
+        transfer: None,
+    });
+    Some((num, target))
+}
+
+// The next control from the replay being played back, once the game is ready
+// for it
+fn replay_control(game_state: &GameState) -> Option<Control> {
+    if game_state.paused || game_state.game_over {
+        return None;
+    }
+    let event = game_state.replay.as_ref()?.next_event()?;
+    let active = game_state.active_player;
+    match (event, &game_state.current_action) {
+        (ReplayEvent::Roll { player, .. }, GameAction::WaitForInput) if player == active => {
+            Some(Control::Roll)
+        }
+        (ReplayEvent::Step { player, direction }, GameAction::Moving(..))
+            if player == active && game_state.current_move.is_none() =>
+        {
+            Some(Control::Move(direction))
+        }
+        (ReplayEvent::Stop { player, remaining }, GameAction::Moving(_, left))
+            if player == active && remaining == *left =>
+        {
+            Some(Control::Stop)
+        }
+        (ReplayEvent::EndTurn { player, .. }, GameAction::HasMoved) if player == active => {
+            Some(Control::EndTurn)
+        }
+        _ => None,
+    }
+}
+
+// Uses or drops an item if that's what happens next in the replay being
+// played back. Returns whether an item was used or dropped.
+fn play_back_items(
+    commands: &mut Commands,
+    game_state: &mut GameState,
+    players: &mut PlayerList,
+    map: &mut Map,
+    player_query: &mut Query<(&PlayerNumber, &mut Transform, &mut Sprite)>,
+) -> bool {
+    if game_state.paused || game_state.game_over {
+        return false;
+    }
+    let event = match game_state
+        .replay
+        .as_ref()
+        .and_then(ReplaySession::next_event)
+    {
+        Some(event) => event,
+        None => return false,
+    };
+    let active = game_state.active_player;
+    match event {
+        ReplayEvent::UseItem {
+            user,
+            item: idx,
+            target,
+            finishes,
+            transfer,
+        } if user == active
+            && matches!(
+                game_state.current_action,
+                GameAction::WaitForInput | GameAction::HasMoved
+            ) =>
+        {
+            let mut item = players[user].take_item(idx);
+            let applied_to = use_configured_item(item.as_ref(), transfer, user, target, players);
+            players[user].record_item_use();
+            game_state.log_item_use(user, item.item_type(), applied_to);
+            if item.consume_charge() {
+                players[user].return_item(idx, item);
+            }
+            game_state.record(event);
+            place_sprites(game_state, players, player_query, [user, target]);
+            if finishes {
+                finish_item_use(game_state);
+            }
+            true
+        }
+        ReplayEvent::Drop { player, item, at }
+            if player == active && players[player].position() == at =>
+        {
+            if players[player].drop_item(item, map) {
+                if let GridCell::Path(_, Some(dropped)) = map.cell_at(at) {
+                    spawn_item(
+                        commands,
+                        game_state.item_texture.clone(),
+                        at,
+                        dropped.as_ref(),
+                    );
+                }
+            }
+            game_state.record(event);
+            true
+        }
+        _ => false,
+    }
+}
+
+// Custom weight transfers are used with the faces and strength the user picked
+// rather than the ones the item was found with
+fn use_configured_item(
+    item: &dyn Item,
+    transfer: Option<TransferParameters>,
+    source: usize,
+    target: usize,
+    players: &mut [Player],
+) -> usize {
+    match transfer {
+        Some(parameters) => {
+            let faces = players[source].die().faces();
+            let custom = WeightTransfer::new_custom(faces, parameters);
+            use_item_on(&custom, source, target, players)
+        }
+        None => use_item_on(item, source, target, players),
+    }
+}
+
+// Moves the sprites of players that an item may have moved to their tiles,
+// cutting short any walk that was still being shown
+fn place_sprites(
+    game_state: &mut GameState,
+    players: &[Player],
+    player_query: &mut Query<(&PlayerNumber, &mut Transform, &mut Sprite)>,
+    affected: [usize; 2],
+) {
+    if let Some(animation) = &game_state.walk_animation {
+        if affected.contains(&animation.player) {
+            game_state.walk_animation = None;
+        }
+    }
+    for num in affected {
+        let Coordinates(x, y) = players[num].position();
+        set_sprite_position(player_query, num, Vec2::new(x as f32 * 96., y as f32 * 96.));
     }
 }
 
@@ -448,29 +1873,105 @@ pub fn update_game(
     mut game_state: ResMut<GameState>,
     mut players: ResMut<PlayerList>,
     keyboard: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut map: ResMut<Map>,
     mut player_query: Query<(&PlayerNumber, &mut Transform, &mut Sprite)>,
     item_query: Query<(Entity, &Transform, &EntityTooltip), Without<PlayerNumber>>,
+    mut settings: ResMut<GameSettings>,
+    mut history: ResMut<MatchHistory>,
 ) {
-    if keyboard.just_released(KeyCode::Escape) {
+    if game_state.inventory_visible && !game_state.paused && keyboard.just_released(KeyCode::Escape)
+    {
+        // Escape closes the inventory before it pauses the game
+        game_state.inventory_visible = false;
+        game_state.selected_item = None;
+    } else if keyboard.just_released(key_bindings.pause) {
         game_state.paused = !game_state.paused;
     }
+    if tick_map_peek(&mut game_state, time.delta()) || game_state.confirming_end {
+        return;
+    }
+    if !game_state.paused {
+        animate_walk(&mut game_state, &mut player_query, time.delta());
+    }
+    if !game_state.paused && !game_state.game_over {
+        game_state.elapsed += time.delta();
+    }
     if keyboard.just_released(KeyCode::Z) {
         game_state.camera_auto_zoom = true;
     }
     if keyboard.just_released(KeyCode::C) {
         game_state.camera_follows_player = true;
     }
+    if play_back_items(
+        &mut commands,
+        &mut game_state,
+        &mut players,
+        &mut map,
+        &mut player_query,
+    ) {
+        return;
+    }
     let player = &mut players[game_state.active_player];
+    if keyboard.just_released(KeyCode::M) && start_scout_peek(&mut game_state, player) {
+        return;
+    }
+    // Replays take every turn the way a human player would, with the
+    // controls coming from the recording instead of the keyboard
+    let (ptype, control) = if game_state.playing_back() {
+        (PlayerType::LocalHuman, replay_control(&game_state))
+    } else {
+        (
+            player.get_type(),
+            get_control(&keyboard, &key_bindings, &game_state),
+        )
+    };
+    if ptype == PlayerType::LocalHuman {
+        game_state.inspecting = false;
+    } else if keyboard.just_released(KeyCode::I) {
+        game_state.inspecting = !game_state.inspecting;
+        if game_state.inspecting {
+            game_state.inspector_player = game_state.active_player;
+        }
+    }
+    if tick_turn_timer(&mut game_state, ptype, time.delta()) {
+        info!(player = game_state.active_player, "Turn time ran out");
+        game_state.inventory_visible = false;
+        game_state.item_preview = ItemUsePreview::default();
+        if game_state.rolled_value.is_none() {
+            let rolled = roll_die(&mut game_state, &mut players);
+            let player = &mut players[game_state.active_player];
+            start_moving(&mut game_state, player, rolled);
+        } else {
+            let num = game_state.active_player;
+            if let GameAction::Moving(_, remaining) = game_state.current_action {
+                game_state.record(ReplayEvent::Stop {
+                    player: num,
+                    remaining,
+                });
+                player.end_move(&map);
+                clear_move(&mut game_state);
+            }
+            game_state.record(ReplayEvent::EndTurn {
+                player: num,
+                at: Some(player.position()),
+            });
+            finish_turn(&mut game_state, player, &mut history);
+        }
+        return;
+    }
+    if game_state.inventory_visible && navigate_inventory(&keyboard, &mut game_state, player) {
+        return;
+    }
     match game_state.current_action {
-        GameAction::WaitForInput => match player.get_type() {
+        GameAction::WaitForInput => match ptype {
             PlayerType::LocalHuman => {
-                if let Some(action) = get_control(&keyboard) {
+                if let Some(action) = control {
                     match action {
                         Control::Roll => {
-                            let rolled = player.roll();
-                            game_state.rolled_value = Some(rolled);
-                            game_state.current_action = GameAction::Moving(0, rolled);
+                            let rolled = roll_die(&mut game_state, &mut players);
+                            let player = &mut players[game_state.active_player];
+                            start_moving(&mut game_state, player, rolled);
                         }
                         Control::Inventory => {
                             game_state.inventory_visible = !game_state.inventory_visible
@@ -479,52 +1980,77 @@ pub fn update_game(
                     }
                 }
             }
-            PlayerType::Computer(_, _) => {
-                let rolled = player.roll();
-                game_state.rolled_value = Some(rolled);
-                game_state.current_action = GameAction::Moving(0, rolled);
+            PlayerType::Computer(_, _) if !game_state.inspecting => {
+                let rolled = roll_die(&mut game_state, &mut players);
+                let player = &mut players[game_state.active_player];
+                start_moving(&mut game_state, player, rolled);
             }
+            PlayerType::Computer(_, _) => {}
         },
         GameAction::UsingItem => {}
         GameAction::Moving(_, remaining) => {
-            match player.get_type() {
+            if resolve_no_moves(&mut game_state, player, &map) {
+                return;
+            }
+            match ptype {
                 PlayerType::LocalHuman => {
+                    if let Some(Control::Stop) = control {
+                        game_state.record(ReplayEvent::Stop {
+                            player: game_state.active_player,
+                            remaining,
+                        });
+                        player.end_move(&map);
+                        stop_moving(&mut game_state);
+                        return;
+                    }
                     if game_state.current_move.is_none() {
-                        if let Some(Control::Move(step)) = get_control(&keyboard) {
+                        if let Some(Control::Move(step)) = control {
                             let previous = player.last_move();
-                            if directions_are_opposite(step, previous) {
-                                if let GridCell::Path(exits, _) = map.cell_at(player.position()) {
-                                    match *exits {
-                                        NORTH | SOUTH | EAST | WEST => {}
-                                        _ => return,
-                                    }
-                                } else {
-                                    panic!("Player not on a path");
-                                }
+                            if directions_are_opposite(step, previous)
+                                && step & player.allowed_moves(&map) == 0
+                            {
+                                return;
                             }
                             game_state.current_move = Some(step);
+                            game_state.record(ReplayEvent::Step {
+                                player: game_state.active_player,
+                                direction: step,
+                            });
                         }
-                    } else {
-                        game_state.time_since_last_move += time.delta();
-                        if game_state.time_since_last_move.as_secs_f32() < game_state.tile_walk_time
-                        {
-                            return;
-                        }
+                    } else if !advance_move_timer(&mut game_state, time.delta()) {
+                        return;
                     }
                 }
                 PlayerType::Computer(algorithm, _) => {
                     if game_state.current_move.is_none() {
-                        game_state.current_move =
-                            Some(algorithm.compute_move(player.position(), &map));
+                        let step = algorithm.compute_move(
+                            player.position(),
+                            player.last_move(),
+                            &map,
+                            &mut game_state.planned_route,
+                            &mut rand::thread_rng(),
+                        );
+                        game_state.current_move = Some(step);
+                        game_state.record(ReplayEvent::Step {
+                            player: game_state.active_player,
+                            direction: step,
+                        });
                     }
-                    game_state.time_since_last_move += time.delta();
-                    if game_state.time_since_last_move.as_secs_f32() < game_state.tile_walk_time {
+                    if !advance_move_timer(&mut game_state, time.delta()) {
                         return;
                     }
                 }
             }
             if let Some(step) = game_state.current_move {
+                let before = player.position();
                 if player.step(step, &map) {
+                    // A sprite still walking after the turn changed hands
+                    // jumps straight to where it was going
+                    if let Some(animation) = game_state.walk_animation.take() {
+                        if animation.player != game_state.active_player {
+                            set_sprite_position(&mut player_query, animation.player, animation.to);
+                        }
+                    }
                     let (mut transform, mut sprite) = {
                         let (mut transform, mut sprite) = (None, None);
                         for (num, t, s) in player_query.iter_mut() {
@@ -538,55 +2064,116 @@ pub fn update_game(
                     };
                     let position = player.position();
                     let Coordinates(x, y) = position;
-                    transform.translation = Vec2::new(x as f32 * 96., y as f32 * 96.).extend(1.);
-                    sprite.flip_x = step == WEST;
-                    // If moving in a new direction, add the new direction to the move list
-                    if step != player.last_move() {
-                        player.append_move(step);
+                    let target = Vec2::new(x as f32 * 96., y as f32 * 96.);
+                    let mut entered = before;
+                    entered.step(step, map.width(), map.height());
+                    if entered == position && game_state.walk_duration() > 0. {
+                        game_state.walk_animation = Some(WalkAnimation {
+                            player: game_state.active_player,
+                            from: transform.translation.truncate(),
+                            to: target,
+                            elapsed: 0.,
+                        });
+                    } else {
+                        // Teleporting players, players wrapping around the edge
+                        // of the map and players who asked for reduced motion
+                        // appear at the destination at once
+                        transform.translation = target.extend(1.);
                     }
+                    sprite.flip_x = step & WEST != 0;
+                    player.record_move(step);
                     game_state.time_since_last_move = Duration::ZERO;
-                    match map.cell_at_mut(position) {
-                        GridCell::Path(exits, item) => {
-                            // Ignore the direction from which the player came. If there
-                            // is only one direction in which the player can move,
-                            // then move in that direction. Otherwise stop.
-                            let backwards = get_opposite_direction(step);
-                            let available = *exits & !backwards;
-                            match available {
-                                NORTH | SOUTH | EAST | WEST => {
-                                    game_state.current_move = Some(available)
-                                }
-                                _ => clear_move(&mut game_state),
-                            }
-
-                            // Check for items
-                            if item.is_some() {
-                                let item = item.take().unwrap();
-                                game_state.picked_up_item =
-                                    Some(item.short_description().to_string());
-                                player.pick_up(item);
-                                for (entity, item_transform, _) in item_query.iter() {
-                                    if item_transform.translation.truncate()
-                                        == transform.translation.truncate()
-                                    {
-                                        commands.entity(entity).despawn();
-                                        break;
-                                    }
-                                }
-                            }
-                        }
+                    let mut trapped = false;
+                    match map.cell_at(position) {
+                        // Cutting a corner leaves the player to pick their way
+                        // from there
+                        GridCell::Path(..) if is_diagonal(step) => clear_move(&mut game_state),
+                        GridCell::Path(exits, _) => match next_corridor_step(*exits, step) {
+                            Some(next) => game_state.current_move = Some(next),
+                            None => clear_move(&mut game_state),
+                        },
                         GridCell::Goal(_) => {
+                            info!(player = player.player_number(), "Player reached the goal");
                             game_state.winners.push(player.player_number());
                             game_state.winner_names.push(player.name().to_string());
+                            game_state.log_event(format!("{} reached the goal", player.name()));
+                            if let Some(entry) = game_state
+                                .ghost
+                                .as_ref()
+                                .filter(|ghost| ghost.player() == player.player_number())
+                                .map(race_result)
+                            {
+                                game_state.log_event(entry);
+                            }
+                            let steps_taken = game_state.total_steps + 1 - remaining;
+                            let finish_time = (game_state.turn_number, steps_taken);
+                            game_state.finish_times.push(finish_time);
+                            player.end_move(&map);
                             game_state.current_action = GameAction::HasMoved;
                             clear_move(&mut game_state);
+                            if game_state.time_attack {
+                                let record = TimeAttackRecord {
+                                    turns: game_state.turn_number + 1,
+                                    seconds: game_state.elapsed.as_secs_f32(),
+                                };
+                                info!(record.turns, record.seconds, "Time attack finished");
+                                if !game_state.playing_back() && settings.record_time_attack(record)
+                                {
+                                    settings::write_settings(&settings);
+                                }
+                                game_state.time_attack_result = Some(record);
+                                finish_game(&mut game_state, &mut history);
+                                history::write_history(&history);
+                            } else if game_state.is_over() {
+                                info!("Game over");
+                                finish_game(&mut game_state, &mut history);
+                                history::write_history(&history);
+                            }
                             return;
                         }
-                        _ => (),
+                        // Phasing players choose each step while inside walls
+                        GridCell::Wall => clear_move(&mut game_state),
+                        // Players choose where to go after teleporting
+                        GridCell::Teleporter(..) => clear_move(&mut game_state),
+                        GridCell::Trap(_) => {
+                            info!(player = player.player_number(), "Player stepped on a trap");
+                            game_state.status_message = Some(format!(
+                                "{} stepped on a trap and lost {} remaining steps",
+                                player.name(),
+                                remaining - 1
+                            ));
+                            trapped = true;
+                        }
+                    }
+
+                    // Check for items on or near the current tile
+                    let collected = map.take_items_within(position, game_state.pickup_radius);
+                    if !collected.is_empty() {
+                        let mut descriptions = vec![];
+                        for (Coordinates(x, y), item) in collected {
+                            // Items that don't fit stay where they are
+                            if player.inventory_full() {
+                                let _ = map.place_item(Coordinates(x, y), item);
+                                continue;
+                            }
+                            descriptions.push(item.short_description().to_string());
+                            player.pick_up(item);
+                            let item_position = Vec2::new(x as f32 * 96., y as f32 * 96.);
+                            for (entity, item_transform, _) in item_query.iter() {
+                                if item_transform.translation.truncate() == item_position {
+                                    commands.entity(entity).despawn();
+                                    break;
+                                }
+                            }
+                        }
+                        if !descriptions.is_empty() {
+                            game_state.picked_up_item = Some(descriptions.join(", "));
+                        }
                     }
                     let mut step_count = remaining;
                     step_count -= 1;
-                    if step_count == 0 {
+                    if step_count == 0 || trapped {
+                        player.end_move(&map);
                         game_state.current_action = GameAction::HasMoved;
                         clear_move(&mut game_state);
                     } else {
@@ -595,22 +2182,16 @@ pub fn update_game(
                 }
             }
         }
+        GameAction::HasMoved if game_state.inspecting => {}
         GameAction::HasMoved => {
-            if let Some(action) = get_control(&keyboard) {
+            if let Some(action) = control {
                 match action {
                     Control::Inventory => {
                         if player.get_type() == PlayerType::LocalHuman {
                             game_state.inventory_visible = !game_state.inventory_visible
                         }
                     }
-                    Control::EndTurn => {
-                        player.end_turn();
-                        if game_state.winners.len() == game_state.player_count - 1 {
-                            game_state.game_over = true;
-                        } else {
-                            end_turn(&mut game_state)
-                        }
-                    }
+                    Control::EndTurn => request_end_turn(&mut game_state, player, &mut history),
                     _ => (),
                 }
             }
@@ -618,20 +2199,112 @@ pub fn update_game(
     }
 }
 
+// Keyboard panning speed in screen pixels per second
+const CAMERA_PAN_SPEED: f32 = 800.;
+
+const CAMERA_PAN_KEYS: [(KeyCode, Vec2); 4] = [
+    (KeyCode::Up, Vec2::Y),
+    (KeyCode::Down, Vec2::new(0., -1.)),
+    (KeyCode::Right, Vec2::X),
+    (KeyCode::Left, Vec2::new(-1., 0.)),
+];
+
+// Camera position and zoom that fit all the given points, with a tile of
+// margin around them, into the part of the window between the side panels
+fn frame_points(
+    points: impl Iterator<Item = Vec2>,
+    window_size: Vec2,
+    left_panel_width: f32,
+    right_panel_width: f32,
+) -> Option<(Vec2, f32)> {
+    let (min, max) = points.fold(None, |bounds: Option<(Vec2, Vec2)>, point| {
+        Some(match bounds {
+            Some((min, max)) => (min.min(point), max.max(point)),
+            None => (point, point),
+        })
+    })?;
+    let size = max - min + Vec2::splat(2. * 96.);
+    let visible_width = (window_size.x - left_panel_width - right_panel_width).max(1.);
+    let scale = (size.x / visible_width)
+        .max(size.y / window_size.y)
+        .clamp(0.05, 10.0);
+    // Shift the camera so that the players end up in the middle of the
+    // visible area rather than the middle of the window
+    let offset = Vec2::new((right_panel_width - left_panel_width) / 2. * scale, 0.);
+    Some(((min + max) / 2. + offset, scale))
+}
+
 pub fn scroll_game(
     mut whl: EventReader<MouseWheel>,
+    mut resized: EventReader<WindowResized>,
     mut cam: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
     windows: Res<Windows>,
     input_mouse: Res<Input<MouseButton>>,
+    keyboard: Res<Input<KeyCode>>,
+    time: Res<Time>,
     mut prev: Local<Option<Vec2>>,
     mut game_state: ResMut<GameState>,
     player_query: Query<(&Transform, &PlayerNumber), Without<MainCamera>>,
+    map: Res<Map>,
 ) {
     let mut tr = Vec2::ZERO;
 
     let delta_zoom: f32 = whl.iter().map(|e| e.y).sum();
     let (mut pos, mut cam) = cam.single_mut();
     let window = windows.get_primary().unwrap();
+    // The last cursor position is in the old window's coordinates, which
+    // would make the camera jump when dragging right after a resize
+    if resized.iter().count() > 0 {
+        *prev = None;
+    }
+    // Panel widths are measured in egui points, which are scaled relative to
+    // the window's logical pixels
+    let left_panel_width = game_state.left_panel_width * game_state.ui_scale;
+    let right_panel_width = game_state.right_panel_width * game_state.ui_scale;
+
+    // Frame the whole map while it is being previewed
+    if !game_state.peek_remaining.is_zero() {
+        let map_size = Vec2::new(map.width() as f32, map.height() as f32) * 96.;
+        let center = (map_size - Vec2::splat(96.)) / 2.;
+        let visible_width = window.width() - left_panel_width;
+        cam.scale = (map_size.x / visible_width).max(map_size.y / window.height());
+        pos.translation = Vec3::new(
+            center.x - left_panel_width / 2. * cam.scale,
+            center.y,
+            pos.translation.z,
+        );
+        return;
+    }
+
+    // The arrow keys pick items while the inventory is open
+    let pan: Vec2 = CAMERA_PAN_KEYS
+        .iter()
+        .filter(|(key, _)| !game_state.inventory_visible && keyboard.pressed(*key))
+        .map(|(_, direction)| *direction)
+        .sum();
+    if pan != Vec2::ZERO {
+        let distance = CAMERA_PAN_SPEED * time.delta_seconds() * cam.scale;
+        pos.translation += (pan.normalize() * distance).extend(0.);
+        game_state.camera_follows_player = false;
+    }
+    if keyboard.just_released(KeyCode::F) {
+        let framing = frame_points(
+            player_query
+                .iter()
+                .map(|(transform, _)| transform.translation.truncate()),
+            Vec2::new(window.width(), window.height()),
+            left_panel_width,
+            right_panel_width,
+        );
+        if let Some((center, scale)) = framing {
+            pos.translation = center.extend(pos.translation.z);
+            cam.scale = scale;
+            game_state.camera_follows_player = false;
+            game_state.camera_auto_zoom = false;
+            game_state.camera_zoom = scale;
+        }
+    }
+
     let cursor_position = match window.cursor_position() {
         Some(x) => x,
         None => return,
@@ -639,24 +2312,27 @@ pub fn scroll_game(
 
     if input_mouse.pressed(MouseButton::Left)
         && !input_mouse.just_pressed(MouseButton::Left)
-        && cursor_position.x > game_state.left_panel_width
-        && cursor_position.x < window.width() - game_state.right_panel_width
+        && cursor_position.x > left_panel_width
+        && cursor_position.x < window.width() - right_panel_width
     {
         tr = cursor_position - prev.unwrap_or(cursor_position);
     }
 
     if delta_zoom != 0. {
+        // Keep the point under the cursor in place while zooming
         let window_size = Vec2::new(window.width(), window.height());
-        let mouse_normalized_screen_pos = (cursor_position / window_size) * 2. - Vec2::ONE;
-        let mouse_world_pos = pos.translation.truncate()
-            + mouse_normalized_screen_pos * Vec2::new(cam.right, cam.top) * cam.scale;
+        let mouse_world_pos = screen_to_world(
+            cursor_position,
+            window_size,
+            pos.translation.truncate(),
+            cam.scale,
+        );
 
         cam.scale -= 0.05 * delta_zoom * cam.scale;
         cam.scale = cam.scale.clamp(0.05, 10.0);
 
-        pos.translation = (mouse_world_pos
-            - mouse_normalized_screen_pos * Vec2::new(cam.right, cam.top) * cam.scale)
-            .extend(pos.translation.z);
+        let cursor_offset = screen_to_world(cursor_position, window_size, Vec2::ZERO, cam.scale);
+        pos.translation = (mouse_world_pos - cursor_offset).extend(pos.translation.z);
 
         game_state.camera_auto_zoom = false;
         game_state.camera_zoom = cam.scale;
@@ -688,34 +2364,207 @@ pub fn scroll_game(
     *prev = Some(cursor_position);
 }
 
+const TEAM_COLORS: [egui::Color32; 6] = [
+    egui::Color32::from_rgb(230, 80, 80),
+    egui::Color32::from_rgb(80, 140, 230),
+    egui::Color32::from_rgb(90, 200, 90),
+    egui::Color32::from_rgb(230, 200, 70),
+    egui::Color32::from_rgb(180, 100, 220),
+    egui::Color32::from_rgb(240, 150, 60),
+];
+
+fn team_color(team: usize) -> egui::Color32 {
+    TEAM_COLORS[(team + TEAM_COLORS.len() - 1) % TEAM_COLORS.len()]
+}
+
+fn match_statistics(ui: &mut egui::Ui, players: &PlayerList) {
+    ui.label("Statistics:");
+    egui::Grid::new("match_statistics").show(ui, |ui| {
+        for heading in [
+            "Player",
+            "Rolls",
+            "Average roll",
+            "Items used",
+            "Tiles traveled",
+        ] {
+            ui.label(heading);
+        }
+        ui.end_row();
+        for player in players.iter() {
+            ui.label(player.name());
+            ui.label(player.total_rolls().to_string());
+            ui.label(
+                player
+                    .average_roll()
+                    .map_or("-".to_string(), |average| format!("{:.1}", average)),
+            );
+            ui.label(player.items_used().to_string());
+            ui.label(player.tiles_traveled().to_string());
+            ui.end_row();
+        }
+    });
+}
+
 pub fn control_panel(
     mut game_state: ResMut<GameState>,
     players: Res<PlayerList>,
+    map: Res<Map>,
     mut egui_context: ResMut<EguiContext>,
+    clipboard: Res<EguiClipboard>,
+    keys: Res<KeyBindings>,
 ) {
     egui::SidePanel::left("Control Panel").show(egui_context.ctx_mut(), |ui| {
         game_state.left_panel_width = ui.available_width();
         if game_state.game_over {
             ui.heading("Game over!");
+            if let Some(record) = game_state.time_attack_result {
+                ui.label(format!("Reached the goal in {}", record));
+                match game_state.time_attack_best {
+                    Some(best) if !record.is_better_than(&best) => {
+                        ui.label(format!("Best: {}", best));
+                    }
+                    _ => {
+                        ui.label("New best!");
+                    }
+                }
+                return;
+            }
+            if player::has_shared_teams(&game_state.teams) {
+                let winner = player::winning_team(
+                    &game_state.teams,
+                    &game_state.winners,
+                    game_state.team_win_condition,
+                );
+                if let Some(team) = winner {
+                    ui.colored_label(team_color(team), format!("Team {} wins!", team));
+                }
+            }
             ui.label("Leaderboard:");
-            for (place, winner) in game_state.winner_names.iter().enumerate() {
-                ui.label(format!("{}: {}", place + 1, winner));
+            let places = player::finishing_places(&game_state.finish_times);
+            for (place, winner) in places.iter().zip(game_state.winner_names.iter()) {
+                ui.label(format!("{}: {}", place, winner));
+            }
+            let unfinished = unfinished_players(&game_state);
+            if !unfinished.is_empty() {
+                ui.label(format!("Did not finish: {}", unfinished.join(", ")));
             }
+            match_statistics(ui, &players);
             return;
         }
-        ui.heading(format!(
-            "{}'s turn",
-            game_state.player_names[game_state.active_player]
-        ));
+        let [r, g, b] = game_state.player_color(game_state.active_player);
+        ui.heading(
+            egui::RichText::new(format!(
+                "{}'s turn",
+                game_state.player_names[game_state.active_player]
+            ))
+            .color(egui::Color32::from_rgb(r, g, b)),
+        );
+        ui.label("Press F1 to show the controls");
+        let active_type = players[game_state.active_player].get_type();
+        if let Some(remaining) = game_state.turn_time_remaining(active_type) {
+            ui.label(format!("Time left this turn: {:.0} seconds", remaining.ceil()));
+        }
+        let order = game_state
+            .turn_order
+            .iter()
+            .map(|num| game_state.player_names[*num].as_str())
+            .collect::<Vec<_>>()
+            .join(" > ");
+        if game_state.time_attack {
+            ui.label(format!(
+                "Turn {}, {:.1} seconds",
+                game_state.turn_number + 1,
+                game_state.elapsed.as_secs_f32()
+            ));
+        } else {
+            ui.label(format!("Turn order: {}", order));
+        }
+        if !game_state.hide_standings {
+            let standings = standings(&game_state, &players, &map);
+            if let Some(&(leader, distance)) = standings.first() {
+                ui.label(format!(
+                    "Leading: {}, {} tiles",
+                    players[leader].name(),
+                    distance
+                ));
+            }
+            ui.label("Estimated turns to finish:");
+            for (num, distance) in standings {
+                let player = &players[num];
+                let estimate = format!(
+                    "{}: {} tiles, about {} turns",
+                    player.name(),
+                    distance,
+                    player.die().estimated_turns(distance)
+                );
+                if player::has_shared_teams(&game_state.teams) {
+                    ui.colored_label(team_color(player.team()), estimate);
+                } else {
+                    ui.label(estimate);
+                }
+            }
+        }
+        egui::CollapsingHeader::new("Event log").show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.)
+                .show(ui, |ui| {
+                    for entry in game_state.event_log.iter() {
+                        ui.label(entry);
+                    }
+                });
+        });
+        if game_state.scouting {
+            ui.label("Scouting the whole map");
+            return;
+        }
+        if !game_state.peek_remaining.is_zero() {
+            ui.label(format!(
+                "Memorize the map! The game starts in {:.0} seconds",
+                game_state.peek_remaining.as_secs_f32().ceil()
+            ));
+            return;
+        }
+        let active = &players[game_state.active_player];
+        if active.get_type() != PlayerType::LocalHuman {
+            if game_state.inspecting {
+                ui.label(format!("{}'s turn is paused. Press I to resume.", active.name()));
+                if game_state.reveal_opponent_inventories {
+                    ui.label(format!("{}'s inventory:", active.name()));
+                    if active.inventory_empty() {
+                        ui.label("No items");
+                    }
+                    for item in active.items() {
+                        ui.label(item_label(item.as_ref()));
+                    }
+                }
+            } else {
+                ui.label(format!("Press I to pause {}'s turn and inspect", active.name()));
+            }
+        } else if active.inventory_full() {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "Your inventory is full, so you won't pick up any more items",
+            );
+        }
         match game_state.current_action {
             GameAction::WaitForInput => {
                 let active = &players[game_state.active_player];
                 match active.get_type() {
                     PlayerType::LocalHuman => {
-                        ui.label("Press R to roll");
-                        ui.label(
-                            "Press E to view your inventory (note that you cannot use items at this time)",
-                        );
+                        ui.label(format!("Press {:?} to roll", keys.roll));
+                        if game_state.pure_race {
+                            // There is no inventory to view
+                        } else if game_state.can_use_items() {
+                            ui.label(format!(
+                                "Press {:?} to view your inventory (you may use one item before rolling)",
+                                keys.inventory
+                            ));
+                        } else {
+                            ui.label(format!(
+                                "Press {:?} to view your inventory (note that you cannot use items at this time)",
+                                keys.inventory
+                            ));
+                        }
                     }
                     _ => {
                         ui.label(format!("Waiting for {} to take their turn", active.name()));
@@ -724,14 +2573,41 @@ pub fn control_panel(
             }
             GameAction::UsingItem => {
                 ui.label("Consult the item preview to see what the item will do.");
-                ui.label("Click confirm to use the item.");
+                if game_state.rolled_value.is_none() {
+                    ui.label("Click confirm to use the item. You will roll afterwards.");
+                } else {
+                    ui.label("Click confirm to use the item. This ends your turn.");
+                }
             }
             GameAction::Moving(_, remaining) => {
                 let is_player = players[game_state.active_player].get_type() == PlayerType::LocalHuman;
                 if is_player {
-                    ui.label("Use WASD to move");
+                    ui.label(format!(
+                        "Use {:?}{:?}{:?}{:?} to move",
+                        keys.north, keys.west, keys.south, keys.east
+                    ));
+                    ui.label(format!(
+                        "Press {:?} to stop here and forfeit the remaining steps",
+                        keys.stop
+                    ));
+                }
+                ui.label(format!(
+                    "{} of {} steps remaining",
+                    remaining, game_state.total_steps
+                ));
+                let active = &players[game_state.active_player];
+                if active.allowed_moves(&map) == 0 {
+                    ui.label("No moves available");
+                } else if must_turn_back(active, &map) {
+                    ui.label("No moves available ahead: the only way is back");
+                }
+                if game_state.total_steps > game_state.rolled_value.unwrap_or(0) {
+                    ui.label(format!(
+                        "Boosted: {} steps for a roll of {}",
+                        game_state.total_steps,
+                        game_state.rolled_value.unwrap_or(0)
+                    ));
                 }
-                ui.label(format!("{} steps remaining", remaining));
                 if is_player {
                     if let Some(description) = &game_state.picked_up_item {
                         ui.label(format!("You picked up an item: {}", description));
@@ -740,15 +2616,21 @@ pub fn control_panel(
             }
             GameAction::HasMoved => {
                 let active = &players[game_state.active_player];
+                if let Some(message) = &game_state.status_message {
+                    ui.label(message);
+                }
                 if game_state.winners.contains(&game_state.active_player) {
                     ui.label(format!("{} has reached the goal!", active.name()));
-                } else if active.get_type() == PlayerType::LocalHuman {
+                } else if active.get_type() == PlayerType::LocalHuman && !game_state.pure_race {
                     if let Some(description) = &game_state.picked_up_item {
                         ui.label(format!("You picked up an item: {}", description));
                     }
-                    ui.label("Press E to view your inventory (you may now use items)");
+                    ui.label(format!(
+                        "Press {:?} to view your inventory (you may now use items)",
+                        keys.inventory
+                    ));
                 }
-                ui.label("Press Enter to end the turn");
+                ui.label(format!("Press {:?} to end the turn", keys.end_turn));
             }
         }
 
@@ -758,7 +2640,7 @@ pub fn control_panel(
         if let Some(description) = &game_state.hover_item {
             ui.label(description);
         } else {
-            ui.label("Hover over an item to see its description");
+            ui.label("Hover over an item or tile to see its description");
         }
 
         let sep = egui::Separator::default().spacing(12.).horizontal();
@@ -777,48 +2659,108 @@ pub fn control_panel(
         if !game_state.camera_auto_zoom {
             ui.label(format!("Current zoom level: {:.2}", game_state.camera_zoom));
         }
+        ui.checkbox(
+            &mut game_state.show_step_numbers,
+            "Number the tiles you will walk along",
+        );
+        ui.checkbox(
+            &mut game_state.show_directions,
+            "Show the direction each player is heading",
+        );
 
         let sep = egui::Separator::default().spacing(12.).horizontal();
         ui.add(sep);
 
-        let mut inspect = game_state.inspector_player;
+        // Without revealed dice, players may only view the die of whoever's
+        // turn it is
+        let mut inspect = if game_state.reveal_opponent_dice {
+            game_state.inspector_player
+        } else {
+            game_state.active_player
+        };
         let player = &players[inspect];
         ui.horizontal(|ui| {
             ui.heading(format!("Die weights for"));
-            egui::ComboBox::from_id_source("inspector_picker")
-                .selected_text(player.name())
-                .show_ui(ui, |ui| {
-                    for num in 0..game_state.player_count {
-                        ui.selectable_value(&mut inspect, num, &game_state.player_names[num]);
-                    }
-                });
+            if game_state.reveal_opponent_dice {
+                egui::ComboBox::from_id_source("inspector_picker")
+                    .selected_text(player.name())
+                    .show_ui(ui, |ui| {
+                        for num in 0..game_state.player_count {
+                            ui.selectable_value(&mut inspect, num, &game_state.player_names[num]);
+                        }
+                    });
+            } else {
+                ui.heading(player.name());
+            }
+            if ui
+                .button("Copy die")
+                .on_hover_text("Copy this die so it can be imported in the settings")
+                .clicked()
+            {
+                clipboard.set_contents(&player.die().export_string());
+            }
         });
         game_state.inspector_player = inspect;
-        let (painter, to_screen) = get_painter(ui);
-        die_weight_labels(&painter, to_screen);
+        ui.checkbox(
+            &mut game_state.show_amplitudes,
+            "Show complex amplitudes on hover (advanced)",
+        );
+        let (response, painter, to_screen) = get_painter(ui);
+        die_weight_labels(&painter, to_screen, player.die().faces());
         player
             .die()
             .visualize_weights(&painter, to_screen, egui::Color32::BLUE);
+        die_probability_labels(&painter, to_screen, player.die());
+        painter.text(
+            to_screen * egui::pos2(0.5, 0.95),
+            egui::Align2::CENTER_CENTER,
+            format!("Average roll: {:.1}", player.die().expected_value()),
+            egui::TextStyle::Body,
+            egui::Color32::WHITE,
+        );
+        if game_state.show_amplitudes {
+            let hovered = response
+                .hover_pos()
+                .and_then(|pos| {
+                    WeightedDie::face_at((to_screen.inverse() * pos).x, player.die().faces())
+                });
+            if let Some(face) = hovered {
+                let amplitude = player.die().weights()[face - 1];
+                response.on_hover_text(format!(
+                    "Face {}: {}\nProbability: {:.4}",
+                    face,
+                    format_amplitude(amplitude),
+                    amplitude.norm_sqr()
+                ));
+            }
+        }
     });
 }
 
-fn get_painter(ui: &mut egui::Ui) -> (egui::Painter, egui::emath::RectTransform) {
+pub(crate) fn get_painter(
+    ui: &mut egui::Ui,
+) -> (egui::Response, egui::Painter, egui::emath::RectTransform) {
     use bevy_egui::egui::*;
     let (response, painter) = ui.allocate_painter(ui.available_size_before_wrap(), Sense::click());
     let to_screen = emath::RectTransform::from_to(
         Rect::from_min_size(Pos2::ZERO, response.rect.square_proportions()),
         response.rect,
     );
-    (painter, to_screen)
+    (response, painter, to_screen)
 }
 
-fn die_weight_labels(painter: &egui::Painter, to_screen: egui::emath::RectTransform) {
+pub(crate) fn die_weight_labels(
+    painter: &egui::Painter,
+    to_screen: egui::emath::RectTransform,
+    faces: usize,
+) {
     use bevy_egui::egui::*;
-    for face in 1..=6 {
+    let spacing = (faces + 1) as f32;
+    for face in 1..=faces {
         painter.text(
             to_screen
                 * Pos2 {
-                    x: face as f32 / 7.,
+                    x: face as f32 / spacing,
                     y: 0.1,
                 },
             Align2::CENTER_CENTER,
@@ -829,6 +2771,29 @@ fn die_weight_labels(painter: &egui::Painter, to_screen: egui::emath::RectTransf
     }
 }
 
+// Writes the chance of rolling each face above its bar
+pub(crate) fn die_probability_labels(
+    painter: &egui::Painter,
+    to_screen: egui::emath::RectTransform,
+    die: &WeightedDie,
+) {
+    use bevy_egui::egui::*;
+    let spacing = (die.faces() + 1) as f32;
+    for (i, p) in die.probabilities().into_iter().enumerate() {
+        painter.text(
+            to_screen
+                * Pos2 {
+                    x: (i + 1) as f32 / spacing,
+                    y: 0.85 - p as f32,
+                },
+            Align2::CENTER_CENTER,
+            format!("{:.0}%", p * 100.),
+            TextStyle::Small,
+            Color32::WHITE,
+        );
+    }
+}
+
 fn item_preview(
     egui_context: &mut ResMut<EguiContext>,
     players: &mut ResMut<PlayerList>,
@@ -841,10 +2806,65 @@ fn item_preview(
             game_state.item_preview.source_player,
         )
         .to_string();
+    let user_name = game_state.player_names[game_state.item_preview.source_player].clone();
+    let faces = players[game_state.item_preview.source_player].die().faces();
     {
         let item_preview = &mut game_state.item_preview;
+        // Custom transfers change as the user picks the faces and strength
+        if item_preview.transfer.is_some() {
+            item_preview.effect = None;
+        }
         if item_preview.effect.is_none() {
             match item_preview.item_type {
+                ItemType::Boost => {
+                    item_preview.effect = Some(ItemEffect::PlayerAction(
+                        "Doubles the target's next move".to_string(),
+                    ));
+                }
+                ItemType::Phase => {
+                    item_preview.effect = Some(ItemEffect::PlayerAction(
+                        "The target will be able to walk through walls during their next move"
+                            .to_string(),
+                    ));
+                }
+                ItemType::Entangle => {
+                    item_preview.effect = Some(ItemEffect::Entangle(
+                        players[item_preview.source_player].die().clone(),
+                        players[item_preview.target_player].die().clone(),
+                    ));
+                }
+                ItemType::Scout => {
+                    item_preview.effect = Some(ItemEffect::PlayerAction(
+                        "The target will be able to look at the whole map on their turn"
+                            .to_string(),
+                    ));
+                }
+                ItemType::SwapPositions => {
+                    item_preview.effect = Some(ItemEffect::PlayerAction(
+                        "The user and the target will swap places on the map".to_string(),
+                    ));
+                }
+                ItemType::Reflect => {
+                    item_preview.effect = Some(ItemEffect::PlayerAction(
+                        "The next item another player uses on the target will be \
+                        applied to that player instead"
+                            .to_string(),
+                    ));
+                }
+                ItemType::CustomTransfer => {
+                    let parameters = item_preview.transfer.unwrap();
+                    item_preview.effect = Some(if parameters.is_valid(faces) {
+                        let die_before = players[item_preview.target_player].die().clone();
+                        let mut die_after = die_before.clone();
+                        WeightTransfer::new_custom(faces, parameters)
+                            .use_item_on_die(&mut die_after);
+                        ItemEffect::DieTransform(die_before, die_after)
+                    } else {
+                        ItemEffect::PlayerAction(
+                            "Pick two different faces to transfer weight between".to_string(),
+                        )
+                    });
+                }
                 _ => {
                     let (die_before, mut die_after) = {
                         let target_player = &mut players[item_preview.target_player];
@@ -859,6 +2879,14 @@ fn item_preview(
             }
         }
     }
+    let hide_target_die = game_state.hide_opponent_dice
+        && game_state.item_preview.source_player != game_state.item_preview.target_player;
+    // Players can only tell the target is reflecting if they may see other
+    // players' dice
+    let reflected = !hide_target_die
+        && game_state.item_preview.item_type.is_reflectable()
+        && game_state.item_preview.source_player != game_state.item_preview.target_player
+        && players[game_state.item_preview.target_player].is_reflecting();
     egui::SidePanel::right("Item Effect").show(egui_context.ctx_mut(), |ui| {
         game_state.right_panel_width = ui.available_width();
         let item_preview = &mut game_state.item_preview;
@@ -867,24 +2895,65 @@ fn item_preview(
                 "Use {} item on {}?",
                 item_preview.item_type, target_name
             ));
-            if ui.button("Confirm").clicked() {
-                let item = {
+            let valid = item_preview
+                .transfer
+                .map_or(true, |parameters| parameters.is_valid(faces));
+            if ui
+                .add_enabled(valid, egui::Button::new("Confirm"))
+                .clicked()
+            {
+                let mut item = {
                     let user = &mut players[item_preview.source_player];
                     user.take_item(item_preview.item_index)
                 };
-                let mut target = &mut players[item_preview.target_player];
-                item.use_item(&mut target);
-                chosen_action = ItemAction::UseItem;
+                info!(
+                    user = item_preview.source_player,
+                    target = item_preview.target_player,
+                    item = item.short_description(),
+                    "Player used item"
+                );
+                let applied_to = use_configured_item(
+                    item.as_ref(),
+                    item_preview.transfer,
+                    item_preview.source_player,
+                    item_preview.target_player,
+                    players,
+                );
+                if item.consume_charge() {
+                    players[item_preview.source_player].return_item(item_preview.item_index, item);
+                }
+                chosen_action = ItemAction::UseItem(applied_to);
             }
             if ui.button("Cancel").clicked() {
                 chosen_action = ItemAction::CancelItem;
             }
         });
+        if reflected {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!(
+                    "{} is reflecting items. This one will hit you instead.",
+                    target_name
+                ),
+            );
+        }
+        if let Some(parameters) = item_preview.transfer.as_mut() {
+            transfer_picker(ui, parameters, faces as u32);
+        }
         match item_preview.effect.as_ref().unwrap() {
+            ItemEffect::DieTransform(..) if hide_target_die => {
+                ui.label("The effect on another player's die is hidden.");
+            }
             ItemEffect::DieTransform(before, after) => {
                 ui.label("Lost weight in red. Gained weight in green. Yellow sections unchanged.");
-                let (painter, to_screen) = get_painter(ui);
-                die_weight_labels(&painter, to_screen);
+                if matches!(item_preview.item_type, ItemType::Floor) {
+                    ui.label(format!(
+                        "Guaranteed minimum roll: {}",
+                        after.lowest_possible_face()
+                    ));
+                }
+                let (_, painter, to_screen) = get_painter(ui);
+                die_weight_labels(&painter, to_screen, before.faces());
                 before.visualize_weights(
                     &painter,
                     to_screen,
@@ -899,6 +2968,21 @@ fn item_preview(
             ItemEffect::PlayerAction(effect) => {
                 ui.label(effect);
             }
+            ItemEffect::Entangle(user_die, target_die) => {
+                ui.label(
+                    "Your next roll will make the target's die more likely \
+                    to land on the same face.",
+                );
+                ui.columns(2, |columns| {
+                    let dice = [(&user_name, user_die), (&target_name, target_die)];
+                    for (ui, (name, die)) in columns.iter_mut().zip(dice) {
+                        ui.label(name.as_str());
+                        let (_, painter, to_screen) = get_painter(ui);
+                        die_weight_labels(&painter, to_screen, die.faces());
+                        die.visualize_weights(&painter, to_screen, egui::Color32::BLUE);
+                    }
+                });
+            }
         }
 
         let sep = egui::Separator::default().horizontal();
@@ -907,10 +2991,111 @@ fn item_preview(
     chosen_action
 }
 
+// Lets the user pick the faces and strength of a custom weight transfer
+fn transfer_picker(ui: &mut egui::Ui, parameters: &mut TransferParameters, faces: u32) {
+    ui.add(egui::Slider::new(&mut parameters.from, 1..=faces).text("Move weight from"));
+    ui.add(egui::Slider::new(&mut parameters.to, 1..=faces).text("onto"));
+    ui.add(egui::Slider::new(&mut parameters.strength, 0.0..=1.0).text("Strength"));
+}
+
+// Items that can be used more than once show how many uses they have left
+fn item_label(item: &dyn Item) -> String {
+    match item.charges() {
+        0 | 1 => item.short_description().to_string(),
+        charges => format!("{} x{}", item.short_description(), charges),
+    }
+}
+
+fn open_item_preview(game_state: &mut GameState, player: &Player, item_index: usize) {
+    let item_type = player.get_item_type(item_index);
+    let transfer = matches!(item_type, ItemType::CustomTransfer)
+        .then(|| TransferParameters::new(player.die().faces()));
+    game_state.item_preview = ItemUsePreview {
+        source_player: player.player_number(),
+        item_type,
+        item_index,
+        target_player: game_state.item_preview.target_player,
+        effect: None,
+        transfer,
+    };
+    game_state.selected_item = Some(item_index);
+    game_state.current_action = GameAction::UsingItem;
+}
+
+// Moves the keyboard selection through the inventory, wrapping around at
+// either end
+fn move_selection(selected: Option<usize>, count: usize, forward: bool) -> Option<usize> {
+    if count == 0 {
+        return None;
+    }
+    Some(match (selected, forward) {
+        (None, true) => 0,
+        (None, false) => count - 1,
+        (Some(i), true) => (i + 1) % count,
+        (Some(i), false) => (i.min(count - 1) + count - 1) % count,
+    })
+}
+
+// The next player after the current target that the user may use items on
+fn cycle_target(game_state: &GameState, user: usize, forward: bool) -> usize {
+    let count = game_state.player_count;
+    let current = game_state.item_preview.target_player;
+    (1..=count)
+        .map(|offset| {
+            if forward {
+                (current + offset) % count
+            } else {
+                (current + count - offset) % count
+            }
+        })
+        .find(|&num| game_state.can_target(user, num))
+        .unwrap_or(current)
+}
+
+// Up and down pick an item, left and right pick its target and Enter opens the
+// item preview. Returns whether Enter was used so that it doesn't also end the
+// turn.
+fn navigate_inventory(
+    keyboard: &Res<Input<KeyCode>>,
+    game_state: &mut GameState,
+    player: &Player,
+) -> bool {
+    let count = player.items().count();
+    if keyboard.just_released(KeyCode::Down) {
+        game_state.selected_item = move_selection(game_state.selected_item, count, true);
+    } else if keyboard.just_released(KeyCode::Up) {
+        game_state.selected_item = move_selection(game_state.selected_item, count, false);
+    }
+    // Dropping or using items can leave the selection past the end
+    game_state.selected_item = game_state
+        .selected_item
+        .filter(|_| count > 0)
+        .map(|i| i.min(count - 1));
+    let user = player.player_number();
+    if keyboard.just_released(KeyCode::Right) {
+        game_state.item_preview.target_player = cycle_target(game_state, user, true);
+    } else if keyboard.just_released(KeyCode::Left) {
+        game_state.item_preview.target_player = cycle_target(game_state, user, false);
+    }
+    if !keyboard.just_released(KeyCode::Return) {
+        return false;
+    }
+    if let Some(index) = game_state.selected_item {
+        if game_state.can_use_items()
+            && game_state.can_target(user, game_state.item_preview.target_player)
+        {
+            open_item_preview(game_state, player, index);
+        }
+    }
+    true
+}
+
 fn inventory_window(
     egui_context: &mut ResMut<EguiContext>,
+    commands: &mut Commands,
     players: &mut ResMut<PlayerList>,
     game_state: &mut ResMut<GameState>,
+    map: &mut Map,
 ) {
     let player = &mut players[game_state.active_player];
     egui::SidePanel::right("Inventory").show(egui_context.ctx_mut(), |ui| {
@@ -923,10 +3108,21 @@ fn inventory_window(
             ui.add(sep);
             return;
         }
+        if player.inventory_full() {
+            ui.label("Your inventory is full. Drop an item to make room for new ones.");
+        }
+        ui.label("Use the arrow keys to pick an item and its target, then Enter to use it");
+        let can_drop = map.can_place_item(player.position());
         let mut used = None;
+        let mut dropped = None;
         for (i, item) in player.items().enumerate() {
-            ui.horizontal(|ui| {
-                ui.collapsing(format!("{}: {}", i, item.short_description()), |ui| {
+            let fill = if game_state.selected_item == Some(i) {
+                ui.visuals().selection.bg_fill
+            } else {
+                egui::Color32::TRANSPARENT
+            };
+            egui::Frame::none().fill(fill).show(ui, |ui| {
+                ui.collapsing(format!("{}: {}", i, item_label(item.as_ref())), |ui| {
                     ui.label(item.full_description());
                     ui.horizontal(|ui| {
                         ui.label("Use this on");
@@ -937,6 +3133,9 @@ fn inventory_window(
                             ))
                             .show_ui(ui, |ui| {
                                 for num in 0..game_state.player_count {
+                                    if !game_state.can_target(player.player_number(), num) {
+                                        continue;
+                                    }
                                     let name = game_state
                                         .get_player_name(num, player.player_number())
                                         .to_string();
@@ -948,21 +3147,47 @@ fn inventory_window(
                                 }
                             });
                     });
-                    if ui.button("Use item...").clicked() {
+                    let allowed = game_state.can_use_items()
+                        && game_state.can_target(
+                            player.player_number(),
+                            game_state.item_preview.target_player,
+                        );
+                    if ui
+                        .add_enabled(allowed, egui::Button::new("Use item..."))
+                        .clicked()
+                    {
                         used = Some(i);
                     }
+                    if ui
+                        .add_enabled(can_drop, egui::Button::new("Drop here"))
+                        .on_disabled_hover_text("Items can only be dropped on empty paths")
+                        .clicked()
+                    {
+                        dropped = Some(i);
+                    }
                 });
             });
         }
+        if let Some(index) = dropped {
+            if player.drop_item(index, map) {
+                info!(player = player.player_number(), index, "Dropped item");
+                game_state.record(ReplayEvent::Drop {
+                    player: player.player_number(),
+                    item: index,
+                    at: player.position(),
+                });
+                if let GridCell::Path(_, Some(item)) = map.cell_at(player.position()) {
+                    spawn_item(
+                        commands,
+                        game_state.item_texture.clone(),
+                        player.position(),
+                        item.as_ref(),
+                    );
+                }
+            }
+        }
         if let Some(item_index) = used {
-            game_state.item_preview = ItemUsePreview {
-                source_player: player.player_number(),
-                item_type: player.get_item_type(item_index),
-                item_index,
-                target_player: game_state.item_preview.target_player,
-                effect: None,
-            };
-            game_state.current_action = GameAction::UsingItem;
+            open_item_preview(game_state, player, item_index);
         }
 
         let sep = egui::Separator::default().horizontal();
@@ -971,9 +3196,12 @@ fn inventory_window(
 }
 
 pub fn item_panel(
+    mut commands: Commands,
     mut egui_context: ResMut<EguiContext>,
     mut players: ResMut<PlayerList>,
     mut game_state: ResMut<GameState>,
+    mut map: ResMut<Map>,
+    mut player_query: Query<(&PlayerNumber, &mut Transform, &mut Sprite)>,
 ) {
     if game_state.paused || game_state.game_over {
         return;
@@ -981,34 +3209,265 @@ pub fn item_panel(
     if game_state.current_action == GameAction::UsingItem {
         match item_preview(&mut egui_context, &mut players, &mut game_state) {
             ItemAction::NoAction => {}
-            ItemAction::UseItem => end_turn(&mut game_state),
-            ItemAction::CancelItem => game_state.current_action = GameAction::HasMoved,
+            ItemAction::UseItem(applied_to) => {
+                let affected = [
+                    game_state.item_preview.source_player,
+                    game_state.item_preview.target_player,
+                ];
+                game_state.record(ReplayEvent::UseItem {
+                    user: affected[0],
+                    item: game_state.item_preview.item_index,
+                    target: affected[1],
+                    finishes: true,
+                    transfer: game_state.item_preview.transfer,
+                });
+                let item_type = game_state.item_preview.item_type;
+                game_state.log_item_use(affected[0], item_type, applied_to);
+                players[affected[0]].record_item_use();
+                place_sprites(&mut game_state, &players, &mut player_query, affected);
+                finish_item_use(&mut game_state);
+            }
+            ItemAction::CancelItem => game_state.current_action = game_state.action_after_item(),
         }
     } else if game_state.inventory_visible {
-        inventory_window(&mut egui_context, &mut players, &mut game_state);
-    } else if game_state.current_action == GameAction::HasMoved {
-        computer_use_item(&*game_state, &mut *players);
+        inventory_window(
+            &mut egui_context,
+            &mut commands,
+            &mut players,
+            &mut game_state,
+            &mut map,
+        );
+    } else if game_state.current_action == GameAction::HasMoved
+        && !game_state.inspecting
+        && !game_state.playing_back()
+    {
+        if let Some((user, target)) = computer_use_item(&mut *game_state, &mut *players, &map) {
+            place_sprites(&mut game_state, &players, &mut player_query, [user, target]);
+        }
     } else {
         game_state.right_panel_width = 0.;
     }
 }
 
+// Plays a sound when a die is rolled, a player steps onto a new tile, picks up
+// an item or reaches the goal
+pub fn play_sound_effects(
+    audio: Res<Audio>,
+    settings: Res<GameSettings>,
+    mut game_state: ResMut<GameState>,
+    players: Res<PlayerList>,
+) {
+    let triggers = SoundTriggers::capture(&game_state, &players);
+    if game_state.sound_triggers.as_ref() == Some(&triggers) {
+        return;
+    }
+    // Bevy can't play sounds at a lower volume yet, so the volume setting can
+    // only mute them
+    if let Some(before) = game_state.sound_triggers.as_ref() {
+        if settings.sfx_volume() > 0. {
+            for effect in triggers.effects_since(before) {
+                audio.play(game_state.sounds.handle(effect));
+            }
+        }
+    }
+    game_state.sound_triggers = Some(triggers);
+}
+
+pub fn help_overlay(
+    mut egui_context: ResMut<EguiContext>,
+    keyboard: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut game_state: ResMut<GameState>,
+) {
+    if keyboard.just_released(KeyCode::F1) {
+        game_state.show_help = !game_state.show_help;
+    }
+    egui::Window::new("Controls")
+        .open(&mut game_state.show_help)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("key_bindings").show(ui, |ui| {
+                for (key, description) in help_entries(&key_bindings) {
+                    ui.label(key);
+                    ui.label(description);
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+pub fn end_match_dialog(
+    mut egui_context: ResMut<EguiContext>,
+    mut game_state: ResMut<GameState>,
+    mut players: ResMut<PlayerList>,
+    mut history: ResMut<MatchHistory>,
+) {
+    if !game_state.confirming_end {
+        return;
+    }
+    let mut confirmed = false;
+    let mut cancelled = false;
+    egui::Window::new("End the match?")
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("Ending your turn now will end the match.");
+            ui.horizontal(|ui| {
+                confirmed = ui.button("End the match").clicked();
+                cancelled = ui.button("Keep playing").clicked();
+            });
+        });
+    if confirmed {
+        game_state.confirming_end = false;
+        let active = game_state.active_player;
+        game_state.record(ReplayEvent::EndTurn {
+            player: active,
+            at: Some(players[active].position()),
+        });
+        finish_turn(&mut game_state, &mut players[active], &mut history);
+    } else if cancelled {
+        game_state.confirming_end = false;
+    }
+}
+
 pub fn pause_menu(
+    mut commands: Commands,
     mut egui_context: ResMut<EguiContext>,
     mut state: ResMut<State<AppState>>,
     mut game_state: ResMut<GameState>,
+    map: Res<Map>,
+    players: Res<PlayerList>,
+    asset_server: Res<AssetServer>,
+    settings: Res<GameSettings>,
+    query: Query<Entity, (With<Transform>, Without<MainCamera>)>,
 ) {
     if game_state.paused || game_state.game_over {
+        let mut restart = false;
         egui::SidePanel::right("Pause").show(egui_context.ctx_mut(), |ui| {
             game_state.right_panel_width = ui.available_width();
             ui.heading("Pause");
             if ui.button("Back to Main").clicked() {
                 state.set(AppState::MainMenu).unwrap();
             }
+            restart = ui
+                .button("Restart")
+                .on_hover_text("Start a new game with the same settings")
+                .clicked();
+            if !game_state.game_over && ui.button("Save game").clicked() {
+                save_game(&game_state, &map, &players);
+                game_state.status_message = Some("Game saved".to_string());
+            }
+            #[cfg(feature = "serde")]
+            if ui
+                .button("Save map")
+                .on_hover_text("Save the map to play it again later")
+                .clicked()
+            {
+                game_state.status_message = Some(save_map(&map));
+            }
+            if ui
+                .button("Export map image")
+                .on_hover_text("Save a picture of the whole map")
+                .clicked()
+            {
+                game_state.status_message = Some(export_map_image(&map));
+            }
 
             let sep = egui::Separator::default().horizontal();
             ui.add(sep);
         });
+        // The camera is kept, everything else is set up again from scratch
+        if restart {
+            info!("Restarting game");
+            for entity in query.iter() {
+                commands.entity(entity).despawn();
+            }
+            spawn_game(
+                &mut commands,
+                &asset_server,
+                &settings,
+                &mut egui_context,
+                new_game(&settings),
+            );
+        }
+    }
+}
+
+// Saves the map next to the saved game. Returns a message for the player.
+#[cfg(feature = "serde")]
+fn save_map(map: &Map) -> String {
+    let dir = match ProjectDirs::from("", "", "Zink'd") {
+        Some(dir) => dir.data_dir().to_path_buf(),
+        None => return "Couldn't find a folder to save the map in".to_string(),
+    };
+    let file = dir.join("map.ron");
+    match create_dir_all(&dir).and_then(|_| map.to_file(&file)) {
+        Ok(()) => format!("Map saved to {}", file.display()),
+        Err(error) => {
+            warn!(%error, "Failed to save map");
+            format!("Failed to save the map: {}", error)
+        }
+    }
+}
+
+// Pixels per cell in exported map images
+const MAP_IMAGE_TILE_SIZE: u32 = 16;
+
+// Saves a picture of the map next to the saved game. Returns a message for
+// the player.
+fn export_map_image(map: &Map) -> String {
+    let dir = match ProjectDirs::from("", "", "Zink'd") {
+        Some(dir) => dir.data_dir().to_path_buf(),
+        None => return "Couldn't find a folder to save the image in".to_string(),
+    };
+    let file = dir.join("map.png");
+    let result = create_dir_all(&dir)
+        .map_err(|error| error.to_string())
+        .and_then(|_| {
+            map.render_to_image(MAP_IMAGE_TILE_SIZE)
+                .save(&file)
+                .map_err(|error| error.to_string())
+        });
+    match result {
+        Ok(()) => format!("Map image saved to {}", file.display()),
+        Err(error) => {
+            warn!(%error, "Failed to export map image");
+            format!("Failed to save the map image: {}", error)
+        }
+    }
+}
+
+pub fn load_game(mut saved: ResMut<SavedGame>) {
+    #[cfg(feature = "serde")]
+    if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
+        let mut file = dir.data_dir().to_path_buf();
+        file.push("savegame.ron");
+        if let Ok(mut file) = File::open(file) {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)
+                .expect("Failed to read saved game");
+            match ron::from_str(contents.as_str()) {
+                Ok(game) => saved.0 = Some(game),
+                Err(error) => warn!(%error, "Failed to load saved game"),
+            }
+        }
+    }
+}
+
+pub fn save_game(game_state: &GameState, map: &Map, players: &[Player]) {
+    #[cfg(feature = "serde")]
+    if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
+        let mut file = dir.data_dir().to_path_buf();
+        create_dir_all(&file).expect("Failed to create data directory");
+        file.push("savegame.ron");
+        let mut file = File::create(file).expect("Failed to create save file");
+        file.write_all(
+            ron::to_string(&(game_state, map, players))
+                .unwrap()
+                .as_ref(),
+        )
+        .expect("Failed to write saved game to disk");
+        info!(turn = game_state.turn_number, "Saved game");
     }
 }
 
@@ -1018,3 +3477,601 @@ pub fn cleanup_game(mut commands: Commands, query: Query<Entity, With<Transform>
         commands.entity(entity).despawn();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::game::*;
+    use zinkd::items::WeightTransfer;
+
+    #[test]
+    fn stop_forfeits_remaining_steps() {
+        let player = Player::spawn_at(Coordinates(3, 4), String::new(), 0, PlayerType::LocalHuman);
+        let mut game_state = GameState {
+            current_action: GameAction::Moving(NORTH, 3),
+            current_move: Some(NORTH),
+            ..Default::default()
+        };
+        stop_moving(&mut game_state);
+        assert!(game_state.current_action == GameAction::HasMoved);
+        assert!(game_state.current_move.is_none());
+        assert!(player.position() == Coordinates(3, 4));
+    }
+
+    #[test]
+    fn die_face_matches_roll() {
+        assert_eq!(die_face_index(None), None);
+        for rolled in 1..=6 {
+            assert_eq!(die_face_index(Some(rolled)), Some(rolled as usize - 1));
+        }
+    }
+
+    #[test]
+    fn missing_die_atlas_falls_back() {
+        assert_eq!(DieAtlas::choose("", 3, 2, 6), DieAtlas::default());
+        assert_eq!(
+            DieAtlas::choose("/nonexistent/die_faces.png", 3, 2, 6),
+            DieAtlas::default()
+        );
+        // Too few frames for a six-sided die
+        assert_eq!(
+            DieAtlas::choose("assets/sprites/goal.png", 2, 2, 6),
+            DieAtlas::default()
+        );
+
+        // Too few frames for a twelve-sided die
+        assert_eq!(
+            DieAtlas::choose("assets/sprites/DieFaces.png", 3, 2, 12),
+            DieAtlas::default()
+        );
+
+        let custom = DieAtlas::choose("assets/sprites/DieFaces.png", 3, 2, 6);
+        assert!(custom.path.ends_with("DieFaces.png"));
+        assert!(
+            custom.uv(4)
+                == egui::Rect::from_min_max(egui::pos2(1. / 3., 0.5), egui::pos2(2. / 3., 1.))
+        );
+    }
+
+    #[test]
+    fn map_peek_locks_input() {
+        let mut game_state = GameState {
+            peek_remaining: Duration::from_secs(2),
+            ..Default::default()
+        };
+        assert!(tick_map_peek(&mut game_state, Duration::from_secs(1)));
+        assert!(tick_map_peek(&mut game_state, Duration::from_millis(1500)));
+        assert!(game_state.peek_remaining.is_zero());
+        assert!(!tick_map_peek(&mut game_state, Duration::from_secs(1)));
+
+        // A duration of zero disables the preview
+        let mut game_state = GameState::default();
+        assert!(!tick_map_peek(&mut game_state, Duration::from_millis(16)));
+    }
+
+    #[test]
+    fn scouting_shows_the_map() {
+        use zinkd::items::{Item, Scout};
+
+        let mut game_state = GameState::default();
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        assert!(!start_scout_peek(&mut game_state, &player));
+        Scout.use_item(&mut player);
+        assert!(start_scout_peek(&mut game_state, &player));
+        assert!(game_state.scouting);
+        assert!(tick_map_peek(&mut game_state, SCOUT_PEEK_DURATION));
+        assert!(!game_state.scouting);
+    }
+
+    #[test]
+    fn standings_rank_players_still_racing() {
+        let map =
+            Map::generate_random_map(20, 20, 3, 0.1, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        let position_of = |wanted: fn(&GridCell) -> bool| {
+            map.iter()
+                .find(|(_, cell)| wanted(cell))
+                .map(|(pos, _)| pos)
+                .unwrap()
+        };
+        let start = *map.starting_positions().next().unwrap();
+        let goal = position_of(|cell| matches!(cell, GridCell::Goal(_)));
+        let wall = position_of(|cell| matches!(cell, GridCell::Wall));
+        let players: Vec<_> = [start, goal, wall, goal]
+            .into_iter()
+            .enumerate()
+            .map(|(num, pos)| Player::spawn_at(pos, String::new(), num, PlayerType::LocalHuman))
+            .collect();
+        let game_state = GameState {
+            turn_order: vec![0, 1, 2, 3],
+            winners: vec![3],
+            ..Default::default()
+        };
+        let standings = standings(&game_state, &players, &map);
+        let distance = map.distance_to_goal(start).unwrap();
+        assert_eq!(standings, vec![(1, 0), (0, distance)]);
+    }
+
+    #[test]
+    fn stuck_player_stops_moving() {
+        let map =
+            Map::generate_random_map(20, 20, 2, 0.1, 10, 6, 0., 0., 1, MapGenerator::Corridors);
+        let wall = map
+            .iter()
+            .find(|(_, cell)| matches!(cell, GridCell::Wall))
+            .map(|(pos, _)| pos)
+            .expect("No walls on map");
+        let mut player = Player::spawn_at(wall, "Stuck".to_string(), 0, PlayerType::LocalHuman);
+        let mut game_state = GameState {
+            current_action: GameAction::Moving(NORTH, 3),
+            ..Default::default()
+        };
+        assert!(resolve_no_moves(&mut game_state, &mut player, &map));
+        assert!(game_state.current_action == GameAction::HasMoved);
+        assert!(game_state.status_message.is_some());
+
+        let start = *map.starting_positions().next().unwrap();
+        let mut player = Player::spawn_at(start, String::new(), 0, PlayerType::LocalHuman);
+        let mut game_state = GameState {
+            current_action: GameAction::Moving(NORTH, 3),
+            ..Default::default()
+        };
+        assert!(!resolve_no_moves(&mut game_state, &mut player, &map));
+        assert!(game_state.current_action == GameAction::Moving(NORTH, 3));
+    }
+
+    #[test]
+    fn item_used_before_roll() {
+        let mut game_state = GameState {
+            items_before_roll: true,
+            ..Default::default()
+        };
+        assert!(game_state.can_use_items());
+
+        let mut players = vec![Player::spawn_at(
+            Coordinates(0, 0),
+            String::new(),
+            0,
+            PlayerType::LocalHuman,
+        )];
+        players[0].pick_up(Box::new(WeightTransfer::new_single(6, 1, 6, 1.)));
+        game_state.current_action = GameAction::UsingItem;
+        let item = players[0].take_item(0);
+        use_item_on(item.as_ref(), 0, 0, &mut players);
+        finish_item_use(&mut game_state);
+
+        // The player still gets to roll, but can't use another item first
+        assert!(game_state.current_action == GameAction::WaitForInput);
+        assert!(!game_state.can_use_items());
+        assert!(players[0].die().weights()[5].norm_sqr() > 1. / 6.);
+
+        game_state.items_before_roll = false;
+        game_state.used_item_before_roll = false;
+        assert!(!game_state.can_use_items());
+    }
+
+    #[test]
+    fn finished_match_is_recorded() {
+        let mut game_state = GameState {
+            player_names: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+            winners: vec![2, 0],
+            winner_names: vec!["C".to_string(), "A".to_string()],
+            finish_times: vec![(4, 2), (6, 1)],
+            turn_number: 6,
+            ..Default::default()
+        };
+        let mut history = MatchHistory::default();
+        finish_game(&mut game_state, &mut history);
+        assert!(game_state.game_over);
+        assert_eq!(history.records().len(), 1);
+        let record = &history.records()[0];
+        assert_eq!(
+            record.finishing_order,
+            vec![(1, "C".to_string()), (2, "A".to_string())]
+        );
+        assert_eq!(record.turns, 7);
+    }
+
+    #[test]
+    fn first_finisher_ends_the_game() {
+        let mut game_state = GameState {
+            player_names: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+            teams: vec![1, 2, 3],
+            winners: vec![1],
+            win_condition: WinCondition::FirstToFinish,
+            ..Default::default()
+        };
+        assert!(game_state.is_over());
+        assert_eq!(unfinished_players(&game_state), vec!["A", "C"]);
+        game_state.win_condition = WinCondition::AllButLast;
+        assert!(!game_state.is_over());
+    }
+
+    #[test]
+    fn ending_the_match_needs_confirmation() {
+        let mut game_state = GameState {
+            player_names: vec!["A".to_string(), "B".to_string()],
+            teams: vec![1, 2],
+            winners: vec![1],
+            current_action: GameAction::HasMoved,
+            ..Default::default()
+        };
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        let mut history = MatchHistory::default();
+        request_end_turn(&mut game_state, &mut player, &mut history);
+        assert!(game_state.confirming_end);
+        assert!(!game_state.game_over);
+        assert!(history.records().is_empty());
+    }
+
+    #[test]
+    fn inventory_selection_wraps() {
+        assert_eq!(move_selection(None, 3, true), Some(0));
+        assert_eq!(move_selection(None, 3, false), Some(2));
+        assert_eq!(move_selection(Some(2), 3, true), Some(0));
+        assert_eq!(move_selection(Some(0), 3, false), Some(2));
+        assert_eq!(move_selection(Some(1), 0, true), None);
+    }
+
+    #[test]
+    fn target_cycling_skips_teammates() {
+        let game_state = GameState {
+            player_count: 4,
+            teams: vec![1, 1, 2, 2],
+            ..Default::default()
+        };
+        assert_eq!(cycle_target(&game_state, 0, true), 2);
+        assert_eq!(cycle_target(&game_state, 0, false), 3);
+    }
+
+    #[test]
+    fn tile_descriptions_list_exits() {
+        assert_eq!(tile_description(&GridCell::Wall), "Wall");
+        assert_eq!(
+            tile_description(&GridCell::Path(NORTH | EAST, None)),
+            "Path: N, E"
+        );
+        assert_eq!(
+            tile_description(&GridCell::Teleporter(WEST, Coordinates(3, 4))),
+            "Teleporter to (3, 4): W"
+        );
+    }
+
+    #[test]
+    fn corridors_turn_players_around_at_dead_ends() {
+        // Walking north into a bend follows it
+        assert_eq!(next_corridor_step(SOUTH | EAST, NORTH), Some(EAST));
+        // A dead end sends the player back
+        assert_eq!(next_corridor_step(SOUTH, NORTH), Some(SOUTH));
+        // Junctions leave the choice to the player
+        assert_eq!(next_corridor_step(SOUTH | EAST | WEST, NORTH), None);
+    }
+
+    #[test]
+    fn help_lists_every_control() {
+        let bindings = KeyBindings::default();
+        let entries = help_entries(&bindings);
+        let controls = [
+            Control::Roll,
+            Control::Inventory,
+            Control::Move(NORTH),
+            Control::Move(SOUTH),
+            Control::Move(EAST),
+            Control::Move(WEST),
+            Control::Move(NORTHEAST),
+            Control::Move(NORTHWEST),
+            Control::Move(SOUTHEAST),
+            Control::Move(SOUTHWEST),
+            Control::Stop,
+            Control::EndTurn,
+        ];
+        for control in controls {
+            // Fails to compile if a new control is added without updating this test
+            match control {
+                Control::Roll
+                | Control::Inventory
+                | Control::Move(_)
+                | Control::Stop
+                | Control::EndTurn => {}
+            }
+            assert!(turn_controls(&bindings)
+                .iter()
+                .any(|(_, bound)| *bound == control));
+            assert!(entries
+                .iter()
+                .any(|(_, description)| *description == control.description()));
+        }
+
+        let rebound = KeyBindings {
+            roll: KeyCode::Q,
+            ..Default::default()
+        };
+        assert!(help_entries(&rebound)
+            .iter()
+            .any(|(key, description)| key == "Q" && *description == Control::Roll.description()));
+    }
+
+    #[test]
+    fn boost_doubles_steps() {
+        let mut player =
+            Player::spawn_at(Coordinates(0, 0), String::new(), 0, PlayerType::LocalHuman);
+        let mut game_state = GameState::default();
+        player.set_boosted(true);
+        start_moving(&mut game_state, &mut player, 4);
+        assert!(game_state.current_action == GameAction::Moving(0, 8));
+        assert_eq!(game_state.rolled_value, Some(4));
+
+        // The boost only lasts for one roll
+        start_moving(&mut game_state, &mut player, 4);
+        assert!(game_state.current_action == GameAction::Moving(0, 4));
+    }
+
+    #[test]
+    fn screen_to_world_after_resize() {
+        let camera = Vec2::new(480., 960.);
+        let offset = Vec2::new(100., -50.);
+        for size in [Vec2::new(1280., 720.), Vec2::new(400., 1600.)] {
+            assert_eq!(screen_to_world(size / 2., size, camera, 0.7), camera);
+            assert_eq!(
+                screen_to_world(size / 2. + offset, size, camera, 0.5),
+                camera + offset * 0.5
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_turn_order_repeats() {
+        assert_eq!(seeded_turn_order(6, false, 3), vec![0, 1, 2, 3, 4, 5]);
+        let order = seeded_turn_order(8, true, 11);
+        assert_eq!(order, seeded_turn_order(8, true, 11));
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn fair_dice_repeat_their_rolls() {
+        assert!(fair_dice_rngs(None, 2).is_empty());
+        let die = WeightedDie::fair_die_with_faces(6);
+        let rolls = |rngs: &mut Vec<StdRng>| -> Vec<u32> {
+            rngs.iter_mut()
+                .flat_map(|rng| (0..10).map(|_| die.roll_with(rng)).collect::<Vec<_>>())
+                .collect()
+        };
+        let first = rolls(&mut fair_dice_rngs(Some(7), 3));
+        assert_eq!(first, rolls(&mut fair_dice_rngs(Some(7), 3)));
+        // Player 1 rolls what player 0 would with the next seed
+        assert_eq!(first[10..20], rolls(&mut fair_dice_rngs(Some(8), 1))[..]);
+    }
+
+    #[test]
+    fn entangled_roll_uses_current_dice() {
+        let mut players: Vec<Player> = (0..2)
+            .map(|num| {
+                Player::spawn_at(
+                    Coordinates(0, 0),
+                    String::new(),
+                    num,
+                    PlayerType::LocalHuman,
+                )
+            })
+            .collect();
+        let pair = EntangledPair::new(0, 1, players[0].die(), players[1].die());
+        players[0].set_entanglement(pair);
+        // The partner's die is loaded after the entanglement
+        let mut loaded = vec![num_complex::Complex64::from(0.); 6];
+        loaded[2] = num_complex::Complex64::from(1.);
+        players[1].set_die_weights(loaded);
+
+        roll_player_die(0, false, &mut players, &mut StdRng::seed_from_u64(3));
+        assert!(players[0].entanglement().unwrap().measured().is_some());
+        let weights = players[1].die().weights();
+        assert!((weights[2].norm_sqr() - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn framing_fits_players_between_panels() {
+        let window = Vec2::new(1000., 800.);
+        let players = [Vec2::new(0., 0.), Vec2::new(960., 480.)];
+        let (center, scale) = frame_points(players.into_iter(), window, 0., 0.).unwrap();
+        assert_eq!(center, Vec2::new(480., 240.));
+        assert_eq!(scale, (960. + 192.) / 1000.);
+
+        // A left panel narrows the view and pushes the players to the right
+        let (center, scale) = frame_points(players.into_iter(), window, 200., 0.).unwrap();
+        assert_eq!(scale, (960. + 192.) / 800.);
+        assert_eq!(center, Vec2::new(480. - 100. * scale, 240.));
+
+        assert!(frame_points(std::iter::empty(), window, 0., 0.).is_none());
+    }
+
+    #[test]
+    fn event_log_is_capped() {
+        let mut game_state = GameState {
+            player_names: vec!["Ferris".to_string(), "Darryl".to_string()],
+            ..Default::default()
+        };
+        game_state.log_item_use(1, ItemType::WeightTransfer, 0);
+        game_state.log_item_use(0, ItemType::Boost, 0);
+        assert_eq!(
+            game_state.event_log[0],
+            "Darryl used Weight Transfer on Ferris"
+        );
+        assert_eq!(game_state.event_log[1], "Ferris used Boost on themselves");
+
+        for turn in 0..MAX_LOG_ENTRIES {
+            game_state.log_event(format!("Turn {}", turn));
+        }
+        assert_eq!(game_state.event_log.len(), MAX_LOG_ENTRIES);
+        assert_eq!(game_state.event_log[0], "Turn 0");
+    }
+
+    #[test]
+    fn inspection_freezes_move_timer() {
+        let mut game_state = GameState {
+            tile_walk_time: 0.5,
+            inspecting: true,
+            ..Default::default()
+        };
+        assert!(!advance_move_timer(&mut game_state, Duration::from_secs(1)));
+        assert_eq!(game_state.time_since_last_move, Duration::ZERO);
+
+        game_state.inspecting = false;
+        assert!(!advance_move_timer(
+            &mut game_state,
+            Duration::from_millis(300)
+        ));
+        assert!(advance_move_timer(
+            &mut game_state,
+            Duration::from_millis(300)
+        ));
+    }
+
+    #[test]
+    fn walk_animation_interpolates() {
+        let animation = WalkAnimation {
+            player: 0,
+            from: Vec2::new(0., 0.),
+            to: Vec2::new(96., 0.),
+            elapsed: 0.25,
+        };
+        assert_eq!(animation.position(0.5), Vec2::new(48., 0.));
+        assert_eq!(animation.position(0.1), Vec2::new(96., 0.));
+        assert_eq!(animation.position(0.), Vec2::new(96., 0.));
+    }
+
+    #[test]
+    fn race_result_compares_turns() {
+        let mut session =
+            ReplaySession::recording(Replay::new(1, GameSettings::default(), vec![0]));
+        let replay = match session.finish(&[0]) {
+            ReplayOutcome::Recorded(replay) => replay,
+            _ => unreachable!(),
+        };
+        let mut ghost = Ghost::new(replay, 0, Coordinates(0, 0), |_| Coordinates(1, 0));
+        assert_eq!(ghost.finishing_turn(), Some(1));
+        assert_eq!(race_result(&ghost), "You tied with the ghost on turn 1");
+        ghost.turn_ended(0);
+        assert_eq!(
+            race_result(&ghost),
+            "The ghost was faster, finishing on turn 1"
+        );
+    }
+
+    #[test]
+    fn reduced_motion_snaps_walks() {
+        let mut game_state = GameState {
+            tile_walk_time: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(game_state.walk_duration(), 0.5);
+        game_state.reduced_motion = true;
+        assert_eq!(game_state.walk_duration(), 0.);
+        let animation = WalkAnimation {
+            player: 0,
+            from: Vec2::new(0., 0.),
+            to: Vec2::new(96., 0.),
+            elapsed: 0.,
+        };
+        assert_eq!(
+            animation.position(game_state.walk_duration()),
+            Vec2::new(96., 0.)
+        );
+        // Turns are paced as before
+        assert!(!advance_move_timer(
+            &mut game_state,
+            Duration::from_millis(200)
+        ));
+        assert!(advance_move_timer(
+            &mut game_state,
+            Duration::from_millis(300)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn game_state_survives_saving() {
+        let game_state = GameState {
+            turn_number: 7,
+            current_action: GameAction::Moving(EAST, 2),
+            winners: vec![1],
+            paused: true,
+            ..Default::default()
+        };
+        let loaded: GameState = ron::from_str(&ron::to_string(&game_state).unwrap()).unwrap();
+        assert_eq!(loaded.turn_number, 7);
+        assert!(loaded.current_action == GameAction::Moving(EAST, 2));
+        assert_eq!(loaded.winners, vec![1]);
+        // Pausing is part of the UI and isn't saved
+        assert!(!loaded.paused);
+    }
+
+    #[test]
+    fn sounds_follow_state_changes() {
+        let mut game_state = GameState::default();
+        let mut players = vec![Player::spawn_at(
+            Coordinates(0, 0),
+            String::new(),
+            0,
+            PlayerType::LocalHuman,
+        )];
+        let before = SoundTriggers::capture(&game_state, &players);
+        assert!(before.effects_since(&before).is_empty());
+
+        start_moving(&mut game_state, &mut players[0], 3);
+        let rolled = SoundTriggers::capture(&game_state, &players);
+        assert_eq!(rolled.effects_since(&before), vec![SoundEffect::Roll]);
+
+        let moved = vec![Player::spawn_at(
+            Coordinates(0, 1),
+            String::new(),
+            0,
+            PlayerType::LocalHuman,
+        )];
+        game_state.picked_up_item = Some("Reflect".to_string());
+        game_state.winners.push(0);
+        let finished = SoundTriggers::capture(&game_state, &moved);
+        assert_eq!(
+            finished.effects_since(&rolled),
+            vec![SoundEffect::Step, SoundEffect::PickUp, SoundEffect::Goal]
+        );
+    }
+
+    #[test]
+    fn turn_timer_runs_out() {
+        use zinkd::npc::{ItemAlgorithm, MoveAlgorithm};
+
+        let mut game_state = GameState {
+            turn_time_limit: Some(1.),
+            ..Default::default()
+        };
+        let computer =
+            PlayerType::Computer(MoveAlgorithm::ShortestPath, ItemAlgorithm::HighestGain);
+        assert!(!tick_turn_timer(
+            &mut game_state,
+            computer,
+            Duration::from_secs(5)
+        ));
+        let human = PlayerType::LocalHuman;
+        assert!(!tick_turn_timer(
+            &mut game_state,
+            human,
+            Duration::from_millis(600)
+        ));
+        let remaining = game_state.turn_time_remaining(human).unwrap();
+        assert!((remaining - 0.4).abs() < 1e-4);
+        assert!(tick_turn_timer(
+            &mut game_state,
+            human,
+            Duration::from_millis(600)
+        ));
+        assert!(game_state.turn_elapsed.is_zero());
+
+        tick_turn_timer(&mut game_state, human, Duration::from_millis(500));
+        game_state.teams = vec![1];
+        game_state.turn_order = vec![0];
+        game_state.player_names = vec![String::new()];
+        end_turn(&mut game_state);
+        assert!(game_state.turn_elapsed.is_zero());
+    }
+}
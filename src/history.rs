@@ -0,0 +1,190 @@
+// MIT/Apache 2.0 dual license
+// Apache 2.0
+// Copyright 2022 Arc676/Alessandro Vinciguerra <alesvinciguerra@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// MIT
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::AppState;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use directories_next::ProjectDirs;
+use std::fs::{create_dir_all, File};
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of matches kept in the history file. Older matches are discarded
+/// once the history is full.
+const MAX_RECORDS: usize = 100;
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct MatchRecord {
+    /// Seconds since the Unix epoch at which the match finished
+    pub timestamp: u64,
+    pub players: Vec<String>,
+    /// Place and name of each player who finished, in the order they finished
+    pub finishing_order: Vec<(usize, String)>,
+    pub turns: u32,
+}
+
+impl MatchRecord {
+    pub fn new(players: Vec<String>, finishing_order: Vec<(usize, String)>, turns: u32) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        MatchRecord {
+            timestamp,
+            players,
+            finishing_order,
+            turns,
+        }
+    }
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct MatchHistory {
+    records: Vec<MatchRecord>,
+}
+
+impl MatchHistory {
+    pub fn records(&self) -> &[MatchRecord] {
+        &self.records
+    }
+
+    pub fn add_record(&mut self, record: MatchRecord) {
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            let excess = self.records.len() - MAX_RECORDS;
+            self.records.drain(..excess);
+        }
+    }
+}
+
+fn format_age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let age = now.saturating_sub(timestamp);
+    match age {
+        0..=59 => "Just now".to_string(),
+        60..=3599 => format!("{} minutes ago", age / 60),
+        3600..=86399 => format!("{} hours ago", age / 3600),
+        _ => format!("{} days ago", age / 86400),
+    }
+}
+
+pub fn load_history(mut history: ResMut<MatchHistory>) {
+    #[cfg(feature = "serde")]
+    if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
+        let mut file = dir.data_dir().to_path_buf();
+        file.push("history.ron");
+        if let Ok(mut file) = File::open(file) {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)
+                .expect("Failed to read match history");
+            if let Ok(existing) = ron::from_str(contents.as_str()) {
+                *history = existing;
+            }
+        }
+    }
+}
+
+pub fn write_history(history: &MatchHistory) {
+    #[cfg(feature = "serde")]
+    if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
+        let mut file = dir.data_dir().to_path_buf();
+        create_dir_all(&file).expect("Failed to create data directory");
+        file.push("history.ron");
+        let mut file = File::create(file).expect("Failed to create match history file");
+        file.write(ron::to_string(history).unwrap().as_ref())
+            .expect("Failed to write match history to disk");
+    }
+}
+
+pub fn history_ui(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    history: Res<MatchHistory>,
+) {
+    egui::CentralPanel::default().show(egui_context.ctx_mut(), |ui| {
+        ui.heading("Match history");
+
+        if ui.button("Back to Main").clicked() {
+            state.set(AppState::MainMenu).unwrap();
+        }
+
+        ui.add(egui::Separator::default().horizontal());
+
+        if history.records().is_empty() {
+            ui.label("No matches played yet");
+            return;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, record) in history.records().iter().enumerate().rev() {
+                let title = format!(
+                    "{}: {} ({} players)",
+                    format_age(record.timestamp),
+                    record
+                        .finishing_order
+                        .first()
+                        .map_or("No winner", |(_, name)| name.as_str()),
+                    record.players.len()
+                );
+                egui::CollapsingHeader::new(title)
+                    .id_source(i)
+                    .show(ui, |ui| {
+                        ui.label(format!("Players: {}", record.players.join(", ")));
+                        ui.label(format!("Turns played: {}", record.turns));
+                        ui.label("Finishing order:");
+                        for (place, name) in record.finishing_order.iter() {
+                            ui.label(format!("{}: {}", place, name));
+                        }
+                    });
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::*;
+
+    #[test]
+    fn history_is_capped() {
+        let mut history = MatchHistory::default();
+        for turns in 0..MAX_RECORDS as u32 + 5 {
+            history.add_record(MatchRecord::new(vec![], vec![], turns));
+        }
+        assert_eq!(history.records().len(), MAX_RECORDS);
+        assert_eq!(history.records()[0].turns, 5);
+    }
+}
@@ -36,8 +36,11 @@ use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 
 mod about;
+mod dice_lab;
 mod game;
+mod history;
 mod main_menu;
+mod replay;
 mod settings;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -46,6 +49,8 @@ pub enum AppState {
     Game,
     Settings,
     About,
+    History,
+    DiceLab,
 }
 
 fn main() {
@@ -54,8 +59,19 @@ fn main() {
         .add_plugin(EguiPlugin)
         .add_state(AppState::MainMenu)
         .insert_resource(settings::GameSettings::default())
+        .insert_resource(settings::KeyBindings::default())
+        .insert_resource(history::MatchHistory::default())
+        .insert_resource(game::SavedGame::default())
+        .insert_resource(replay::LastReplay::default())
         .add_startup_system(settings::load_settings)
-        .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(main_menu::setup_menu))
+        .add_startup_system(history::load_history)
+        .add_system(settings::apply_ui_scale)
+        .add_system_set(
+            SystemSet::on_enter(AppState::MainMenu)
+                .with_system(game::load_game.label("LoadGame"))
+                .with_system(replay::load_replay.label("LoadReplay"))
+                .with_system(main_menu::setup_menu.after("LoadGame").after("LoadReplay")),
+        )
         .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu::main_menu))
         .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(main_menu::cleanup_menu))
         .add_system_set(SystemSet::on_enter(AppState::Game).with_system(game::setup_game))
@@ -64,14 +80,26 @@ fn main() {
                 .with_system(game::scroll_game.label("Camera"))
                 .with_system(game::update_game.before("Camera"))
                 .with_system(game::update_die)
+                .with_system(game::update_direction_indicators)
+                .with_system(game::update_ghost)
+                .with_system(game::update_step_numbers)
+                .with_system(game::update_reachable_highlights)
+                .with_system(game::play_sound_effects)
                 .with_system(game::control_panel)
                 .with_system(game::item_panel)
                 .with_system(game::entity_tooltips)
-                .with_system(game::pause_menu),
+                .with_system(game::pause_menu)
+                .with_system(game::end_match_dialog)
+                .with_system(game::help_overlay),
         )
         .add_system_set(SystemSet::on_exit(AppState::Game).with_system(game::cleanup_game))
         .add_system_set(SystemSet::on_update(AppState::Settings).with_system(settings::settings_ui))
         .add_system_set(SystemSet::on_exit(AppState::Settings).with_system(settings::save_settings))
         .add_system_set(SystemSet::on_update(AppState::About).with_system(about::about_ui))
+        .add_system_set(SystemSet::on_update(AppState::History).with_system(history::history_ui))
+        .add_system_set(
+            SystemSet::on_enter(AppState::DiceLab).with_system(dice_lab::setup_dice_lab),
+        )
+        .add_system_set(SystemSet::on_update(AppState::DiceLab).with_system(dice_lab::dice_lab_ui))
         .run();
 }
@@ -32,15 +32,22 @@
 // IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+use crate::game::SavedGame;
+use crate::replay::LastReplay;
 use crate::AppState;
 use bevy::app::AppExit;
 use bevy::prelude::*;
 
 pub struct MainMenu {
     play_btn: Entity,
+    resume_btn: Option<Entity>,
+    replay_btn: Option<Entity>,
+    ghost_btn: Option<Entity>,
     settings_btn: Entity,
     quit_btn: Entity,
     about_btn: Entity,
+    history_btn: Entity,
+    dice_lab_btn: Entity,
 }
 
 const NORMAL_BUTTON: Color = Color::rgb(0.35, 0.35, 0.35);
@@ -79,17 +86,42 @@ macro_rules! button_with_text {
     };
 }
 
-pub fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    saved: Res<SavedGame>,
+    last_replay: Res<LastReplay>,
+) {
     commands.spawn_bundle(UiCameraBundle::default());
     let play_btn = button_with_text!(commands, asset_server, "Play");
+    let resume_btn = if saved.exists() {
+        Some(button_with_text!(commands, asset_server, "Resume"))
+    } else {
+        None
+    };
+    let (replay_btn, ghost_btn) = if last_replay.exists() {
+        (
+            Some(button_with_text!(commands, asset_server, "Replay")),
+            Some(button_with_text!(commands, asset_server, "Ghost")),
+        )
+    } else {
+        (None, None)
+    };
     let settings_btn = button_with_text!(commands, asset_server, "Settings");
+    let history_btn = button_with_text!(commands, asset_server, "History");
+    let dice_lab_btn = button_with_text!(commands, asset_server, "Dice Lab");
     let about_btn = button_with_text!(commands, asset_server, "About");
     let quit_btn = button_with_text!(commands, asset_server, "Quit");
     commands.insert_resource(MainMenu {
         play_btn,
+        resume_btn,
+        replay_btn,
+        ghost_btn,
         settings_btn,
         quit_btn,
         about_btn,
+        history_btn,
+        dice_lab_btn,
     });
 }
 
@@ -100,15 +132,30 @@ pub fn main_menu(
     mut interaction_query: Query<ColoredButton, ButtonFilter>,
     mut app_exit_events: EventWriter<AppExit>,
     menu: Res<MainMenu>,
+    mut saved: ResMut<SavedGame>,
+    mut last_replay: ResMut<LastReplay>,
 ) {
     for (entity, interaction, mut color) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
                 *color = PRESSED_BUTTON.into();
                 if entity == menu.play_btn {
+                    saved.discard();
+                    state.set(AppState::Game).unwrap();
+                } else if Some(entity) == menu.resume_btn {
+                    state.set(AppState::Game).unwrap();
+                } else if Some(entity) == menu.replay_btn {
+                    last_replay.watch();
+                    state.set(AppState::Game).unwrap();
+                } else if Some(entity) == menu.ghost_btn {
+                    last_replay.race();
                     state.set(AppState::Game).unwrap();
                 } else if entity == menu.settings_btn {
                     state.set(AppState::Settings).unwrap();
+                } else if entity == menu.history_btn {
+                    state.set(AppState::History).unwrap();
+                } else if entity == menu.dice_lab_btn {
+                    state.set(AppState::DiceLab).unwrap();
                 } else if entity == menu.about_btn {
                     state.set(AppState::About).unwrap();
                 } else {
@@ -127,7 +174,18 @@ pub fn main_menu(
 
 pub fn cleanup_menu(mut commands: Commands, menu: Res<MainMenu>) {
     commands.entity(menu.play_btn).despawn_recursive();
+    if let Some(resume_btn) = menu.resume_btn {
+        commands.entity(resume_btn).despawn_recursive();
+    }
+    if let Some(replay_btn) = menu.replay_btn {
+        commands.entity(replay_btn).despawn_recursive();
+    }
+    if let Some(ghost_btn) = menu.ghost_btn {
+        commands.entity(ghost_btn).despawn_recursive();
+    }
     commands.entity(menu.settings_btn).despawn_recursive();
     commands.entity(menu.quit_btn).despawn_recursive();
     commands.entity(menu.about_btn).despawn_recursive();
+    commands.entity(menu.history_btn).despawn_recursive();
+    commands.entity(menu.dice_lab_btn).despawn_recursive();
 }
@@ -0,0 +1,522 @@
+// MIT/Apache 2.0 dual license
+// Apache 2.0
+// Copyright 2022 Arc676/Alessandro Vinciguerra <alesvinciguerra@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// MIT
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::settings::GameSettings;
+use bevy::prelude::*;
+#[cfg(feature = "serde")]
+use directories_next::ProjectDirs;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+#[cfg(feature = "serde")]
+use std::fs::create_dir_all;
+#[cfg(feature = "serde")]
+use std::io;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use zinkd::items::TransferParameters;
+use zinkd::map::{Coordinates, Direction};
+
+/// A choice made by a player during a game. Steps that follow a corridor on
+/// their own aren't choices and aren't recorded.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ReplayEvent {
+    Roll {
+        player: usize,
+        rolled: u32,
+    },
+    Step {
+        player: usize,
+        direction: Direction,
+    },
+    /// The player stopped moving with `remaining` steps left
+    Stop {
+        player: usize,
+        remaining: u32,
+    },
+    /// `finishes` is whether the item use also ended the user's turn, or
+    /// their chance to use an item before rolling. `transfer` holds the
+    /// faces and strength picked for a custom weight transfer.
+    UseItem {
+        user: usize,
+        item: usize,
+        target: usize,
+        finishes: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        transfer: Option<TransferParameters>,
+    },
+    Drop {
+        player: usize,
+        item: usize,
+        at: Coordinates,
+    },
+    /// `at` is where the player ended their turn. Replays recorded before
+    /// positions were kept don't have it.
+    EndTurn {
+        player: usize,
+        #[cfg_attr(feature = "serde", serde(default))]
+        at: Option<Coordinates>,
+    },
+}
+
+/// Everything needed to play a game again: the settings and seed it was
+/// started with and the choices the players made, in order
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Replay {
+    seed: u64,
+    settings: GameSettings,
+    turn_order: Vec<usize>,
+    events: Vec<ReplayEvent>,
+    finishing_order: Vec<usize>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, settings: GameSettings, turn_order: Vec<usize>) -> Self {
+        Replay {
+            seed,
+            settings,
+            turn_order,
+            events: vec![],
+            finishing_order: vec![],
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn settings(&self) -> &GameSettings {
+        &self.settings
+    }
+
+    pub fn turn_order(&self) -> &[usize] {
+        &self.turn_order
+    }
+
+    pub fn events(&self) -> &[ReplayEvent] {
+        &self.events
+    }
+
+    /// The players who reached the goal, in the order they got there
+    pub fn finishing_order(&self) -> &[usize] {
+        &self.finishing_order
+    }
+
+    /// Where the player stood at the end of each of their turns. Turns are
+    /// only recorded up to the first one without a position.
+    pub fn turn_end_positions(&self, player: usize) -> Vec<Coordinates> {
+        self.events
+            .iter()
+            .filter_map(|event| match *event {
+                ReplayEvent::EndTurn { player: ended, at } if ended == player => Some(at),
+                _ => None,
+            })
+            .map_while(|at| at)
+            .collect()
+    }
+}
+
+/// A player's run from a replay, shown alongside a live game on the same map
+/// so that the player can race against it
+pub struct Ghost {
+    player: usize,
+    // Where the ghost stood after each of its turns, starting from its
+    // starting position
+    path: Vec<Coordinates>,
+    finishing_turn: Option<usize>,
+    // The turns the live player has finished so far
+    turns: usize,
+}
+
+impl Ghost {
+    /// The ghost of the given player in the replay, who started the game at
+    /// `start`. A game that ended as the player reached the goal has no
+    /// position for their last turn, so `finish` gives where they ended up.
+    pub fn new(
+        replay: &Replay,
+        player: usize,
+        start: Coordinates,
+        finish: impl FnOnce(Coordinates) -> Coordinates,
+    ) -> Self {
+        let mut path = vec![start];
+        path.extend(replay.turn_end_positions(player));
+        let finishing_turn = if replay.finishing_order().contains(&player) {
+            let last = *path.last().unwrap();
+            let goal = finish(last);
+            if last != goal {
+                path.push(goal);
+            }
+            Some(path.len() - 1)
+        } else {
+            None
+        };
+        Ghost {
+            player,
+            path,
+            finishing_turn,
+            turns: 0,
+        }
+    }
+
+    /// The player whose run the ghost follows
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    /// Where the ghost stood after the given number of its turns. Once its
+    /// recorded turns run out, it stays where it ended up.
+    pub fn position_after(&self, turns: usize) -> Coordinates {
+        self.path[turns.min(self.path.len() - 1)]
+    }
+
+    /// Where the ghost stands as of the live player's turn
+    pub fn position(&self) -> Coordinates {
+        self.position_after(self.turns)
+    }
+
+    /// Keeps the ghost in step with the live player
+    pub fn turn_ended(&mut self, player: usize) {
+        if player == self.player {
+            self.turns += 1;
+        }
+    }
+
+    /// The turn on which the ghost reached the goal, if it did
+    pub fn finishing_turn(&self) -> Option<usize> {
+        self.finishing_turn
+    }
+
+    /// The live player's current turn, counting from one
+    pub fn current_turn(&self) -> usize {
+        self.turns + 1
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Replay {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let text = ron::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        std::fs::write(path, text)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        let text = std::fs::read_to_string(path)?;
+        ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+enum ReplayMode {
+    Recording(Replay),
+    // Events before `next` have already happened again
+    Playback {
+        replay: Replay,
+        next: usize,
+        diverged: bool,
+    },
+}
+
+/// How a game with a replay ended
+pub enum ReplayOutcome<'a> {
+    /// The game was recorded and the replay can be saved
+    Recorded(&'a Replay),
+    /// The replay was played back and ended the same way as the recorded game
+    Matched,
+    /// The replay was played back but the game turned out differently
+    Diverged,
+}
+
+/// The replay of the game in progress. The dice are rolled with a generator
+/// seeded from the replay so that playing it back gives the same rolls.
+pub struct ReplaySession {
+    rng: StdRng,
+    mode: ReplayMode,
+}
+
+impl ReplaySession {
+    pub fn recording(replay: Replay) -> Self {
+        ReplaySession {
+            rng: StdRng::seed_from_u64(replay.seed),
+            mode: ReplayMode::Recording(replay),
+        }
+    }
+
+    pub fn playback(replay: Replay) -> Self {
+        ReplaySession {
+            rng: StdRng::seed_from_u64(replay.seed),
+            mode: ReplayMode::Playback {
+                replay,
+                next: 0,
+                diverged: false,
+            },
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    pub fn is_playing_back(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playback { .. })
+    }
+
+    /// The next event to play back, if any are left
+    pub fn next_event(&self) -> Option<ReplayEvent> {
+        match &self.mode {
+            ReplayMode::Recording(_) => None,
+            ReplayMode::Playback { replay, next, .. } => replay.events.get(*next).copied(),
+        }
+    }
+
+    /// Adds an event to the replay being recorded. During playback, the event
+    /// is checked against the recording instead.
+    pub fn record(&mut self, event: ReplayEvent) {
+        match &mut self.mode {
+            ReplayMode::Recording(replay) => replay.events.push(event),
+            ReplayMode::Playback {
+                replay,
+                next,
+                diverged,
+            } => {
+                let matches = match (replay.events.get(*next), event) {
+                    // Older replays don't know where turns ended
+                    (
+                        Some(ReplayEvent::EndTurn {
+                            player: recorded,
+                            at: None,
+                        }),
+                        ReplayEvent::EndTurn { player, .. },
+                    ) => *recorded == player,
+                    (recorded, event) => recorded == Some(&event),
+                };
+                if !matches {
+                    warn!(event = *next, "Replay diverged from the recorded game");
+                    *diverged = true;
+                }
+                *next += 1;
+            }
+        }
+    }
+
+    pub fn finish(&mut self, finishing_order: &[usize]) -> ReplayOutcome<'_> {
+        match &mut self.mode {
+            ReplayMode::Recording(replay) => {
+                replay.finishing_order = finishing_order.to_vec();
+                ReplayOutcome::Recorded(replay)
+            }
+            ReplayMode::Playback {
+                replay,
+                next,
+                diverged,
+            } => {
+                if *diverged
+                    || *next != replay.events.len()
+                    || replay.finishing_order != finishing_order
+                {
+                    ReplayOutcome::Diverged
+                } else {
+                    ReplayOutcome::Matched
+                }
+            }
+        }
+    }
+}
+
+/// The replay of the last finished game, which can be watched or raced against
+/// from the main menu
+#[derive(Default)]
+pub struct LastReplay {
+    replay: Option<Replay>,
+    watch: bool,
+    race: bool,
+}
+
+impl LastReplay {
+    pub fn exists(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Plays the replay back in the next game
+    pub fn watch(&mut self) {
+        self.watch = true;
+    }
+
+    /// The replay to play back, if the player chose to watch it
+    pub fn take_to_watch(&mut self) -> Option<Replay> {
+        if !std::mem::take(&mut self.watch) {
+            return None;
+        }
+        self.replay.take()
+    }
+
+    /// Races against a ghost of the replay in the next game
+    pub fn race(&mut self) {
+        self.race = true;
+    }
+
+    /// The replay to race against, if the player chose to
+    pub fn take_to_race(&mut self) -> Option<Replay> {
+        if !std::mem::take(&mut self.race) {
+            return None;
+        }
+        self.replay.take()
+    }
+}
+
+pub fn load_replay(mut last: ResMut<LastReplay>) {
+    #[cfg(feature = "serde")]
+    if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
+        let file = dir.data_dir().join("replay.ron");
+        last.replay = Replay::load(file).ok();
+    }
+}
+
+pub fn write_replay(replay: &Replay) {
+    #[cfg(feature = "serde")]
+    if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
+        let dir = dir.data_dir();
+        let file = dir.join("replay.ron");
+        if let Err(error) = create_dir_all(dir).and_then(|_| replay.save(file)) {
+            warn!(%error, "Failed to save replay");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::replay::*;
+    use rand::Rng;
+    use zinkd::map::NORTH;
+
+    fn events() -> Vec<ReplayEvent> {
+        vec![
+            ReplayEvent::Roll {
+                player: 0,
+                rolled: 3,
+            },
+            ReplayEvent::Step {
+                player: 0,
+                direction: NORTH,
+            },
+            ReplayEvent::EndTurn {
+                player: 0,
+                at: Some(Coordinates(2, 3)),
+            },
+        ]
+    }
+
+    fn recorded() -> Replay {
+        let mut session =
+            ReplaySession::recording(Replay::new(5, GameSettings::default(), vec![0]));
+        for event in events() {
+            session.record(event);
+        }
+        assert!(matches!(session.finish(&[0]), ReplayOutcome::Recorded(_)));
+        match session.mode {
+            ReplayMode::Recording(replay) => replay,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn playback_follows_the_recording() {
+        let replay = recorded();
+        let mut recording =
+            ReplaySession::recording(Replay::new(5, GameSettings::default(), vec![0]));
+        let mut playback = ReplaySession::playback(replay);
+        assert!(playback.is_playing_back());
+        assert_eq!(recording.rng().gen::<u64>(), playback.rng().gen::<u64>());
+
+        for event in events() {
+            assert!(playback.next_event() == Some(event));
+            playback.record(event);
+        }
+        assert!(playback.next_event().is_none());
+        assert!(matches!(playback.finish(&[0]), ReplayOutcome::Matched));
+        assert!(matches!(playback.finish(&[]), ReplayOutcome::Diverged));
+
+        let mut playback = ReplaySession::playback(recorded());
+        playback.record(ReplayEvent::Roll {
+            player: 0,
+            rolled: 4,
+        });
+        assert!(matches!(playback.finish(&[0]), ReplayOutcome::Diverged));
+    }
+
+    #[test]
+    fn ghost_follows_recorded_turns() {
+        let mut replay = Replay::new(5, GameSettings::default(), vec![0, 1]);
+        let ends = [(0, 1, 0), (1, 5, 5), (0, 2, 0), (0, 2, 1)];
+        for (player, x, y) in ends {
+            replay.events.push(ReplayEvent::EndTurn {
+                player,
+                at: Some(Coordinates(x, y)),
+            });
+        }
+        replay.finishing_order = vec![0];
+        let mut ghost = Ghost::new(&replay, 0, Coordinates(0, 0), |_| Coordinates(3, 1));
+        let recorded = replay.turn_end_positions(0);
+        assert_eq!(recorded.len(), 3);
+        assert!(ghost.position_after(0) == Coordinates(0, 0));
+        for (turn, at) in recorded.iter().enumerate() {
+            assert!(ghost.position_after(turn + 1) == *at);
+        }
+        // The game ended on the turn the player reached the goal
+        assert_eq!(ghost.finishing_turn(), Some(4));
+        assert!(ghost.position_after(10) == Coordinates(3, 1));
+
+        ghost.turn_ended(1);
+        assert!(ghost.position() == Coordinates(0, 0));
+        ghost.turn_ended(0);
+        assert!(ghost.position() == Coordinates(1, 0));
+        assert_eq!(ghost.current_turn(), 2);
+
+        let other = Ghost::new(&replay, 1, Coordinates(4, 4), |at| at);
+        assert_eq!(other.finishing_turn(), None);
+        assert!(other.position_after(1) == Coordinates(5, 5));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn replay_round_trip() {
+        let replay = recorded();
+        let path = std::env::temp_dir().join("zinkd_replay_round_trip.ron");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.seed(), 5);
+        assert!(loaded.events() == replay.events());
+        assert_eq!(loaded.finishing_order(), &[0]);
+        assert!(loaded.settings() == replay.settings());
+    }
+}
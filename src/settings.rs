@@ -36,7 +36,7 @@ use crate::AppState;
 use bevy::prelude::*;
 use bevy_egui::egui::emath::Numeric;
 use bevy_egui::egui::{Separator, Slider, Ui};
-use bevy_egui::{egui, EguiContext};
+use bevy_egui::{egui, EguiClipboard, EguiContext, EguiSettings};
 use directories_next::ProjectDirs;
 use ron;
 use serde;
@@ -44,8 +44,15 @@ use std::fmt::Formatter;
 use std::fs::{create_dir_all, File};
 use std::io::{Read, Write};
 use std::slice::Iter;
+use zinkd::dice::{WeightedDie, DEFAULT_FACES, MAX_FACES, MIN_FACES};
+use zinkd::map::{Map, MapGenerator, MAP_GENERATORS};
 use zinkd::npc::{self, ItemAlgorithm, MoveAlgorithm};
-use zinkd::player::PlayerType;
+use zinkd::player::{
+    PlayerType, TeamWinCondition, WinCondition, TEAM_WIN_CONDITIONS, WIN_CONDITIONS,
+};
+
+/// Seconds per turn when turns are first limited
+const DEFAULT_TURN_TIME_LIMIT: f32 = 30.;
 
 #[derive(Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
@@ -76,39 +83,382 @@ impl PlayerSprite {
     }
 }
 
+/// Presets for how much players can see about each other
+#[derive(Copy, Clone, PartialEq)]
+pub enum InformationMode {
+    Full,
+    Standard,
+    Hidden,
+}
+pub const INFORMATION_MODES: [InformationMode; 3] = [
+    InformationMode::Full,
+    InformationMode::Standard,
+    InformationMode::Hidden,
+];
+
+impl std::fmt::Display for InformationMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                InformationMode::Full => "Full",
+                InformationMode::Standard => "Standard",
+                InformationMode::Hidden => "Hidden",
+            }
+        )
+    }
+}
+
+impl InformationMode {
+    pub fn description(&self) -> &'static str {
+        match self {
+            InformationMode::Full => "Everything is visible. Good for learning the game.",
+            InformationMode::Standard => {
+                "Players can see their own die and where everyone is heading"
+            }
+            InformationMode::Hidden => {
+                "Players can only see their own die and the map. Good for competitive games."
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+pub const SCREEN_CORNERS: [ScreenCorner; 4] = [
+    ScreenCorner::TopLeft,
+    ScreenCorner::TopRight,
+    ScreenCorner::BottomLeft,
+    ScreenCorner::BottomRight,
+];
+
+impl Default for ScreenCorner {
+    fn default() -> Self {
+        ScreenCorner::BottomRight
+    }
+}
+
+impl std::fmt::Display for ScreenCorner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ScreenCorner::TopLeft => "Top left",
+                ScreenCorner::TopRight => "Top right",
+                ScreenCorner::BottomLeft => "Bottom left",
+                ScreenCorner::BottomRight => "Bottom right",
+            }
+        )
+    }
+}
+
+/// Presets that size the map and pick how strong the computer players are
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+pub const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Difficulty::Easy => "Easy",
+                Difficulty::Normal => "Normal",
+                Difficulty::Hard => "Hard",
+            }
+        )
+    }
+}
+
+impl Difficulty {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "A small map with plenty of items and computers that wander",
+            Difficulty::Normal => "The default game",
+            Difficulty::Hard => "A large map with few items and computers that play to win",
+        }
+    }
+
+    fn computer_player(&self) -> PlayerType {
+        match self {
+            Difficulty::Easy => {
+                PlayerType::Computer(MoveAlgorithm::RandomWalk, ItemAlgorithm::HighestGain)
+            }
+            Difficulty::Normal => {
+                PlayerType::Computer(MoveAlgorithm::ShortestPath, ItemAlgorithm::HighestGain)
+            }
+            Difficulty::Hard => PlayerType::Computer(MoveAlgorithm::AStar, ItemAlgorithm::Sabotage),
+        }
+    }
+
+    /// Overwrites the settings covered by the preset. Everything else,
+    /// including which players are computers, is left as it is.
+    pub fn apply_to(&self, settings: &mut GameSettings) {
+        let (size, travel_distance, item_density, walking_speed) = match self {
+            Difficulty::Easy => (30, 20, 0.2, 1.5),
+            Difficulty::Normal => (60, 40, 0.1, 2.),
+            Difficulty::Hard => (90, 60, 0.05, 3.),
+        };
+        settings.map_width = size;
+        settings.map_height = size;
+        settings.initial_travel_distance = travel_distance;
+        settings.item_density = item_density;
+        settings.walking_speed = walking_speed;
+        for ptype in settings.player_types.iter_mut() {
+            if let PlayerType::Computer(..) = ptype {
+                *ptype = self.computer_player();
+            }
+        }
+        settings.difficulty = Some(*self);
+    }
+}
+
+/// Keys for the turn controls and for pausing the game
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct KeyBindings {
+    pub roll: KeyCode,
+    pub inventory: KeyCode,
+    pub north: KeyCode,
+    pub south: KeyCode,
+    pub east: KeyCode,
+    pub west: KeyCode,
+    pub north_east: KeyCode,
+    pub north_west: KeyCode,
+    pub south_east: KeyCode,
+    pub south_west: KeyCode,
+    pub stop: KeyCode,
+    pub end_turn: KeyCode,
+    pub pause: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            roll: KeyCode::R,
+            inventory: KeyCode::E,
+            north: KeyCode::W,
+            south: KeyCode::S,
+            east: KeyCode::D,
+            west: KeyCode::A,
+            north_east: KeyCode::Numpad9,
+            north_west: KeyCode::Numpad7,
+            south_east: KeyCode::Numpad3,
+            south_west: KeyCode::Numpad1,
+            stop: KeyCode::Space,
+            end_turn: KeyCode::Return,
+            pause: KeyCode::Escape,
+        }
+    }
+}
+
+impl KeyBindings {
+    fn keys(&self) -> [KeyCode; 13] {
+        [
+            self.roll,
+            self.inventory,
+            self.north,
+            self.south,
+            self.east,
+            self.west,
+            self.north_east,
+            self.north_west,
+            self.south_east,
+            self.south_west,
+            self.stop,
+            self.end_turn,
+            self.pause,
+        ]
+    }
+
+    fn keys_mut(&mut self) -> [(&'static str, &mut KeyCode); 13] {
+        [
+            ("Roll the die", &mut self.roll),
+            ("Show the inventory", &mut self.inventory),
+            ("Move north", &mut self.north),
+            ("Move south", &mut self.south),
+            ("Move east", &mut self.east),
+            ("Move west", &mut self.west),
+            ("Move northeast", &mut self.north_east),
+            ("Move northwest", &mut self.north_west),
+            ("Move southeast", &mut self.south_east),
+            ("Move southwest", &mut self.south_west),
+            ("Stop moving", &mut self.stop),
+            ("End the turn", &mut self.end_turn),
+            ("Pause the game", &mut self.pause),
+        ]
+    }
+
+    /// Keys that are bound to more than one action
+    pub fn conflicts(&self) -> Vec<KeyCode> {
+        let keys = self.keys();
+        let mut conflicts = vec![];
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].contains(key) && !conflicts.contains(key) {
+                conflicts.push(*key);
+            }
+        }
+        conflicts
+    }
+}
+
+/// Result of a solo time attack run
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct TimeAttackRecord {
+    pub turns: u32,
+    pub seconds: f32,
+}
+
+impl TimeAttackRecord {
+    /// Fewer turns is better; ties are broken by the elapsed time
+    pub fn is_better_than(&self, other: &TimeAttackRecord) -> bool {
+        (self.turns, self.seconds) < (other.turns, other.seconds)
+    }
+}
+
+impl std::fmt::Display for TimeAttackRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} turns in {:.1} seconds", self.turns, self.seconds)
+    }
+}
+
+// White leaves the player's sprite untinted
+const NO_TINT: [u8; 3] = [255, 255, 255];
+
+#[derive(Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct GameSettings {
+    difficulty: Option<Difficulty>,
     players: usize,
     player_sprites: Vec<PlayerSprite>,
     player_names: Vec<String>,
-    is_cc: Vec<bool>,
+    player_colors: Vec<[u8; 3]>,
     player_types: Vec<PlayerType>,
     map_width: usize,
     map_height: usize,
+    items_enabled: bool,
     item_density: f64,
+    item_bias: f64,
+    teleporter_density: f64,
+    trap_density: f64,
+    num_goals: usize,
+    map_generator: MapGenerator,
+    map_seed: String,
+    fair_dice_seed: String,
+    map_file: String,
     initial_travel_distance: usize,
     default_zoom_level: f32,
     walking_speed: f32,
+    reduced_motion: bool,
+    shuffle_turn_order: bool,
+    item_pickup_radius: usize,
+    map_peek_duration: f32,
+    turn_time_limit: Option<f32>,
+    ui_scale: f64,
+    teams: Vec<usize>,
+    team_win_condition: TeamWinCondition,
+    win_condition: WinCondition,
+    friendly_fire: bool,
+    die_size: f32,
+    die_corner: ScreenCorner,
+    custom_die_atlas: String,
+    die_atlas_columns: usize,
+    die_atlas_rows: usize,
+    time_attack: bool,
+    time_attack_best: Option<TimeAttackRecord>,
+    items_before_roll: bool,
+    diagonal_movement: bool,
+    toroidal_map: bool,
+    probability_floor: f64,
+    die_faces: usize,
+    collapse_on_roll: bool,
+    starting_dice: Vec<String>,
+    reveal_opponent_dice: bool,
+    hide_opponent_dice: bool,
+    reveal_opponent_inventories: bool,
+    show_standings: bool,
+    show_directions: bool,
+    key_bindings: KeyBindings,
+    sfx_volume: f32,
+    max_inventory: usize,
 }
 
 impl Default for GameSettings {
     fn default() -> Self {
         GameSettings {
+            difficulty: None,
             players: 2,
             player_sprites: vec![PlayerSprite::Ferris, PlayerSprite::Darryl],
             player_names: vec!["Ferris".to_string(), "Darryl".to_string()],
-            is_cc: vec![false, true],
+            player_colors: vec![NO_TINT; 2],
             player_types: vec![
                 PlayerType::LocalHuman,
                 PlayerType::Computer(MoveAlgorithm::ShortestPath, ItemAlgorithm::HighestGain),
             ],
             map_width: 60,
             map_height: 60,
+            items_enabled: true,
             item_density: 0.1,
+            item_bias: 0.,
+            teleporter_density: 0.,
+            trap_density: 0.,
+            num_goals: 1,
+            map_generator: MapGenerator::Corridors,
+            map_seed: String::new(),
+            fair_dice_seed: String::new(),
+            map_file: String::new(),
             initial_travel_distance: 40,
             default_zoom_level: 0.7,
             walking_speed: 2.,
+            reduced_motion: false,
+            shuffle_turn_order: false,
+            item_pickup_radius: 0,
+            map_peek_duration: 0.,
+            turn_time_limit: None,
+            ui_scale: 1.,
+            teams: vec![1, 2],
+            team_win_condition: TeamWinCondition::AllMembers,
+            win_condition: WinCondition::AllButLast,
+            friendly_fire: false,
+            die_size: 64.,
+            die_corner: ScreenCorner::BottomRight,
+            custom_die_atlas: String::new(),
+            die_atlas_columns: 6,
+            die_atlas_rows: 1,
+            time_attack: false,
+            time_attack_best: None,
+            items_before_roll: false,
+            diagonal_movement: false,
+            toroidal_map: false,
+            probability_floor: 0.,
+            die_faces: DEFAULT_FACES,
+            collapse_on_roll: false,
+            starting_dice: vec![String::new(); 2],
+            reveal_opponent_dice: true,
+            hide_opponent_dice: false,
+            reveal_opponent_inventories: true,
+            show_standings: true,
+            show_directions: true,
+            key_bindings: KeyBindings::default(),
+            sfx_volume: 1.,
+            max_inventory: 0,
         }
     }
 }
@@ -118,8 +468,89 @@ impl GameSettings {
         *self = GameSettings::default();
     }
 
+    /// Lists combinations of settings that are likely to make for a poor game.
+    /// These are only advisories and don't prevent starting the game.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        let area = self.map_width * self.map_height;
+        if self.item_density > 0.5 && area < 40 * 40 {
+            warnings.push(
+                "A high item density on a small map leaves little room for distinct paths"
+                    .to_string(),
+            );
+        }
+        let max_dist = self.map_height.min(self.map_width) * 3 / 4;
+        if self.initial_travel_distance > max_dist {
+            warnings.push(format!(
+                "The travel distance is too large for the map and will be reduced to at most {}",
+                max_dist
+            ));
+        } else if self.players() >= 4 && self.initial_travel_distance * 10 >= max_dist * 9 {
+            warnings.push(
+                "With many players and a long travel distance, players are likely to start \
+                crowded together near the edges of the map"
+                    .to_string(),
+            );
+        }
+        if self.walking_speed >= 8. {
+            warnings.push("Players will walk too fast for their moves to be followed".to_string());
+        }
+        if self.map_generator == MapGenerator::Staircases && !self.diagonal_movement {
+            warnings.push(
+                "Staircase maps take many turns to cross without diagonal movement".to_string(),
+            );
+        }
+        warnings
+    }
+
+    /// The last difficulty preset that was applied
+    pub fn difficulty(&self) -> Option<Difficulty> {
+        self.difficulty
+    }
+
+    /// Sets the individual visibility settings according to the preset
+    pub fn apply_information_mode(&mut self, mode: InformationMode) {
+        let (dice, inventories, directions) = match mode {
+            InformationMode::Full => (true, true, true),
+            InformationMode::Standard => (false, false, true),
+            InformationMode::Hidden => (false, false, false),
+        };
+        self.reveal_opponent_dice = dice;
+        self.hide_opponent_dice = !dice;
+        self.reveal_opponent_inventories = inventories;
+        self.show_directions = directions;
+        self.show_standings = directions;
+    }
+
+    pub fn reveal_opponent_dice(&self) -> bool {
+        self.reveal_opponent_dice
+    }
+
+    /// Whether the item preview hides other players' dice. Previews never
+    /// show dice that players aren't allowed to view.
+    pub fn hide_opponent_dice(&self) -> bool {
+        self.hide_opponent_dice || !self.reveal_opponent_dice
+    }
+
+    pub fn reveal_opponent_inventories(&self) -> bool {
+        self.reveal_opponent_inventories
+    }
+
+    pub fn show_directions(&self) -> bool {
+        self.show_directions
+    }
+
+    /// Whether to show who is closest to the goal during the match
+    pub fn show_standings(&self) -> bool {
+        self.show_standings
+    }
+
     pub fn players(&self) -> usize {
-        self.players
+        if self.time_attack {
+            1
+        } else {
+            self.players
+        }
     }
 
     pub fn player_sprites_iter(&self) -> Iter<'_, PlayerSprite> {
@@ -130,6 +561,10 @@ impl GameSettings {
         self.player_names.iter()
     }
 
+    pub fn player_color(&self, player: usize) -> [u8; 3] {
+        self.player_colors.get(player).copied().unwrap_or(NO_TINT)
+    }
+
     pub fn player_types_iter(&self) -> Iter<'_, PlayerType> {
         self.player_types.iter()
     }
@@ -142,8 +577,56 @@ impl GameSettings {
         self.map_height
     }
 
+    pub fn items_enabled(&self) -> bool {
+        self.items_enabled
+    }
+
+    /// The share of tiles holding an item, which is zero if items are off
     pub fn item_density(&self) -> f64 {
-        self.item_density
+        if self.items_enabled {
+            self.item_density
+        } else {
+            0.
+        }
+    }
+
+    /// How strongly item generation favors movement items on large maps and
+    /// die items on small ones. Zero generates every item type equally often.
+    pub fn item_bias(&self) -> f64 {
+        self.item_bias
+    }
+
+    pub fn teleporter_density(&self) -> f64 {
+        self.teleporter_density
+    }
+
+    pub fn trap_density(&self) -> f64 {
+        self.trap_density
+    }
+
+    pub fn num_goals(&self) -> usize {
+        self.num_goals
+    }
+
+    pub fn map_generator(&self) -> MapGenerator {
+        self.map_generator
+    }
+
+    /// The seed to generate the map from, or `None` for a random map
+    pub fn map_seed(&self) -> Option<u64> {
+        self.map_seed.trim().parse().ok()
+    }
+
+    /// The seed for everyone's dice in fair mode, or `None` to roll freely.
+    /// Each player rolls from the seed plus their player number, so the rolls
+    /// only stay equal until items change the weights of someone's die.
+    pub fn fair_dice_seed(&self) -> Option<u64> {
+        self.fair_dice_seed.trim().parse().ok()
+    }
+
+    /// The file to load the map from instead of generating one, if any
+    pub fn map_file(&self) -> Option<&str> {
+        Some(self.map_file.trim()).filter(|path| !path.is_empty())
     }
 
     pub fn travel_distance(&self) -> usize {
@@ -157,6 +640,154 @@ impl GameSettings {
     pub fn walking_speed(&self) -> f32 {
         self.walking_speed
     }
+
+    /// Whether players jump from tile to tile instead of walking between them
+    pub fn reduced_motion(&self) -> bool {
+        self.reduced_motion
+    }
+
+    pub fn shuffle_turn_order(&self) -> bool {
+        self.shuffle_turn_order
+    }
+
+    pub fn item_pickup_radius(&self) -> usize {
+        self.item_pickup_radius
+    }
+
+    /// The number of items each player can carry, or 0 for no limit
+    pub fn max_inventory(&self) -> usize {
+        self.max_inventory
+    }
+
+    /// Seconds each human player has for their turn, if turns are timed
+    pub fn turn_time_limit(&self) -> Option<f32> {
+        self.turn_time_limit
+    }
+
+    pub fn map_peek_duration(&self) -> f32 {
+        self.map_peek_duration
+    }
+
+    pub fn ui_scale(&self) -> f64 {
+        self.ui_scale
+    }
+
+    pub fn team(&self, player: usize) -> usize {
+        self.teams.get(player).copied().unwrap_or(player + 1)
+    }
+
+    pub fn team_win_condition(&self) -> TeamWinCondition {
+        self.team_win_condition
+    }
+
+    pub fn win_condition(&self) -> WinCondition {
+        self.win_condition
+    }
+
+    pub fn friendly_fire(&self) -> bool {
+        self.friendly_fire
+    }
+
+    pub fn die_size(&self) -> f32 {
+        self.die_size
+    }
+
+    pub fn die_corner(&self) -> ScreenCorner {
+        self.die_corner
+    }
+
+    pub fn custom_die_atlas(&self) -> &str {
+        &self.custom_die_atlas
+    }
+
+    pub fn die_atlas_columns(&self) -> usize {
+        self.die_atlas_columns
+    }
+
+    pub fn die_atlas_rows(&self) -> usize {
+        self.die_atlas_rows
+    }
+
+    /// The die the player starts with, if one was imported for them and it
+    /// has the configured number of faces. Everyone starts with the same die
+    /// in fair mode.
+    pub fn starting_die(&self, player: usize) -> Option<WeightedDie> {
+        if self.fair_dice_seed().is_some() {
+            return None;
+        }
+        self.starting_dice
+            .get(player)
+            .filter(|encoded| !encoded.is_empty())
+            .and_then(|encoded| WeightedDie::import_string(encoded).ok())
+            .filter(|die| die.faces() == self.die_faces)
+    }
+
+    pub fn die_faces(&self) -> usize {
+        self.die_faces
+    }
+
+    pub fn collapse_on_roll(&self) -> bool {
+        self.collapse_on_roll
+    }
+
+    pub fn probability_floor(&self) -> f64 {
+        self.probability_floor
+    }
+
+    pub fn items_before_roll(&self) -> bool {
+        self.items_before_roll
+    }
+
+    pub fn diagonal_movement(&self) -> bool {
+        self.diagonal_movement
+    }
+
+    /// Whether the edges of the map wrap around to the opposite edge
+    pub fn toroidal_map(&self) -> bool {
+        self.toroidal_map
+    }
+
+    pub fn time_attack(&self) -> bool {
+        self.time_attack
+    }
+
+    pub fn time_attack_best(&self) -> Option<TimeAttackRecord> {
+        self.time_attack_best
+    }
+
+    pub fn sfx_volume(&self) -> f32 {
+        self.sfx_volume
+    }
+
+    pub fn key_bindings(&self) -> &KeyBindings {
+        &self.key_bindings
+    }
+
+    /// Stores the result of a time attack run if it beats the previous best.
+    /// Returns whether the record was a new best.
+    pub fn record_time_attack(&mut self, record: TimeAttackRecord) -> bool {
+        match self.time_attack_best {
+            Some(best) if !record.is_better_than(&best) => false,
+            _ => {
+                self.time_attack_best = Some(record);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl GameSettings {
+    /// Encodes the settings as a string that can be shared with other players
+    pub fn export_string(&self) -> String {
+        base64::encode(ron::to_string(self).unwrap())
+    }
+
+    pub fn import_string(encoded: &str) -> Result<Self, String> {
+        let bytes = base64::decode(encoded.trim()).map_err(|_| "Not a valid settings string")?;
+        let text = String::from_utf8(bytes).map_err(|_| "Not a valid settings string")?;
+        ron::from_str(&text).map_err(|e| format!("Failed to read settings: {}", e))
+    }
 }
 
 fn number_setting<T>(ui: &mut Ui, num: &mut T, min: T, max: T, lbl: &str)
@@ -172,25 +803,76 @@ pub fn settings_ui(
     mut egui_context: ResMut<EguiContext>,
     mut state: ResMut<State<AppState>>,
     mut settings: ResMut<GameSettings>,
+    clipboard: Res<EguiClipboard>,
+    keyboard: Res<Input<KeyCode>>,
+    mut import_error: Local<Option<String>>,
+    mut die_error: Local<Option<String>>,
+    mut rebinding: Local<Option<usize>>,
+    mut map_status: Local<Option<Result<String, String>>>,
 ) {
     egui::CentralPanel::default().show(egui_context.ctx_mut(), |ui| {
         ui.heading("Zink'd: Settings");
 
-        number_setting(ui, &mut settings.players, 2, 6, "Number of players");
+        ui.horizontal(|ui| {
+            ui.label("Difficulty:");
+            let selected = settings
+                .difficulty
+                .map_or("Custom".to_string(), |difficulty| difficulty.to_string());
+            egui::ComboBox::from_id_source("difficulty_picker")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for difficulty in DIFFICULTIES {
+                        if ui
+                            .selectable_label(
+                                settings.difficulty == Some(difficulty),
+                                difficulty.to_string(),
+                            )
+                            .on_hover_text(difficulty.description())
+                            .clicked()
+                        {
+                            difficulty.apply_to(&mut settings);
+                        }
+                    }
+                });
+        });
+
+        ui.checkbox(
+            &mut settings.time_attack,
+            "Solo time attack: the first player races to the goal alone",
+        );
+        if let Some(best) = settings.time_attack_best {
+            ui.label(format!("Best time attack: {}", best));
+        }
+        if !settings.time_attack {
+            number_setting(ui, &mut settings.players, 2, 6, "Number of players");
+        }
         let size = settings.players;
         if size > settings.player_sprites.len() {
             settings.player_sprites.resize(size, PlayerSprite::Ferris);
             settings.player_names.resize(size, "New Player".to_string());
             settings.player_types.resize(size, PlayerType::LocalHuman);
-            settings.is_cc.resize(size, false);
+        }
+        while settings.teams.len() < size {
+            let team = settings.teams.len() + 1;
+            settings.teams.push(team);
+        }
+        if settings.starting_dice.len() < size {
+            settings.starting_dice.resize(size, String::new());
+        }
+        if settings.player_colors.len() < size {
+            settings.player_colors.resize(size, NO_TINT);
         }
 
-        for i in 0..size {
+        for i in 0..settings.players() {
             ui.label(format!("Player {}", i + 1));
             ui.horizontal(|ui| {
                 ui.label("Name:");
                 ui.text_edit_singleline(&mut settings.player_names[i]);
 
+                if !settings.time_attack {
+                    number_setting(ui, &mut settings.teams[i], 1, 6, "Team:");
+                }
+
                 ui.label("Avatar:");
                 let sprite = &mut settings.player_sprites[i];
                 egui::ComboBox::from_id_source(format!("sprite_picker_{}", i))
@@ -207,41 +889,178 @@ pub fn settings_ui(
                             PlayerSprite::Darryl.to_string(),
                         );
                     });
+                ui.label("Color:");
+                ui.color_edit_button_srgb(&mut settings.player_colors[i]);
 
-                if ui
-                    .checkbox(&mut settings.is_cc[i], "Computer controlled")
-                    .clicked()
-                {
-                    if settings.is_cc[i] {
-                        settings.player_types[i] = PlayerType::Computer(
-                            MoveAlgorithm::ShortestPath,
-                            ItemAlgorithm::HighestGain,
-                        );
-                    } else {
-                        settings.player_types[i] = PlayerType::LocalHuman;
+                if ui.button("Paste die").clicked() {
+                    let pasted = clipboard.get_contents().unwrap_or_default();
+                    match WeightedDie::import_string(&pasted) {
+                        Ok(die) if die.faces() != settings.die_faces => {
+                            *die_error = Some(format!(
+                                "The pasted die has {} faces but games use {}",
+                                die.faces(),
+                                settings.die_faces
+                            ));
+                        }
+                        Ok(_) => {
+                            settings.starting_dice[i] = pasted.trim().to_string();
+                            *die_error = None;
+                        }
+                        Err(e) => *die_error = Some(e),
                     }
                 }
+                if !settings.starting_dice[i].is_empty() {
+                    ui.label("Custom die");
+                    if ui.button("Use a fair die").clicked() {
+                        settings.starting_dice[i].clear();
+                    }
+                }
+
+                // Time attack is always played by a human
+                if settings.time_attack {
+                    return;
+                }
+                ui.label("Player:");
+                let computer = settings
+                    .difficulty
+                    .unwrap_or(Difficulty::Normal)
+                    .computer_player();
+                let ptype = &mut settings.player_types[i];
+                let is_computer = matches!(ptype, PlayerType::Computer(..));
+                egui::ComboBox::from_id_source(format!("type_picker_{}", i))
+                    .selected_text(if is_computer { "Computer" } else { "Human" })
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(!is_computer, "Human").clicked() {
+                            *ptype = PlayerType::LocalHuman;
+                        }
+                        // Keep the chosen strategy when reselecting Computer
+                        if ui.selectable_label(is_computer, "Computer").clicked() && !is_computer {
+                            *ptype = computer;
+                        }
+                    });
                 if let PlayerType::Computer(mv, it) = &mut settings.player_types[i] {
                     ui.label("Strategy");
                     egui::ComboBox::from_id_source(format!("move_picker_{}", i))
                         .selected_text(mv.to_string())
                         .show_ui(ui, |ui| {
                             for algo in npc::MOVE_ALGORITHMS {
-                                ui.selectable_value(mv, algo, algo.to_string());
+                                ui.selectable_value(mv, algo, algo.to_string())
+                                    .on_hover_text(algo.description());
                             }
-                        });
+                        })
+                        .response
+                        .on_hover_text(mv.description());
                     ui.label("Items");
                     egui::ComboBox::from_id_source(format!("item_picker_{}", i))
                         .selected_text(it.to_string())
                         .show_ui(ui, |ui| {
                             for algo in npc::ITEM_ALGORITHMS {
-                                ui.selectable_value(it, algo, algo.to_string());
+                                ui.selectable_value(it, algo, algo.to_string())
+                                    .on_hover_text(algo.description());
                             }
-                        });
+                        })
+                        .response
+                        .on_hover_text(it.description());
                 }
             });
         }
 
+        if let Some(error) = die_error.as_ref() {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.label(
+            "Players on the same team win together. With every player on their own team, \
+            the win condition decides when the game ends.",
+        );
+        ui.horizontal(|ui| {
+            ui.label("Game without teams ends when:");
+            let condition = &mut settings.win_condition;
+            egui::ComboBox::from_id_source("win_condition")
+                .selected_text(condition.to_string())
+                .show_ui(ui, |ui| {
+                    for option in WIN_CONDITIONS {
+                        ui.selectable_value(condition, option, option.to_string());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Team wins when:");
+            let condition = &mut settings.team_win_condition;
+            egui::ComboBox::from_id_source("team_win_condition")
+                .selected_text(condition.to_string())
+                .show_ui(ui, |ui| {
+                    for option in TEAM_WIN_CONDITIONS {
+                        ui.selectable_value(condition, option, option.to_string());
+                    }
+                });
+        });
+        ui.checkbox(
+            &mut settings.friendly_fire,
+            "Allow using items on teammates",
+        );
+        number_setting(
+            ui,
+            &mut settings.die_faces,
+            MIN_FACES,
+            MAX_FACES,
+            "Number of faces on each die",
+        );
+        number_setting(
+            ui,
+            &mut settings.probability_floor,
+            0.,
+            0.1,
+            "Minimum probability of rolling each face (0 to disable)",
+        );
+        ui.checkbox(&mut settings.collapse_on_roll, "Rolling collapses the die")
+            .on_hover_text(
+                "Each roll makes the rolled face more likely to come up again, \
+                like measuring a quantum state",
+            );
+        ui.checkbox(
+            &mut settings.items_before_roll,
+            "Allow using one item before rolling in addition to after moving",
+        );
+
+        ui.horizontal(|ui| {
+            ui.label("Information preset:");
+            for mode in INFORMATION_MODES {
+                if ui
+                    .button(mode.to_string())
+                    .on_hover_text(mode.description())
+                    .clicked()
+                {
+                    settings.apply_information_mode(mode);
+                }
+            }
+        });
+        ui.checkbox(
+            &mut settings.reveal_opponent_dice,
+            "Let players view other players' dice",
+        );
+        ui.checkbox(
+            &mut settings.hide_opponent_dice,
+            "Hide how items change other players' dice in the item preview",
+        );
+        ui.checkbox(
+            &mut settings.reveal_opponent_inventories,
+            "Let players view computer players' inventories",
+        );
+        ui.checkbox(
+            &mut settings.show_directions,
+            "Show the direction each player is heading by default",
+        );
+        ui.checkbox(
+            &mut settings.show_standings,
+            "Show which players are closest to the goal",
+        );
+
+        ui.checkbox(
+            &mut settings.shuffle_turn_order,
+            "Randomize turn order at the start of each game",
+        );
+
         let sep = Separator::default().spacing(12.).horizontal();
         ui.add(sep);
 
@@ -262,7 +1081,129 @@ pub fn settings_ui(
             "Initial travel distance",
         );
 
-        number_setting(ui, &mut settings.item_density, 0., 0.8, "Item density");
+        ui.checkbox(
+            &mut settings.items_enabled,
+            "Place items on the map (turn off for a pure race)",
+        );
+        if settings.items_enabled {
+            number_setting(ui, &mut settings.item_density, 0., 0.8, "Item density");
+            number_setting(
+                ui,
+                &mut settings.item_bias,
+                0.,
+                2.,
+                "Favor movement items on large maps (0 for uniform)",
+            );
+        }
+        number_setting(
+            ui,
+            &mut settings.teleporter_density,
+            0.,
+            0.1,
+            "Teleporter density (0 to disable)",
+        );
+        number_setting(
+            ui,
+            &mut settings.trap_density,
+            0.,
+            0.1,
+            "Trap density (0 to disable)",
+        );
+        number_setting(
+            ui,
+            &mut settings.num_goals,
+            1,
+            4,
+            "Number of goals (reaching any of them counts)",
+        );
+        ui.horizontal(|ui| {
+            ui.label("Map layout");
+            let generator = &mut settings.map_generator;
+            egui::ComboBox::from_id_source("map_generator")
+                .selected_text(generator.to_string())
+                .show_ui(ui, |ui| {
+                    for option in MAP_GENERATORS {
+                        ui.selectable_value(generator, option, option.to_string())
+                            .on_hover_text(option.description());
+                    }
+                })
+                .response
+                .on_hover_text(generator.description());
+        });
+        ui.checkbox(
+            &mut settings.diagonal_movement,
+            "Allow diagonal moves that cut across the corners of corridors",
+        );
+        ui.checkbox(
+            &mut settings.toroidal_map,
+            "Wrap the edges of the map around to the opposite edge",
+        );
+        ui.horizontal(|ui| {
+            ui.label("Map seed (leave empty for a random map):");
+            ui.text_edit_singleline(&mut settings.map_seed);
+        });
+        if !settings.map_seed.trim().is_empty() && settings.map_seed().is_none() {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "The map seed must be a whole number; a random map will be used",
+            );
+        }
+        ui.horizontal(|ui| {
+            ui.label("Fair dice seed (leave empty to roll freely):");
+            ui.text_edit_singleline(&mut settings.fair_dice_seed)
+                .on_hover_text(
+                    "Everyone starts with the same die and rolls from the same seed. \
+                     Items that change a die's weights still make the rolls diverge.",
+                );
+        });
+        if !settings.fair_dice_seed.trim().is_empty() && settings.fair_dice_seed().is_none() {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "The fair dice seed must be a whole number; dice will roll freely",
+            );
+        }
+        #[cfg(feature = "serde")]
+        ui.horizontal(|ui| {
+            ui.label("Map file (leave empty to generate a map):");
+            ui.text_edit_singleline(&mut settings.map_file);
+            if ui.button("Load map").clicked() {
+                *map_status = settings.map_file().map(|path| match Map::from_file(path) {
+                    Ok(map) if map.starting_positions().len() < settings.players() => Err(format!(
+                        "The map only has room for {} players",
+                        map.starting_positions().len()
+                    )),
+                    Ok(map) => Ok(format!(
+                        "Loaded a {}x{} map. It will be used instead of generating one.",
+                        map.width(),
+                        map.height()
+                    )),
+                    Err(e) => Err(format!("Failed to load the map: {}", e)),
+                });
+            }
+        });
+        match map_status.as_ref() {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(error)) => {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            None => {}
+        }
+        number_setting(
+            ui,
+            &mut settings.item_pickup_radius,
+            0,
+            2,
+            "Item pickup radius (0 to only pick up items on the tile you land on)",
+        );
+        number_setting(
+            ui,
+            &mut settings.max_inventory,
+            0,
+            10,
+            "Items each player can carry (0 for no limit)",
+        );
 
         let sep = Separator::default().spacing(12.).horizontal();
         ui.add(sep);
@@ -274,6 +1215,60 @@ pub fn settings_ui(
             10.,
             "Walking speed (tiles per second)",
         );
+        ui.checkbox(
+            &mut settings.reduced_motion,
+            "Reduce motion (players jump to each tile instead of walking)",
+        );
+
+        number_setting(
+            ui,
+            &mut settings.map_peek_duration,
+            0.,
+            10.,
+            "Seconds to show the whole map before the game starts (0 to disable)",
+        );
+
+        let mut timed = settings.turn_time_limit.is_some();
+        ui.checkbox(&mut timed, "Limit the time for each turn")
+            .on_hover_text(
+                "When time runs out, the die is rolled automatically or, if the player \
+                has already rolled, the turn ends. Computer players aren't timed.",
+            );
+        if !timed {
+            settings.turn_time_limit = None;
+        } else if let Some(limit) = settings.turn_time_limit.as_mut() {
+            number_setting(ui, limit, 5., 120., "Seconds per turn");
+        } else {
+            settings.turn_time_limit = Some(DEFAULT_TURN_TIME_LIMIT);
+        }
+
+        number_setting(ui, &mut settings.die_size, 32., 256., "Rolled die size");
+        ui.horizontal(|ui| {
+            ui.label("Rolled die position:");
+            let corner = &mut settings.die_corner;
+            egui::ComboBox::from_id_source("die_corner")
+                .selected_text(corner.to_string())
+                .show_ui(ui, |ui| {
+                    for option in SCREEN_CORNERS {
+                        ui.selectable_value(corner, option, option.to_string());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Custom die face image (leave empty for the default):");
+            ui.text_edit_singleline(&mut settings.custom_die_atlas);
+        });
+        ui.horizontal(|ui| {
+            number_setting(ui, &mut settings.die_atlas_columns, 1, 12, "Columns");
+            number_setting(ui, &mut settings.die_atlas_rows, 1, 12, "Rows");
+        });
+        if settings.die_atlas_columns * settings.die_atlas_rows < settings.die_faces {
+            ui.label(format!(
+                "The die face image needs at least one frame per face ({} frames)",
+                settings.die_faces
+            ));
+        }
 
         number_setting(
             ui,
@@ -283,13 +1278,78 @@ pub fn settings_ui(
             "Default camera zoom level (higher is more zoomed out)",
         );
 
+        number_setting(ui, &mut settings.ui_scale, 0.5, 3., "Interface scale");
+        number_setting(
+            ui,
+            &mut settings.sfx_volume,
+            0.,
+            1.,
+            "Sound effect volume (0 to mute)",
+        );
+
         let sep = Separator::default().spacing(12.).horizontal();
         ui.add(sep);
 
+        ui.label("Controls (click a key, then press the key to use instead)");
+        if let Some(index) = *rebinding {
+            if let Some(key) = keyboard.get_just_pressed().next() {
+                *settings.key_bindings.keys_mut()[index].1 = *key;
+                *rebinding = None;
+            }
+        }
+        egui::Grid::new("key_bindings").show(ui, |ui| {
+            for (i, (action, key)) in settings.key_bindings.keys_mut().into_iter().enumerate() {
+                ui.label(action);
+                let text = if *rebinding == Some(i) {
+                    "Press a key...".to_string()
+                } else {
+                    format!("{:?}", key)
+                };
+                if ui.button(text).clicked() {
+                    *rebinding = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        for key in settings.key_bindings.conflicts() {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!("{:?} is bound to more than one action", key),
+            );
+        }
+
+        let sep = Separator::default().spacing(12.).horizontal();
+        ui.add(sep);
+
+        for warning in settings.warnings() {
+            ui.colored_label(egui::Color32::YELLOW, warning);
+        }
+
         if ui.button("Revert to default settings").clicked() {
             settings.reset_settings();
         }
 
+        #[cfg(feature = "serde")]
+        ui.horizontal(|ui| {
+            if ui.button("Copy settings").clicked() {
+                clipboard.set_contents(&settings.export_string());
+                *import_error = None;
+            }
+            if ui.button("Paste settings").clicked() {
+                let pasted = clipboard.get_contents().unwrap_or_default();
+                match GameSettings::import_string(&pasted) {
+                    Ok(imported) => {
+                        *settings = imported;
+                        *import_error = None;
+                    }
+                    Err(e) => *import_error = Some(e),
+                }
+            }
+        });
+        if let Some(error) = import_error.as_ref() {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
         let sep = Separator::default().spacing(12.).horizontal();
         ui.add(sep);
 
@@ -299,6 +1359,14 @@ pub fn settings_ui(
     });
 }
 
+// bevy_egui recomputes the egui pixels per point every frame from the window's
+// scale factor, so the interface scale has to go through its settings
+pub fn apply_ui_scale(settings: Res<GameSettings>, mut egui_settings: ResMut<EguiSettings>) {
+    if settings.is_changed() {
+        egui_settings.scale_factor = settings.ui_scale;
+    }
+}
+
 pub fn load_settings(mut settings: ResMut<GameSettings>) {
     #[cfg(feature = "serde")]
     if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
@@ -317,13 +1385,177 @@ pub fn load_settings(mut settings: ResMut<GameSettings>) {
 }
 
 pub fn save_settings(settings: Res<GameSettings>) {
+    write_settings(&settings);
+}
+
+pub fn write_settings(settings: &GameSettings) {
     #[cfg(feature = "serde")]
     if let Some(dir) = ProjectDirs::from("", "", "Zink'd") {
         let mut file = dir.config_dir().to_path_buf();
         create_dir_all(&file).expect("Failed to create config directory");
         file.push("settings.ron");
         let mut file = File::create(file).expect("Failed to create settings file");
-        file.write(ron::to_string(&*settings).unwrap().as_ref())
+        file.write(ron::to_string(settings).unwrap().as_ref())
             .expect("Failed to write settings to disk");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::*;
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn settings_string_round_trip() {
+        let mut settings = GameSettings::default();
+        settings.players = 3;
+        settings.player_names.push("Guest".to_string());
+        settings.player_colors.push([40, 120, 200]);
+        settings.item_density = 0.35;
+        settings.die_corner = ScreenCorner::TopLeft;
+        let imported = GameSettings::import_string(&settings.export_string());
+        assert!(imported.unwrap() == settings);
+        assert!(GameSettings::import_string("not settings").is_err());
+    }
+
+    #[test]
+    fn missing_player_colors_are_untinted() {
+        let mut settings = GameSettings::default();
+        settings.player_colors = vec![[200, 30, 30]];
+        assert_eq!(settings.player_color(0), [200, 30, 30]);
+        assert_eq!(settings.player_color(1), NO_TINT);
+    }
+
+    #[test]
+    fn time_attack_keeps_best_record() {
+        let mut settings = GameSettings::default();
+        let first = TimeAttackRecord {
+            turns: 12,
+            seconds: 80.,
+        };
+        assert!(settings.record_time_attack(first));
+        let slower = TimeAttackRecord {
+            turns: 12,
+            seconds: 95.,
+        };
+        assert!(!settings.record_time_attack(slower));
+        let fewer_turns = TimeAttackRecord {
+            turns: 10,
+            seconds: 120.,
+        };
+        assert!(settings.record_time_attack(fewer_turns));
+        assert_eq!(settings.time_attack_best(), Some(fewer_turns));
+    }
+
+    #[test]
+    fn difficulty_presets() {
+        let mut settings = GameSettings::default();
+        Difficulty::Hard.apply_to(&mut settings);
+        assert_eq!(settings.difficulty(), Some(Difficulty::Hard));
+        assert_eq!(settings.map_width(), 90);
+        assert!(settings.player_types[0] == PlayerType::LocalHuman);
+        assert!(
+            settings.player_types[1]
+                == PlayerType::Computer(MoveAlgorithm::AStar, ItemAlgorithm::Sabotage)
+        );
+
+        // Normal matches the defaults, apart from remembering the preset
+        Difficulty::Normal.apply_to(&mut settings);
+        settings.difficulty = None;
+        assert!(settings == GameSettings::default());
+    }
+
+    #[test]
+    fn hidden_information_mode() {
+        let mut settings = GameSettings::default();
+        settings.apply_information_mode(InformationMode::Hidden);
+        assert!(!settings.reveal_opponent_dice());
+        assert!(!settings.reveal_opponent_inventories());
+        assert!(!settings.show_directions());
+        assert!(settings.hide_opponent_dice());
+        assert!(!settings.show_standings());
+
+        settings.apply_information_mode(InformationMode::Full);
+        assert!(settings.reveal_opponent_dice());
+        assert!(settings.reveal_opponent_inventories());
+        assert!(settings.show_directions());
+        assert!(!settings.hide_opponent_dice());
+        assert!(settings.show_standings());
+
+        settings.apply_information_mode(InformationMode::Standard);
+        assert!(!settings.reveal_opponent_dice());
+        assert!(settings.hide_opponent_dice());
+        assert!(settings.show_directions());
+
+        settings.apply_information_mode(InformationMode::Full);
+        settings.reveal_opponent_dice = false;
+        assert!(settings.hide_opponent_dice());
+    }
+
+    #[test]
+    fn map_seed_parsing() {
+        assert_eq!(GameSettings::default().map_seed(), None);
+        let seeded = |seed: &str| GameSettings {
+            map_seed: seed.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(seeded(" 1234 ").map_seed(), Some(1234));
+        assert_eq!(seeded("abc").map_seed(), None);
+    }
+
+    #[test]
+    fn fair_dice_ignore_starting_dice() {
+        let mut settings = GameSettings::default();
+        settings.starting_dice[0] = WeightedDie::fair_die_with_faces(DEFAULT_FACES).export_string();
+        assert!(settings.starting_die(0).is_some());
+        settings.fair_dice_seed = " 42 ".to_string();
+        assert_eq!(settings.fair_dice_seed(), Some(42));
+        assert!(settings.starting_die(0).is_none());
+    }
+
+    #[test]
+    fn pure_race_has_no_items() {
+        let mut settings = GameSettings::default();
+        assert_eq!(settings.item_density(), 0.1);
+        settings.items_enabled = false;
+        assert_eq!(settings.item_density(), 0.);
+    }
+
+    #[test]
+    fn key_binding_conflicts() {
+        let mut bindings = KeyBindings::default();
+        assert!(bindings.conflicts().is_empty());
+        bindings.roll = KeyCode::W;
+        bindings.pause = KeyCode::W;
+        assert_eq!(bindings.conflicts(), vec![KeyCode::W]);
+    }
+
+    #[test]
+    fn degenerate_settings_warnings() {
+        assert!(GameSettings::default().warnings().is_empty());
+
+        let settings = GameSettings {
+            players: 6,
+            map_width: 20,
+            map_height: 20,
+            item_density: 0.7,
+            initial_travel_distance: 15,
+            walking_speed: 10.,
+            ..Default::default()
+        };
+        let warnings = settings.warnings();
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("item density"));
+        assert!(warnings[1].contains("crowded"));
+        assert!(warnings[2].contains("walk too fast"));
+    }
+
+    #[test]
+    fn starting_die_must_match_face_count() {
+        let mut settings = GameSettings::default();
+        settings.starting_dice[0] = WeightedDie::fair_die_with_faces(8).export_string();
+        assert!(settings.starting_die(0).is_none());
+        settings.die_faces = 8;
+        assert_eq!(settings.starting_die(0).unwrap().faces(), 8);
+    }
+}